@@ -1,7 +1,7 @@
 use arret_core::{
     interval::Interval,
     rate_limiter::RateLimiter,
-    rule::{FixedWindow, TokenBucket},
+    rule::{FixedWindow, Gcra, LeakyBucket, SlidingWindowCounter, SlidingWindowLog, TokenBucket},
 };
 use criterion::{criterion_group, criterion_main, Bencher, Criterion};
 
@@ -113,12 +113,190 @@ fn bench_fixed_window_async(b: &mut Bencher) {
     })
 }
 
+fn bench_sliding_window_log(b: &mut Bencher) {
+    let mut con = prepare_connection();
+    let sliding_window_log = SlidingWindowLog::new(1_000, Interval::from_secs(1).unwrap()).unwrap();
+
+    b.iter(|| {
+        let _ = sliding_window_log.acquire("bench_sliding_window_log", 1, &mut con);
+    })
+}
+
+#[cfg(feature = "aio")]
+async fn sliding_window_log_async(con: &redis::aio::MultiplexedConnection) {
+    let mut con = con.clone();
+
+    let sliding_window_log = SlidingWindowLog::new(1_000, Interval::from_secs(1).unwrap()).unwrap();
+
+    let _ = aio::RateLimiter::acquire(
+        &sliding_window_log,
+        "bench_sliding_window_log_async",
+        1,
+        &mut con,
+    )
+    .await
+    .expect("Failed to acquire from sliding window log");
+}
+
+#[cfg(feature = "aio")]
+fn bench_sliding_window_log_async(b: &mut Bencher) {
+    let runtime = current_thread_runtime();
+
+    let con = runtime.block_on(prepare_connection_async());
+
+    b.to_async(runtime).iter_custom(|iters| {
+        let con = con.clone();
+
+        async move {
+            let start = std::time::Instant::now();
+
+            let futures = (0..iters).map(|_| sliding_window_log_async(&con));
+            futures::future::join_all(futures).await;
+
+            start.elapsed()
+        }
+    })
+}
+
+fn bench_sliding_window_counter(b: &mut Bencher) {
+    let mut con = prepare_connection();
+    let sliding_window_counter =
+        SlidingWindowCounter::new(1_000, Interval::from_secs(1).unwrap()).unwrap();
+
+    b.iter(|| {
+        let _ = sliding_window_counter.acquire("bench_sliding_window_counter", 1, &mut con);
+    })
+}
+
+#[cfg(feature = "aio")]
+async fn sliding_window_counter_async(con: &redis::aio::MultiplexedConnection) {
+    let mut con = con.clone();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(1_000, Interval::from_secs(1).unwrap()).unwrap();
+
+    let _ = aio::RateLimiter::acquire(
+        &sliding_window_counter,
+        "bench_sliding_window_counter_async",
+        1,
+        &mut con,
+    )
+    .await
+    .expect("Failed to acquire from sliding window counter");
+}
+
+#[cfg(feature = "aio")]
+fn bench_sliding_window_counter_async(b: &mut Bencher) {
+    let runtime = current_thread_runtime();
+
+    let con = runtime.block_on(prepare_connection_async());
+
+    b.to_async(runtime).iter_custom(|iters| {
+        let con = con.clone();
+
+        async move {
+            let start = std::time::Instant::now();
+
+            let futures = (0..iters).map(|_| sliding_window_counter_async(&con));
+            futures::future::join_all(futures).await;
+
+            start.elapsed()
+        }
+    })
+}
+
+fn bench_gcra(b: &mut Bencher) {
+    let mut con = prepare_connection();
+    let gcra = Gcra::new(1_000, Interval::from_secs(1).unwrap()).unwrap();
+
+    b.iter(|| {
+        let _ = gcra.acquire("bench_gcra", 1, &mut con);
+    })
+}
+
+#[cfg(feature = "aio")]
+async fn gcra_async(con: &redis::aio::MultiplexedConnection) {
+    let mut con = con.clone();
+
+    let gcra = Gcra::new(1_000, Interval::from_secs(1).unwrap()).unwrap();
+
+    let _ = aio::RateLimiter::acquire(&gcra, "bench_gcra_async", 1, &mut con)
+        .await
+        .expect("Failed to acquire from GCRA");
+}
+
+#[cfg(feature = "aio")]
+fn bench_gcra_async(b: &mut Bencher) {
+    use futures::future;
+
+    let runtime = current_thread_runtime();
+    let con = runtime.block_on(prepare_connection_async());
+
+    b.to_async(runtime).iter_custom(|iters| {
+        let con = con.clone();
+
+        async move {
+            let start = std::time::Instant::now();
+
+            let futures = (0..iters).map(|_| gcra_async(&con));
+            future::join_all(futures).await;
+
+            start.elapsed()
+        }
+    })
+}
+
+fn bench_leaky_bucket(b: &mut Bencher) {
+    let mut con = prepare_connection();
+    let leaky_bucket = LeakyBucket::new(1_000, Interval::from_secs(1).unwrap(), 1_000).unwrap();
+
+    b.iter(|| {
+        let _ = leaky_bucket.acquire("bench_leaky_bucket", 1, &mut con);
+    })
+}
+
+#[cfg(feature = "aio")]
+async fn leaky_bucket_async(con: &redis::aio::MultiplexedConnection) {
+    let mut con = con.clone();
+
+    let leaky_bucket = LeakyBucket::new(1_000, Interval::from_secs(1).unwrap(), 1_000).unwrap();
+
+    let _ = aio::RateLimiter::acquire(&leaky_bucket, "bench_leaky_bucket_async", 1, &mut con)
+        .await
+        .expect("Failed to acquire from leaky bucket");
+}
+
+#[cfg(feature = "aio")]
+fn bench_leaky_bucket_async(b: &mut Bencher) {
+    use futures::future;
+
+    let runtime = current_thread_runtime();
+    let con = runtime.block_on(prepare_connection_async());
+
+    b.to_async(runtime).iter_custom(|iters| {
+        let con = con.clone();
+
+        async move {
+            let start = std::time::Instant::now();
+
+            let futures = (0..iters).map(|_| leaky_bucket_async(&con));
+            future::join_all(futures).await;
+
+            start.elapsed()
+        }
+    })
+}
+
 fn bench_sync_query(c: &mut Criterion) {
     let mut group = c.benchmark_group("sync_query");
 
     group
         .bench_function("token_bucket", bench_token_bucket)
-        .bench_function("fixed_window", bench_fixed_window);
+        .bench_function("fixed_window", bench_fixed_window)
+        .bench_function("sliding_window_log", bench_sliding_window_log)
+        .bench_function("sliding_window_counter", bench_sliding_window_counter)
+        .bench_function("gcra", bench_gcra)
+        .bench_function("leaky_bucket", bench_leaky_bucket);
     group.finish();
 }
 
@@ -128,7 +306,11 @@ fn bench_async_query(c: &mut Criterion) {
 
     group
         .bench_function("token_bucket", bench_token_bucket_async)
-        .bench_function("fixed_window", bench_fixed_window_async);
+        .bench_function("fixed_window", bench_fixed_window_async)
+        .bench_function("sliding_window_log", bench_sliding_window_log_async)
+        .bench_function("sliding_window_counter", bench_sliding_window_counter_async)
+        .bench_function("gcra", bench_gcra_async)
+        .bench_function("leaky_bucket", bench_leaky_bucket_async);
     group.finish();
 }
 
@@ -1,4 +1,46 @@
-use crate::{error::Result, rate_limiter::AcquireResult};
+use std::time::Instant;
+
+use crate::{
+    concurrency_limiter::{Lease, LeaseResult},
+    error::{Error, Result},
+    estimating_limiter::{Estimate, EstimateResult},
+    rate_limiter::{self, AcquireResult, Quota},
+    reserving_limiter::{Reservation, ReservationResult},
+    rule::{Clock, SystemClock},
+    store::Operation,
+};
+
+/// A backend holding the state of rules, which allows asynchronous requests to be made,
+/// such as an asynchronous Redis connection or a [`MemoryStore`](crate::store::MemoryStore).
+#[async_trait::async_trait]
+pub trait Store {
+    /// Executes the given `operation` atomically, and returns its output.
+    ///
+    /// Either every entry written by the operation is saved, or none of them is.
+    async fn execute<O>(&mut self, operation: &O) -> Result<O::Output>
+    where
+        O: Operation + Sync,
+        O::Output: Send;
+
+    /// Executes the given independent `operations`, each one atomically, and returns
+    /// their outputs in the same order.
+    ///
+    /// Redis connections run the operations in a single script, so that they take a
+    /// single round trip, unless their keys span several slots of Redis Cluster. An
+    /// operation failing within the script does not fail the others. Other stores
+    /// execute them one by one by default.
+    async fn execute_many<O>(&mut self, operations: &[O]) -> Vec<Result<O::Output>>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        let mut outputs = Vec::with_capacity(operations.len());
+        for operation in operations {
+            outputs.push(self.execute(operation).await);
+        }
+        outputs
+    }
+}
 
 /// A rate limiter for a single resource, which allows asynchronous
 /// requests to be made.
@@ -10,8 +52,243 @@ pub trait RateLimiter {
     /// [`AcquireResult::Ok`] is returned.
     /// Otherwise, [`AcquireResult::Throttled`] is returned.
     ///
-    /// Requires a Redis connection to be passed in.
-    async fn acquire<C>(&self, resource: &str, tokens: u64, con: &mut C) -> Result<AcquireResult>
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + Send + Sync;
+
+    /// Try to acquire `tokens` for the given `resource`, as by [`acquire`](Self::acquire),
+    /// and also returns the epochmillis time the quota was computed at, if known.
+    ///
+    /// Rules of this crate return the current time of their operation, which is the time
+    /// of Redis with [`TimeSource::Server`](crate::rule::TimeSource::Server), so that
+    /// the time left until [`Quota::reset`] is measured against the same clock as the
+    /// reset itself. Defaults to `None`.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + Send + Sync,
+    {
+        self.acquire(resource, tokens, store)
+            .await
+            .map(|result| (result, None))
+    }
+
+    /// Returns `tokens` acquired for the given `resource` at `acquired_at` but not used,
+    /// such as when the upstream request failed or the client disconnected.
+    ///
+    /// `acquired_at` is the epochmillis time the tokens were acquired at, as returned by
+    /// [`acquire_timed`](Self::acquire_timed). Tokens are credited back up to the capacity
+    /// of the rule. Rules with windows only credit the window the tokens were acquired
+    /// in, and drop the refund once it has ended, so that a refund never carries over to
+    /// the next one. Returns the quota of the resource after the refund.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + Send + Sync;
+
+    /// Try to acquire tokens for many independent `acquisitions` of a resource and its
+    /// tokens, and returns their results in the same order.
+    ///
+    /// Rules of this crate execute the acquisitions with [`Store::execute_many`], which
+    /// takes a single round trip to Redis. Defaults to [`acquire`](Self::acquire) for each
+    /// of them in turn.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + Send + Sync,
+    {
+        let mut results = Vec::with_capacity(acquisitions.len());
+        for &(resource, tokens) in acquisitions {
+            results.push(self.acquire(resource, tokens, store).await);
+        }
+        results
+    }
+
+    /// Returns the current quota of the given `resource`, without consuming any token.
+    ///
+    /// The quota is computed by the same script as [`acquire`](Self::acquire), but rules
+    /// of this crate only check it, so that nothing is written to the store. Defaults to
+    /// acquiring no token, which may still write the state of the resource.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + Send + Sync,
+    {
+        self.acquire(resource, 0, store)
+            .await
+            .map(|result| result.quota())
+    }
+
+    /// Acquire `tokens` for the given `resource`, waiting on the timer of the Tokio
+    /// runtime until they are acquired or the `deadline` would be exceeded.
+    ///
+    /// Throttled requests are retried once the quota resets, with some jitter so that
+    /// clients waiting for the same reset do not retry all at once. Delayed requests
+    /// wait for their delay before returning.
+    ///
+    /// # Errors
+    /// - [`Error::DeadlineExceeded`] if the tokens cannot be acquired before the
+    ///   deadline, without waiting until then. Tokens of a delayed request which would
+    ///   proceed after the deadline are refunded.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn acquire_wait<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        deadline: Instant,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + Send + Sync,
+    {
+        loop {
+            let (result, now) = self.acquire_timed(resource, tokens, store).await?;
+            match result {
+                AcquireResult::Ok(quota) => return Ok(quota),
+                AcquireResult::Delayed(quota, delay) => {
+                    if Instant::now() + delay > deadline {
+                        let acquired_at = now.unwrap_or_else(|| SystemClock.now());
+                        self.refund(resource, tokens, acquired_at, store).await?;
+                        return Err(Error::DeadlineExceeded(quota));
+                    }
+                    tokio::time::sleep(delay).await;
+                    return Ok(quota);
+                }
+                AcquireResult::Throttled(quota) => {
+                    let wait = rate_limiter::retry_wait(&quota, now);
+                    if Instant::now() + wait > deadline {
+                        return Err(Error::DeadlineExceeded(quota));
+                    }
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+    }
+}
+
+/// A rate limiter for requests whose cost is only known once they complete, which allows
+/// asynchronous requests to be made.
+#[async_trait::async_trait]
+pub trait EstimatingLimiter: RateLimiter {
+    /// Try to acquire `estimate` tokens for the given `resource`, as the estimated cost of
+    /// a request.
+    ///
+    /// If the rate limit has not been exceeded, the tokens are acquired and
+    /// [`EstimateResult::Ok`] is returned. The estimate should be
+    /// [committed](Self::commit) once the actual cost is known, or it settles as
+    /// estimated after the estimate timeout of the rule.
+    /// Otherwise, [`EstimateResult::Throttled`] is returned.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn acquire_estimate<S>(
+        &self,
+        resource: &str,
+        estimate: u64,
+        store: &mut S,
+    ) -> Result<EstimateResult>
+    where
+        S: Store + Send + Sync;
+
+    /// Commit the given `estimate` with the `actual` cost of the request.
+    ///
+    /// The difference is refunded if the estimate was too high, or charged if it was too
+    /// low, even beyond the tokens left.
+    ///
+    /// Returns `false` if the estimate had expired, in which case it stays charged as
+    /// estimated.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn commit<S>(&self, estimate: Estimate, actual: u64, store: &mut S) -> Result<bool>
+    where
+        S: Store + Send + Sync;
+}
+
+/// A rate limiter which reserves tokens ahead of their refill, which allows asynchronous
+/// requests to be made.
+#[async_trait::async_trait]
+pub trait ReservingLimiter: RateLimiter {
+    /// Reserve `tokens` for the given `resource`.
+    ///
+    /// If the tokens are refilled within the maximum delay of the rule, they are reserved
+    /// and [`ReservationResult::Ok`] is returned, with the instant the caller may proceed.
+    /// Otherwise, nothing is reserved and [`ReservationResult::Throttled`] is returned.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn reserve<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<ReservationResult>
+    where
+        S: Store + Send + Sync;
+
+    /// Cancel the given `reservation`, which restores its tokens up to the capacity of the
+    /// rule.
+    ///
+    /// Returns `false` if the instant to proceed had already passed, in which case nothing
+    /// is restored.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn cancel<S>(&self, reservation: Reservation, store: &mut S) -> Result<bool>
+    where
+        S: Store + Send + Sync;
+}
+
+/// A limiter of concurrent operations for a single resource, which allows
+/// asynchronous requests to be made.
+#[async_trait::async_trait]
+pub trait ConcurrencyLimiter {
+    /// Try to acquire a lease on the given `resource`.
+    ///
+    /// If fewer leases than the capacity are held, a new lease is granted and
+    /// [`LeaseResult::Ok`] is returned. The lease must be [released](Self::release)
+    /// once the operation completes, or it expires after the lease timeout unless
+    /// [extended](Self::extend). Otherwise, [`LeaseResult::Throttled`] is returned.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn acquire<S>(&self, resource: &str, store: &mut S) -> Result<LeaseResult>
+    where
+        S: Store + Send + Sync;
+
+    /// Release the given `lease`, so that another operation may acquire it.
+    ///
+    /// Returns `false` if the lease had already expired or been released.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn release<S>(&self, lease: &Lease, store: &mut S) -> Result<bool>
+    where
+        S: Store + Send + Sync;
+
+    /// Extend the given `lease` to the lease timeout from now, for operations which
+    /// outlive it.
+    ///
+    /// Returns the lease with its new expiration, or `None` if it had already expired or
+    /// been released, in which case it is not granted again.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    async fn extend<S>(&self, lease: &Lease, store: &mut S) -> Result<Option<Lease>>
     where
-        C: redis::aio::ConnectionLike + Send + Sync;
+        S: Store + Send + Sync;
 }
@@ -0,0 +1,83 @@
+use crate::{error::Result, rate_limiter::Quota, store::Store};
+
+/// A limiter of concurrent operations for a single resource.
+///
+/// Unlike a [`RateLimiter`](crate::rate_limiter::RateLimiter), which limits how often a
+/// resource is requested, it limits how many operations on a resource are in flight.
+pub trait ConcurrencyLimiter {
+    /// Try to acquire a lease on the given `resource`.
+    ///
+    /// If fewer leases than the capacity are held, a new lease is granted and
+    /// [`LeaseResult::Ok`] is returned. The lease must be [released](Self::release)
+    /// once the operation completes, or it expires after the lease timeout unless
+    /// [extended](Self::extend). Otherwise, [`LeaseResult::Throttled`] is returned.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn acquire<S>(&self, resource: &str, store: &mut S) -> Result<LeaseResult>
+    where
+        S: Store + ?Sized;
+
+    /// Release the given `lease`, so that another operation may acquire it.
+    ///
+    /// Returns `false` if the lease had already expired or been released.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn release<S>(&self, lease: &Lease, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized;
+
+    /// Extend the given `lease` to the lease timeout from now, for operations which
+    /// outlive it.
+    ///
+    /// Returns the lease with its new expiration, or `None` if it had already expired or
+    /// been released, in which case it is not granted again.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn extend<S>(&self, lease: &Lease, store: &mut S) -> Result<Option<Lease>>
+    where
+        S: Store + ?Sized;
+}
+
+/// A result from a concurrency limiting request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeaseResult {
+    /// The lease was granted.
+    Ok(Lease, Quota),
+
+    /// The lease was denied because the concurrency limit was reached.
+    Throttled(Quota),
+}
+
+/// A lease on a resource granted by a [`ConcurrencyLimiter`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[must_use = "a lease should be released once the operation completes"]
+pub struct Lease {
+    resource: String,
+    id: String,
+    expires_at: u64,
+}
+
+impl Lease {
+    pub(crate) fn new(resource: &str, id: String, expires_at: u64) -> Self {
+        Self {
+            resource: resource.into(),
+            id,
+            expires_at,
+        }
+    }
+
+    /// Returns the resource the lease was granted on.
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Returns the unique id of the lease.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the epochmillis timestamp when the lease expires unless released before.
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+}
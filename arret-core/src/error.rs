@@ -1,13 +1,35 @@
 use std::fmt;
 
+use crate::rate_limiter::Quota;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     /// Time interval with zero duration is not supported.
     ZeroTimeInterval,
 
+    /// Time interval too long to be represented in milliseconds.
+    InvalidInterval(String),
+
     /// Invalid rate limiting rule.
     InvalidRule(String),
 
+    /// Invalid keyspace of a rule.
+    InvalidKeyspace(String),
+
+    /// Invalid path of resources of a hierarchy, or resources not matching the rules of
+    /// a composite.
+    InvalidPath(String),
+
+    /// The library of Redis Functions installed on Redis is missing or of another version.
+    LibraryVersion {
+        expected: String,
+        installed: Option<String>,
+    },
+
+    /// The tokens could not be acquired before the deadline, with the quota of the
+    /// resource at the last attempt.
+    DeadlineExceeded(Quota),
+
     /// Internal error.
     Internal(String),
 }
@@ -18,7 +40,29 @@ impl fmt::Display for Error {
             Self::ZeroTimeInterval => {
                 write!(f, "Time interval with zero duration is not supported")
             }
+            Self::InvalidInterval(msg) => write!(f, "Invalid time interval: {msg}"),
             Self::InvalidRule(msg) => write!(f, "Invalid rate limiting rule: {msg}"),
+            Self::InvalidKeyspace(msg) => write!(f, "Invalid keyspace: {msg}"),
+            Self::InvalidPath(msg) => write!(f, "Invalid path: {msg}"),
+            Self::LibraryVersion {
+                expected,
+                installed: Some(installed),
+            } => write!(
+                f,
+                "Expected version {expected} of the Redis Functions library, found {installed}"
+            ),
+            Self::LibraryVersion {
+                expected,
+                installed: None,
+            } => write!(
+                f,
+                "Expected version {expected} of the Redis Functions library, found none"
+            ),
+            Self::DeadlineExceeded(quota) => write!(
+                f,
+                "Deadline exceeded before the tokens could be acquired, {} of {} remaining",
+                quota.remaining, quota.limit
+            ),
             Self::Internal(err) => write!(f, "Internal error: {err}"),
         }
     }
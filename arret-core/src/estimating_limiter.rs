@@ -0,0 +1,101 @@
+use crate::{
+    error::Result,
+    rate_limiter::{Quota, RateLimiter},
+    store::Store,
+};
+
+/// A rate limiter for requests whose cost is only known once they complete, such as the
+/// tokens generated by a language model.
+///
+/// The estimated cost of a request is acquired up front, which returns an [`Estimate`].
+/// Once the request completes, the estimate is [committed](Self::commit) with the actual
+/// cost, which charges or refunds the difference.
+pub trait EstimatingLimiter: RateLimiter {
+    /// Try to acquire `estimate` tokens for the given `resource`, as the estimated cost of
+    /// a request.
+    ///
+    /// If the rate limit has not been exceeded, the tokens are acquired and
+    /// [`EstimateResult::Ok`] is returned. The estimate should be
+    /// [committed](Self::commit) once the actual cost is known, or it settles as
+    /// estimated after the estimate timeout of the rule.
+    /// Otherwise, [`EstimateResult::Throttled`] is returned.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn acquire_estimate<S>(
+        &self,
+        resource: &str,
+        estimate: u64,
+        store: &mut S,
+    ) -> Result<EstimateResult>
+    where
+        S: Store + ?Sized;
+
+    /// Commit the given `estimate` with the `actual` cost of the request.
+    ///
+    /// The difference is refunded if the estimate was too high, as by
+    /// [`refund`](RateLimiter::refund). It is charged if the estimate was too low, even
+    /// beyond the tokens left, since the request has already been served.
+    ///
+    /// Returns `false` if the estimate had expired, in which case it stays charged as
+    /// estimated.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn commit<S>(&self, estimate: Estimate, actual: u64, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized;
+}
+
+/// A result from an estimated acquisition.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EstimateResult {
+    /// The estimated tokens were acquired.
+    Ok(Estimate, Quota),
+
+    /// The request was denied because the rate limit was exceeded.
+    Throttled(Quota),
+}
+
+/// Tokens acquired by an [`EstimatingLimiter`] for a request whose actual cost is not
+/// known yet.
+///
+/// An estimate is committed at most once, which is why it cannot be cloned.
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[must_use = "an estimate should be committed once the actual cost is known"]
+pub struct Estimate {
+    resource: String,
+    tokens: u64,
+    acquired_at: u64,
+    expires_at: u64,
+}
+
+impl Estimate {
+    pub(crate) fn new(resource: &str, tokens: u64, acquired_at: u64, expires_at: u64) -> Self {
+        Self {
+            resource: resource.into(),
+            tokens,
+            acquired_at,
+            expires_at,
+        }
+    }
+
+    /// Returns the resource the tokens were acquired for.
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Returns the estimated number of tokens acquired.
+    pub fn tokens(&self) -> u64 {
+        self.tokens
+    }
+
+    /// Returns the epochmillis timestamp when the tokens were acquired.
+    pub fn acquired_at(&self) -> u64 {
+        self.acquired_at
+    }
+
+    /// Returns the epochmillis timestamp when the estimate settles unless committed
+    /// before.
+    pub fn expires_at(&self) -> u64 {
+        self.expires_at
+    }
+}
@@ -4,15 +4,39 @@ use crate::error::{Error, Result};
 
 /// Represents a time window for specifing a rate limiting [`Rule`](super::rule::Rule).
 ///
-/// Sub-second precision time windows are not supported.
+/// Sub-millisecond precision time windows are not supported.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Interval(u64);
 
 impl Interval {
     /// Creates a new [`Interval`] with the given number of seconds.
+    ///
+    /// ```rust
+    /// use arret_core::{error::Error, interval::Interval};
+    ///
+    /// assert_eq!(Interval::from_secs(60).unwrap().as_millis(), 60_000);
+    /// assert_eq!(Interval::from_secs(0), Err(Error::ZeroTimeInterval));
+    /// assert!(matches!(Interval::from_secs(u64::MAX), Err(Error::InvalidInterval(_))));
+    /// ```
     pub fn from_secs(seconds: u64) -> Result<Self> {
-        if seconds > 0 {
-            Ok(Self(seconds))
+        let milliseconds = seconds.checked_mul(1_000).ok_or_else(|| {
+            Error::InvalidInterval(format!("{seconds} seconds do not fit in milliseconds"))
+        })?;
+        Self::from_millis(milliseconds)
+    }
+
+    /// Creates a new [`Interval`] with the given number of milliseconds.
+    ///
+    /// ```rust
+    /// use arret_core::{error::Error, interval::Interval};
+    ///
+    /// assert_eq!(Interval::from_millis(1_000), Interval::from_secs(1));
+    /// assert_eq!(Interval::from_millis(100).unwrap().as_millis(), 100);
+    /// assert_eq!(Interval::from_millis(0), Err(Error::ZeroTimeInterval));
+    /// ```
+    pub fn from_millis(milliseconds: u64) -> Result<Self> {
+        if milliseconds > 0 {
+            Ok(Self(milliseconds))
         } else {
             Err(Error::ZeroTimeInterval)
         }
@@ -20,8 +44,9 @@ impl Interval {
 
     /// Creates a new [`Interval`] with a given [`Duration`].
     ///
-    /// Sub-second precision duration is not supported. Instead, it will be rounded
-    /// down to the nearest second.
+    /// Sub-millisecond precision duration is not supported. Instead, it will be rounded
+    /// down to the nearest millisecond. Durations of more than [`u64::MAX`] milliseconds
+    /// are not supported either.
     ///
     /// ```rust
     /// use std::time::Duration;
@@ -29,27 +54,44 @@ impl Interval {
     ///
     /// assert_eq!(Interval::from_duration(Duration::from_secs(60)), Interval::from_secs(60));
     /// assert_eq!(Interval::from_duration(Duration::from_secs(1)), Interval::from_secs(1));
-    /// assert_eq!(Interval::from_duration(Duration::from_millis(100)), Err(Error::ZeroTimeInterval));
+    /// assert_eq!(Interval::from_duration(Duration::from_millis(100)), Interval::from_millis(100));
+    /// assert_eq!(Interval::from_duration(Duration::from_micros(100)), Err(Error::ZeroTimeInterval));
     /// assert_eq!(Interval::from_duration(Duration::ZERO), Err(Error::ZeroTimeInterval));
+    /// assert!(matches!(Interval::from_duration(Duration::MAX), Err(Error::InvalidInterval(_))));
     /// ```
     pub fn from_duration(duration: Duration) -> Result<Self> {
-        Self::from_secs(duration.as_secs())
+        let milliseconds = duration.as_millis().try_into().map_err(|_| {
+            Error::InvalidInterval(format!("{duration:?} does not fit in milliseconds"))
+        })?;
+        Self::from_millis(milliseconds)
     }
 
-    /// Returns the number of seconds in the interval.
+    /// Returns the number of whole seconds in the interval.
     ///
     /// ```rust
     /// use arret_core::interval::Interval;
     ///
     /// assert_eq!(Interval::from_secs(60).unwrap().as_secs(), 60);
+    /// assert_eq!(Interval::from_millis(1_500).unwrap().as_secs(), 1);
     /// ```
     pub fn as_secs(&self) -> u64 {
+        self.0 / 1_000
+    }
+
+    /// Returns the number of milliseconds in the interval.
+    ///
+    /// ```rust
+    /// use arret_core::interval::Interval;
+    ///
+    /// assert_eq!(Interval::from_secs(60).unwrap().as_millis(), 60_000);
+    /// ```
+    pub fn as_millis(&self) -> u64 {
         self.0
     }
 }
 
 impl From<Interval> for Duration {
     fn from(interval: Interval) -> Self {
-        Duration::from_secs(interval.0)
+        Duration::from_millis(interval.0)
     }
 }
@@ -1,7 +1,11 @@
+pub mod concurrency_limiter;
 pub mod error;
+pub mod estimating_limiter;
 pub mod interval;
 pub mod rate_limiter;
+pub mod reserving_limiter;
 pub mod rule;
+pub mod store;
 
 #[cfg(feature = "aio")]
 pub mod aio;
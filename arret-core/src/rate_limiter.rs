@@ -1,4 +1,15 @@
-use crate::error::Result;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    error::{Error, Result},
+    rule::{Clock, SystemClock},
+    store::Store,
+};
 
 /// A rate limiter for a single resource.
 pub trait RateLimiter {
@@ -8,13 +19,153 @@ pub trait RateLimiter {
     /// [`AcquireResult::Ok`] is returned.
     /// Otherwise, [`AcquireResult::Throttled`] is returned.
     ///
-    /// Requires a Redis connection to be passed in.
-    fn acquire(
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized;
+
+    /// Try to acquire `tokens` for the given `resource`, as by [`acquire`](Self::acquire),
+    /// and also returns the epochmillis time the quota was computed at, if known.
+    ///
+    /// Rules of this crate return the current time of their operation, which is the time
+    /// of Redis with [`TimeSource::Server`](crate::rule::TimeSource::Server), so that
+    /// the time left until [`Quota::reset`] is measured against the same clock as the
+    /// reset itself. Defaults to `None`.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        self.acquire(resource, tokens, store)
+            .map(|result| (result, None))
+    }
+
+    /// Returns `tokens` acquired for the given `resource` at `acquired_at` but not used,
+    /// such as when the upstream request failed or the client disconnected.
+    ///
+    /// `acquired_at` is the epochmillis time the tokens were acquired at, as returned by
+    /// [`acquire_timed`](Self::acquire_timed). Tokens are credited back up to the capacity
+    /// of the rule. Rules with windows only credit the window the tokens were acquired
+    /// in, and drop the refund once it has ended, so that a refund never carries over to
+    /// the next one. Returns the quota of the resource after the refund.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized;
+
+    /// Try to acquire tokens for many independent `acquisitions` of a resource and its
+    /// tokens, and returns their results in the same order.
+    ///
+    /// Rules of this crate execute the acquisitions with [`Store::execute_many`], which
+    /// takes a single round trip to Redis. Defaults to [`acquire`](Self::acquire) for each
+    /// of them in turn.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.acquire(resource, tokens, store))
+            .collect()
+    }
+
+    /// Returns the current quota of the given `resource`, without consuming any token.
+    ///
+    /// The quota is computed by the same script as [`acquire`](Self::acquire), such as
+    /// the refill of a bucket or the position in a window, but rules of this crate only
+    /// check it, so that nothing is written to the store. Defaults to acquiring no token,
+    /// which may still write the state of the resource, such as to drop expired entries.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        self.acquire(resource, 0, store)
+            .map(|result| result.quota())
+    }
+
+    /// Acquire `tokens` for the given `resource`, blocking the current thread until they
+    /// are acquired or the `deadline` would be exceeded.
+    ///
+    /// Throttled requests are retried once the quota resets, with some jitter so that
+    /// clients waiting for the same reset do not retry all at once. Delayed requests
+    /// sleep for their delay before returning.
+    ///
+    /// # Errors
+    /// - [`Error::DeadlineExceeded`] if the tokens cannot be acquired before the
+    ///   deadline, without sleeping until then. Tokens of a delayed request which would
+    ///   proceed after the deadline are refunded.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn acquire_wait<S>(
         &self,
         resource: &str,
         tokens: u64,
-        con: &mut dyn redis::ConnectionLike,
-    ) -> Result<AcquireResult>;
+        deadline: Instant,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        loop {
+            let (result, now) = self.acquire_timed(resource, tokens, store)?;
+            match result {
+                AcquireResult::Ok(quota) => return Ok(quota),
+                AcquireResult::Delayed(quota, delay) => {
+                    if Instant::now() + delay > deadline {
+                        let acquired_at = now.unwrap_or_else(|| SystemClock.now());
+                        self.refund(resource, tokens, acquired_at, store)?;
+                        return Err(Error::DeadlineExceeded(quota));
+                    }
+                    thread::sleep(delay);
+                    return Ok(quota);
+                }
+                AcquireResult::Throttled(quota) => {
+                    let wait = retry_wait(&quota, now);
+                    if Instant::now() + wait > deadline {
+                        return Err(Error::DeadlineExceeded(quota));
+                    }
+                    thread::sleep(wait);
+                }
+            }
+        }
+    }
+}
+
+/// Returns how long to wait before retrying a request throttled with the given `quota`
+/// at `now`, which is until the quota resets plus a random jitter of at least a
+/// millisecond and up to a tenth of the time to the reset.
+///
+/// The time to the reset is measured from the current time of the system if `now` is
+/// not known.
+pub(crate) fn retry_wait(quota: &Quota, now: Option<u64>) -> Duration {
+    let now = now.map_or_else(SystemTime::now, |now| {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(now)
+    });
+    let retry_after = quota.retry_after(now);
+    let max_jitter = (retry_after / 10).as_millis() as u64 + 1;
+    let random = RandomState::new().build_hasher().finish();
+    retry_after + Duration::from_millis(1 + random % max_jitter)
 }
 
 /// A result from a rate limiting request.
@@ -23,10 +174,25 @@ pub enum AcquireResult {
     /// The request was allowed.
     Ok(Quota),
 
+    /// The request was allowed, but may only proceed after the given delay.
+    ///
+    /// Returned by rules which shape traffic instead of rejecting it, such as
+    /// [`LeakyBucket`](crate::rule::LeakyBucket).
+    Delayed(Quota, Duration),
+
     /// The request was denied because the rate limit was exceeded.
     Throttled(Quota),
 }
 
+impl AcquireResult {
+    /// Returns the quota of the resource after the request.
+    pub fn quota(&self) -> Quota {
+        match self {
+            Self::Ok(quota) | Self::Delayed(quota, _) | Self::Throttled(quota) => *quota,
+        }
+    }
+}
+
 /// Metadata about the current rate limiting state.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Quota {
@@ -41,7 +207,8 @@ pub struct Quota {
 
     /// The epochmillis timestamp when the current interval will reset.
     ///
-    /// The client may use this to determine when to retry the request.
+    /// The client may use this to determine when to retry the request,
+    /// see [`Quota::reset_at`] and [`Quota::retry_after`].
     pub reset: u64,
 }
 
@@ -55,4 +222,39 @@ impl Quota {
             reset,
         }
     }
+
+    /// Returns the time when the current interval will reset.
+    ///
+    /// ```rust
+    /// use std::time::{Duration, SystemTime};
+    /// use arret_core::rate_limiter::Quota;
+    ///
+    /// let quota = Quota { limit: 10, remaining: 0, used: 10, reset: 1_500 };
+    ///
+    /// assert_eq!(quota.reset_at(), SystemTime::UNIX_EPOCH + Duration::from_millis(1_500));
+    /// ```
+    pub fn reset_at(&self) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_millis(self.reset)
+    }
+
+    /// Returns how long the client should wait after `now` until the current interval
+    /// resets, which may be used as the `Retry-After` of a throttled request.
+    ///
+    /// Returns [`Duration::ZERO`] if the interval has already been reset.
+    ///
+    /// ```rust
+    /// use std::time::{Duration, SystemTime};
+    /// use arret_core::rate_limiter::Quota;
+    ///
+    /// let quota = Quota { limit: 10, remaining: 0, used: 10, reset: 1_500 };
+    /// let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1);
+    ///
+    /// assert_eq!(quota.retry_after(now), Duration::from_millis(500));
+    /// assert_eq!(quota.retry_after(now + Duration::from_secs(1)), Duration::ZERO);
+    /// ```
+    pub fn retry_after(&self, now: SystemTime) -> Duration {
+        self.reset_at()
+            .duration_since(now)
+            .unwrap_or(Duration::ZERO)
+    }
 }
@@ -0,0 +1,26 @@
+-- Runs `operation` for each operation of a batch in turn, whose keys and arguments
+-- follow each other, the arguments of each operation preceded by its number of keys
+-- and arguments. An operation failing does not fail the others, and its error is
+-- returned as a status reply in its place.
+local results = {}
+local k, a = 0, 1
+while a <= #ARGV do
+  local keyCount, argCount = tonumber(ARGV[a]), tonumber(ARGV[a + 1])
+  local keys = {unpack(KEYS, k + 1, k + keyCount)}
+  local argv = {unpack(ARGV, a + 2, a + 1 + argCount)}
+
+  local ok, result = pcall(operation, keys, argv)
+  if not ok then
+    -- Errors of Redis commands are tables, unlike errors of Lua itself
+    if type(result) == "table" then
+      result = result.err
+    end
+    result = redis.status_reply(tostring(result))
+  end
+  results[#results + 1] = result
+
+  k = k + keyCount
+  a = a + 2 + argCount
+end
+
+return results
@@ -0,0 +1,71 @@
+local function calendarWindow(
+  key,
+  now,
+  capacity,
+  windowStart,
+  windowEnd,
+  requestedTokens,
+  migrationPolicy,
+  mode,
+  modeArgs
+)
+  -- The bounds of the window are computed by the client from its calendar,
+  -- and the window is identified by its start
+  local bucket = capacity
+  local stored = redis.call("HMGET", key, "start", "bucket", "capacity")
+  local storedCapacity = tonumber(stored[3])
+  if tonumber(stored[1]) == windowStart then
+    if storedCapacity == capacity then
+      bucket = tonumber(stored[2])
+    else
+      -- Migrate the bucket if it was written under another capacity,
+      -- such as after a change of plan within the window
+      bucket = migrate(migrationPolicy, tonumber(stored[2]), storedCapacity, capacity) or capacity
+    end
+  end
+
+  local available = bucket
+  if mode == "refund" then
+    local acquiredAt = modeArgs[1]
+
+    -- Return the tokens to the bucket, up to its capacity,
+    -- only if they were acquired in the current window
+    if acquiredAt >= windowStart and acquiredAt < windowEnd then
+      bucket = math.min(capacity, bucket + requestedTokens)
+    end
+  elseif bucket < requestedTokens then
+    -- Not enough tokens
+    return {false, bucket, windowEnd, now}
+  elseif mode == "check" then
+    -- Enough tokens, which are left in the bucket
+    return {true, bucket, windowEnd, now}
+  else
+    -- Consume the tokens in the current window
+    bucket = bucket - requestedTokens
+  end
+
+  -- Update the bucket of the current window if its tokens changed,
+  -- which expires along with the window
+  if bucket ~= available then
+    if bucket < capacity then
+      redis.call("HSET", key, "start", windowStart, "bucket", bucket, "capacity", capacity)
+      redis.call("PEXPIRE", key, windowEnd - now)
+    else
+      redis.call("DEL", key)
+    end
+  end
+
+  return {true, bucket, windowEnd, now}
+end
+
+return calendarWindow(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  tonumber(ARGV[5]),
+  ARGV[6],
+  ARGV[7],
+  {tonumber(ARGV[8])}
+)
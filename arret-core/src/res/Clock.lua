@@ -0,0 +1,17 @@
+-- Returns the current time in milliseconds since the Unix epoch,
+-- which is read from the Redis server unless given by the client
+local function currentTime(now)
+  if now ~= "" then
+    return tonumber(now)
+  end
+
+  -- Scripts reading the server time must be replicated by their effects,
+  -- which functions always are
+  if redis.replicate_commands ~= nil then
+    redis.replicate_commands()
+  end
+
+  local time = redis.call("TIME")
+  return tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+end
+
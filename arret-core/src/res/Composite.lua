@@ -0,0 +1,65 @@
+local function composite(keys, now, rules, mode, modeArgs)
+  local results = {}
+
+  -- Check every rule before acquiring from any of them,
+  -- so that tokens are only consumed if all of them admit the request
+  if mode == "acquire" then
+    local accepted = true
+    for i, rule in ipairs(rules) do
+      results[i] = rule(keys[i], now, "check", {})
+      accepted = accepted and results[i][1]
+    end
+    if not accepted then
+      return {false, results, now}
+    end
+  end
+
+  for i, rule in ipairs(rules) do
+    results[i] = rule(keys[i], now, mode, modeArgs)
+  end
+
+  return {true, results, now}
+end
+
+-- The mode of the composite is followed by its own arguments,
+-- such as the time the tokens of a refund were acquired at
+local mode = ARGV[2]
+local modeArgs = {}
+local i = 3
+if mode == "refund" then
+  modeArgs = {tonumber(ARGV[3])}
+  i = 4
+end
+
+-- Each rule is given by its kind, followed by the arguments of its own script
+-- up to its mode, which is the one of the composite instead
+local rules = {}
+while i <= #ARGV do
+  if ARGV[i] == "token_bucket" then
+    local capacity = tonumber(ARGV[i + 1])
+    local refillInterval = tonumber(ARGV[i + 2])
+    local refillAmount = tonumber(ARGV[i + 3])
+    local requestedTokens = tonumber(ARGV[i + 4])
+    local migrationPolicy = ARGV[i + 5]
+    rules[#rules + 1] = function(key, now, mode, modeArgs)
+      return tokenBucket(
+        key, now, capacity, refillInterval, refillAmount, requestedTokens, migrationPolicy,
+        mode, modeArgs
+      )
+    end
+    i = i + 7
+  else
+    local capacity = tonumber(ARGV[i + 1])
+    local window = tonumber(ARGV[i + 2])
+    local requestedTokens = tonumber(ARGV[i + 3])
+    local migrationPolicy = ARGV[i + 4]
+    rules[#rules + 1] = function(key, now, mode, modeArgs)
+      return fixedWindow(
+        key, now, capacity, window, requestedTokens, migrationPolicy, mode, modeArgs
+      )
+    end
+    i = i + 6
+  end
+end
+
+return composite(KEYS, currentTime(ARGV[1]), rules, mode, modeArgs)
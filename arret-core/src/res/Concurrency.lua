@@ -0,0 +1,44 @@
+local function concurrency(
+  key,
+  sequenceKey,
+  now,
+  capacity,
+  leaseTimeout
+)
+  -- Leases are stored as members scored by their expiration time,
+  -- so that leases of crashed workers expire on their own
+  redis.call("ZREMRANGEBYSCORE", key, "-inf", now)
+
+  local leases = redis.call("ZCARD", key)
+
+  if leases >= capacity then
+    -- No lease available until the oldest one is released or expires
+    local oldest = redis.call("ZRANGE", key, 0, 0, "WITHSCORES")
+    local reset = now
+    if #oldest > 0 then
+      reset = tonumber(oldest[2])
+    end
+    return {false, 0, reset, "", 0}
+  else
+    -- Grant a new lease, whose id is unique even after the sequence expires
+    -- Expiration should be set so that idle resources do not take up space
+    local id = now .. "-" .. redis.call("INCR", sequenceKey)
+    local expiresAt = now + leaseTimeout
+
+    redis.call("ZADD", key, expiresAt, id)
+    redis.call("PEXPIRE", key, leaseTimeout)
+    redis.call("PEXPIRE", sequenceKey, leaseTimeout)
+
+    local oldest = redis.call("ZRANGE", key, 0, 0, "WITHSCORES")
+
+    return {true, capacity - leases - 1, tonumber(oldest[2]), id, expiresAt}
+  end
+end
+
+return concurrency(
+  KEYS[1],
+  KEYS[2],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3])
+)
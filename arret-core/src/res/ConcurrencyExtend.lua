@@ -0,0 +1,28 @@
+local function concurrencyExtend(
+  key,
+  now,
+  leaseTimeout,
+  id
+)
+  -- Leases which already expired are dropped rather than brought back
+  redis.call("ZREMRANGEBYSCORE", key, "-inf", now)
+
+  if not redis.call("ZSCORE", key, id) then
+    -- The lease already expired or was released
+    return false
+  end
+
+  -- The extended lease expires last, so the resource expires along with it
+  local expiresAt = now + leaseTimeout
+  redis.call("ZADD", key, "XX", expiresAt, id)
+  redis.call("PEXPIRE", key, leaseTimeout)
+
+  return expiresAt
+end
+
+return concurrencyExtend(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  ARGV[3]
+)
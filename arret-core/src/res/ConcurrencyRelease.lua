@@ -0,0 +1,12 @@
+local function concurrencyRelease(
+  key,
+  id
+)
+  -- Leases which already expired or were released are no longer members
+  return redis.call("ZREM", key, id)
+end
+
+return concurrencyRelease(
+  KEYS[1],
+  ARGV[2]
+)
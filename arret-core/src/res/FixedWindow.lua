@@ -1,35 +1,80 @@
 local function fixedWindow(
-  slot,
+  key,
+  now,
   capacity,
   window,
-  requestedTokens
+  requestedTokens,
+  migrationPolicy,
+  mode,
+  modeArgs
 )
-  -- Retrieve the current bucket for the key,
-  -- or create a new one if it doesn't exist
-  local bucket = redis.call("GET", slot)
-  if bucket == false then
-    bucket = capacity
-  else
-    bucket = tonumber(bucket)
+  local windowId = math.floor(now / window)
+  local reset = (windowId + 1) * window
+
+  -- Retrieve the bucket of the current window for the key,
+  -- or create a new one if it doesn't exist or belongs to a past window
+  local bucket = capacity
+  local stored = redis.call("HMGET", key, "window", "bucket", "capacity", "length")
+  local storedWindowId = tonumber(stored[1])
+  local storedCapacity = tonumber(stored[3])
+  local storedWindow = tonumber(stored[4])
+  if storedCapacity == capacity and storedWindow == window then
+    if storedWindowId == windowId then
+      bucket = tonumber(stored[2])
+    end
+  elseif storedWindowId ~= nil and storedWindowId == math.floor(now / storedWindow) then
+    -- Migrate the bucket if it was written under other parameters,
+    -- as long as its window has not ended yet
+    bucket = migrate(migrationPolicy, tonumber(stored[2]), storedCapacity, capacity) or capacity
   end
 
-  if bucket < requestedTokens then
+  local available = bucket
+  if mode == "commit" then
+    local estimatedTokens, acquiredAt, expiresAt = modeArgs[1], modeArgs[2], modeArgs[3]
+
+    -- Estimates which have expired stay charged as estimated
+    if now >= expiresAt then
+      return {false, bucket, reset, now}
+    end
+
+    if requestedTokens > estimatedTokens then
+      -- Charge the difference with the actual tokens, even beyond the tokens left
+      -- since the request has already been served
+      bucket = math.max(0, bucket - (requestedTokens - estimatedTokens))
+    elseif math.floor(acquiredAt / window) == windowId then
+      -- Refund the difference only to the window the estimate was acquired in
+      bucket = math.min(capacity, bucket + estimatedTokens - requestedTokens)
+    end
+  elseif mode == "refund" then
+    local acquiredAt = modeArgs[1]
+
+    -- Return the tokens to the bucket, up to its capacity, only if they were
+    -- acquired in the current window, so that tokens of a past window are never
+    -- credited to another one
+    if math.floor(acquiredAt / window) == windowId then
+      bucket = math.min(capacity, bucket + requestedTokens)
+    end
+  elseif bucket < requestedTokens then
     -- Not enough tokens
-    return {false, bucket}
+    return {false, bucket, reset, now}
+  elseif mode == "check" then
+    -- Enough tokens, which are left in the bucket
+    return {true, bucket, reset, now}
   else
     -- Consume the tokens in the current window
-    -- Expiration should be set so that past slots do not take up space
     bucket = bucket - requestedTokens
+  end
 
-    redis.call("SET", slot, bucket, "EX", window)
-
-    return {true, bucket}
+  -- Update the bucket of the current window if its tokens changed
+  -- Expiration should be set so that past windows do not take up space
+  if bucket ~= available then
+    if bucket < capacity then
+      redis.call("HSET", key, "window", windowId, "bucket", bucket, "capacity", capacity, "length", window)
+      redis.call("PEXPIRE", key, reset - now)
+    else
+      redis.call("DEL", key)
+    end
   end
-end
 
-return fixedWindow(
-  KEYS[1],
-  tonumber(ARGV[1]),
-  tonumber(ARGV[2]),
-  tonumber(ARGV[3])
-)
+  return {true, bucket, reset, now}
+end
@@ -0,0 +1,10 @@
+return fixedWindow(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  ARGV[5],
+  ARGV[6],
+  {tonumber(ARGV[7]), tonumber(ARGV[8]), tonumber(ARGV[9])}
+)
@@ -0,0 +1,77 @@
+local function gcra(
+  key,
+  now,
+  capacity,
+  period,
+  requestedTokens,
+  migrationPolicy,
+  mode
+)
+  -- Each token is emitted every emission interval,
+  -- and up to a period worth of tokens may be requested at once
+  local emissionInterval = period / capacity
+  local burstTolerance = period
+
+  -- Tolerate floating point errors of a tiny fraction of a token
+  local epsilon = 1e-6
+  local function ceil(time)
+    return math.ceil(time - epsilon * emissionInterval)
+  end
+
+  -- Retrieve the theoretical arrival time for the key,
+  -- which is the current time if it doesn't exist
+  local tat = now
+  local stored = redis.call("HMGET", key, "tat", "capacity", "period")
+  if stored[1] then
+    tat = math.max(tonumber(stored[1]), now)
+
+    -- Migrate the tokens used if they were written under other parameters,
+    -- which are paid back over the emission interval
+    local storedCapacity = tonumber(stored[2])
+    local storedPeriod = tonumber(stored[3])
+    if storedCapacity ~= capacity or storedPeriod ~= period then
+      local used = (tat - now) / (storedPeriod / storedCapacity)
+      used = migrate(migrationPolicy, used, storedCapacity, capacity) or 0
+      tat = now + used * emissionInterval
+    end
+  end
+
+  local newTat = tat + requestedTokens * emissionInterval
+  if mode == "refund" then
+    -- Move the theoretical arrival time back, but not before the current time,
+    -- so that refunds never add up to more than the capacity
+    newTat = math.max(now, tat - requestedTokens * emissionInterval)
+  end
+  local allowAt = newTat - burstTolerance
+
+  if mode ~= "refund" and (allowAt - now) / emissionInterval > epsilon then
+    -- Not enough tokens
+    local remaining = math.floor((burstTolerance - (tat - now)) / emissionInterval + epsilon)
+    return {false, remaining, ceil(allowAt), now}
+  else
+    -- Update the theoretical arrival time
+    -- Expiration should be set so that idle keys do not take up space
+    local remaining = math.floor((burstTolerance - (newTat - now)) / emissionInterval + epsilon)
+
+    if requestedTokens > 0 then
+      if newTat > now then
+        redis.call("HSET", key, "tat", newTat, "capacity", capacity, "period", period)
+        redis.call("PEXPIRE", key, ceil(newTat - now))
+      else
+        redis.call("DEL", key)
+      end
+    end
+
+    return {true, remaining, ceil(newTat), now}
+  end
+end
+
+return gcra(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  ARGV[5],
+  ARGV[6]
+)
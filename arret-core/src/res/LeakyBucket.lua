@@ -0,0 +1,94 @@
+local function leakyBucket(
+  key,
+  now,
+  capacity,
+  drainInterval,
+  drainAmount,
+  requestedTokens,
+  migrationPolicy,
+  mode
+)
+  -- Each token takes a constant time to drain from the bucket
+  local drainTime = drainInterval / drainAmount
+
+  -- Tolerate floating point errors of a tiny fraction of a token
+  local epsilon = 1e-6
+  local function ceil(time)
+    return math.ceil(time - epsilon * drainTime)
+  end
+
+  -- Retrieve the time at which the queue of the bucket drains,
+  -- which is the current time if it doesn't exist
+  local tail = now
+  local stored = redis.call("HMGET", key, "tail", "capacity", "drainInterval", "drainAmount")
+  if stored[1] then
+    tail = math.max(tonumber(stored[1]), now)
+
+    -- Migrate the tokens queued if they were written under other parameters,
+    -- which then drain at the new rate
+    local storedCapacity = tonumber(stored[2])
+    local storedDrainInterval = tonumber(stored[3])
+    local storedDrainAmount = tonumber(stored[4])
+    if storedCapacity ~= capacity
+      or storedDrainInterval ~= drainInterval
+      or storedDrainAmount ~= drainAmount
+    then
+      local queued = (tail - now) / (storedDrainInterval / storedDrainAmount)
+      queued = migrate(migrationPolicy, queued, storedCapacity, capacity) or 0
+      tail = now + queued * drainTime
+    end
+  end
+
+  local queued = (tail - now) / drainTime
+
+  local newTail
+  local remaining
+  if mode == "refund" then
+    -- Take the tokens out of the queue, but not past the current time,
+    -- so that refunds never add up to more than the capacity
+    newTail = math.max(now, tail - requestedTokens * drainTime)
+    remaining = math.floor(capacity - (newTail - now) / drainTime + epsilon)
+  elseif queued + requestedTokens > capacity + epsilon then
+    -- Not enough room in the queue
+    local remaining = math.max(0, math.floor(capacity - queued + epsilon))
+    local retryAt = tail
+    if requestedTokens <= capacity then
+      retryAt = tail - (capacity - requestedTokens) * drainTime
+    end
+    return {false, remaining, ceil(retryAt), 0, now}
+  else
+    -- Enqueue the tokens, which proceed once the tokens ahead have drained
+    newTail = tail + requestedTokens * drainTime
+    remaining = math.floor(capacity - queued - requestedTokens + epsilon)
+  end
+
+  -- Update the queue of the bucket
+  -- Expiration should be set so that drained buckets do not take up space
+  if requestedTokens > 0 then
+    if newTail > now then
+      redis.call(
+        "HSET", key,
+        "tail", newTail,
+        "capacity", capacity,
+        "drainInterval", drainInterval,
+        "drainAmount", drainAmount
+      )
+      redis.call("PEXPIRE", key, ceil(newTail - now))
+    else
+      redis.call("DEL", key)
+    end
+  end
+
+  return {true, remaining, ceil(newTail), ceil(tail - now), now}
+end
+
+return leakyBucket(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  tonumber(ARGV[5]),
+  ARGV[6],
+  ARGV[7]
+)
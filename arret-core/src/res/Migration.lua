@@ -0,0 +1,15 @@
+-- Returns the tokens left in a state written under another capacity,
+-- or nil if the state is discarded
+local function migrate(policy, tokens, oldCapacity, capacity)
+  if policy == "reset" then
+    return nil
+  elseif policy == "proportional" then
+    if oldCapacity > 0 then
+      return math.floor(tokens * capacity / oldCapacity)
+    end
+    return 0
+  end
+
+  return math.min(tokens, capacity)
+end
+
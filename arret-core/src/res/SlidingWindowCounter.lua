@@ -0,0 +1,85 @@
+local function slidingWindowCounter(
+  key,
+  now,
+  capacity,
+  window,
+  requestedTokens,
+  migrationPolicy,
+  mode,
+  modeArgs
+)
+  local windowId = math.floor(now / window)
+  local elapsed = now - windowId * window
+
+  -- Retrieve the counters of the current and the previous window,
+  -- which are zero if they do not exist
+  local current = 0
+  local previous = 0
+  local stored = redis.call("HMGET", key, "window", "current", "previous", "capacity", "length")
+  local storedWindowId = tonumber(stored[1])
+  if storedWindowId == windowId then
+    current = tonumber(stored[2])
+    previous = tonumber(stored[3])
+  elseif storedWindowId == windowId - 1 then
+    previous = tonumber(stored[2])
+  end
+
+  -- Counters of windows of another length cannot be carried over,
+  -- while counters written under another capacity are migrated
+  local storedCapacity = tonumber(stored[4])
+  if storedWindowId ~= nil and tonumber(stored[5]) ~= window then
+    current = 0
+    previous = 0
+  elseif storedWindowId ~= nil and storedCapacity ~= capacity then
+    current = migrate(migrationPolicy, current, storedCapacity, capacity) or 0
+    previous = migrate(migrationPolicy, previous, storedCapacity, capacity) or 0
+  end
+
+  -- Weight the previous window by how much of it still overlaps the rolling window
+  local used = current + math.floor(previous * (window - elapsed) / window)
+
+  if mode == "refund" then
+    -- Take the tokens back from the counter of the window they were acquired in,
+    -- so that tokens of a past window are never credited to another one
+    local acquiredWindowId = math.floor(modeArgs[1] / window)
+    if acquiredWindowId == windowId then
+      current = math.max(0, current - requestedTokens)
+    elseif acquiredWindowId == windowId - 1 then
+      previous = math.max(0, previous - requestedTokens)
+    end
+  elseif used + requestedTokens > capacity then
+    -- Not enough tokens
+    return {false, current, previous, now}
+  else
+    -- Count the tokens in the current window
+    current = current + requestedTokens
+  end
+
+  -- Update the counters
+  -- Expiration should be set so that the counter outlives the next window,
+  -- where it is read as the previous one
+  if requestedTokens > 0 then
+    redis.call(
+      "HSET", key,
+      "window", windowId,
+      "current", current,
+      "previous", previous,
+      "capacity", capacity,
+      "length", window
+    )
+    redis.call("PEXPIRE", key, (windowId + 2) * window - now)
+  end
+
+  return {true, current, previous, now}
+end
+
+return slidingWindowCounter(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  ARGV[5],
+  ARGV[6],
+  {tonumber(ARGV[7])}
+)
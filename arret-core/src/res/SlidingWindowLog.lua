@@ -0,0 +1,112 @@
+local function slidingWindowLog(
+  key,
+  totalKey,
+  now,
+  capacity,
+  window,
+  requestedTokens,
+  mode
+)
+  -- Each grant is stored as a member "{timestamp}:{sequence}:{tokens}",
+  -- scored by the time it was granted. The sequence is zero-padded, so that
+  -- grants of the same time are ordered by it, and refunds take the latest one
+  local function grantTokens(grant)
+    return tonumber(string.match(grant, ":(%d+)$"))
+  end
+
+  -- The tokens of the grants in the log are kept in a running total,
+  -- which is summed from the log if it is missing, such as for a log
+  -- written before the total was kept
+  local total = tonumber(redis.call("GET", totalKey))
+  local used = total
+  if used == nil then
+    used = 0
+    for _, grant in ipairs(redis.call("ZRANGE", key, 0, -1)) do
+      used = used + grantTokens(grant)
+    end
+  end
+
+  -- Take the grants which have already slid out of the window out of the total,
+  -- and drop them unless only checking, which leaves the log untouched
+  local expired = redis.call("ZRANGEBYSCORE", key, "-inf", now - window)
+  for _, grant in ipairs(expired) do
+    used = used - grantTokens(grant)
+  end
+  if mode ~= "check" and #expired > 0 then
+    redis.call("ZREMRANGEBYSCORE", key, "-inf", now - window)
+  end
+
+  local oldest = redis.call(
+    "ZRANGEBYSCORE", key, "(" .. (now - window), "+inf", "WITHSCORES", "LIMIT", 0, 1
+  )
+  oldest = tonumber(oldest[2])
+
+  local accepted = true
+  local remaining = math.max(0, capacity - used)
+  if mode == "refund" then
+    -- Take the tokens back from the latest grants
+    local refunded = 0
+    while refunded < requestedTokens do
+      local latest = redis.call("ZREVRANGE", key, 0, 0, "WITHSCORES")
+      if #latest == 0 then
+        break
+      end
+
+      local grant, tokens = string.match(latest[1], "^(.*):(%d+)$")
+      tokens = tonumber(tokens)
+      local taken = math.min(tokens, requestedTokens - refunded)
+      refunded = refunded + taken
+
+      -- The tokens left are added back before the grant is removed, so that the log
+      -- is never emptied and deleted along with its expiration
+      if taken < tokens then
+        redis.call("ZADD", key, latest[2], grant .. ":" .. (tokens - taken))
+      end
+      redis.call("ZREM", key, latest[1])
+    end
+
+    used = used - refunded
+    remaining = math.max(0, capacity - used)
+    if used == 0 then
+      oldest = nil
+    end
+  elseif remaining < requestedTokens then
+    -- Not enough tokens
+    accepted = false
+  elseif mode ~= "check" then
+    -- Record the grant in the log
+    -- Expiration should be set so that idle logs do not take up space
+    remaining = remaining - requestedTokens
+
+    if requestedTokens > 0 then
+      -- Refunds take the grants of a time from the latest one, so the sequences of
+      -- the grants left are always the first ones and the count is the next one
+      local sequence = string.format("%010d", redis.call("ZCOUNT", key, now, now))
+      redis.call("ZADD", key, now, now .. ":" .. sequence .. ":" .. requestedTokens)
+      redis.call("PEXPIRE", key, window)
+      used = used + requestedTokens
+    end
+  end
+
+  -- Update the total if it changed, which expires along with the log
+  if mode ~= "check" and used ~= (total or 0) then
+    local ttl = redis.call("PTTL", key)
+    if used > 0 and ttl > 0 then
+      redis.call("SET", totalKey, used, "PX", ttl)
+    else
+      redis.call("DEL", totalKey)
+    end
+  end
+
+  return {accepted, remaining, (oldest or now) + window, now}
+end
+
+return slidingWindowLog(
+  KEYS[1],
+  KEYS[2],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  ARGV[5]
+)
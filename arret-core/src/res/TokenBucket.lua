@@ -4,15 +4,29 @@ local function tokenBucket(
   capacity,
   refillInterval,
   refillAmount,
-  requestedTokens
+  requestedTokens,
+  migrationPolicy,
+  mode,
+  modeArgs
 )
   -- Retrieve the current bucket for the key,
   -- or create a new one if it doesn't exist
   local bucket = redis.call("GET", key)
+  if bucket ~= false then
+    bucket = cjson.decode(bucket)
+
+    -- Migrate the bucket if it was written under other parameters
+    if bucket[3] ~= capacity or bucket[4] ~= refillInterval or bucket[5] ~= refillAmount then
+      local tokens = migrate(migrationPolicy, bucket[1], bucket[3], capacity)
+      if tokens == nil then
+        bucket = false
+      else
+        bucket[1] = tokens
+      end
+    end
+  end
   if bucket == false then
     bucket = {capacity, now}
-  else
-    bucket = cjson.decode(bucket)
   end
 
   local tokens = bucket[1]
@@ -23,26 +37,75 @@ local function tokenBucket(
   tokens = math.min(capacity, tokens + (intervalsPassed * refillAmount))
   lastUpdatedAt = lastUpdatedAt + (intervalsPassed * refillInterval)
 
-  if tokens < requestedTokens then
+  local available = tokens
+  if mode == "commit" then
+    local estimatedTokens, expiresAt = modeArgs[1], modeArgs[3]
+
+    -- Estimates which have expired stay charged as estimated
+    if now >= expiresAt then
+      return {false, tokens, lastUpdatedAt + refillInterval, now}
+    end
+
+    if requestedTokens > estimatedTokens then
+      -- Charge the difference with the actual tokens, even beyond the tokens left
+      -- since the request has already been served, but never into reserved tokens
+      tokens = math.min(tokens, math.max(0, tokens + estimatedTokens - requestedTokens))
+    else
+      -- Refund the difference with the actual tokens, up to the capacity
+      tokens = math.min(capacity, tokens + estimatedTokens - requestedTokens)
+    end
+  elseif mode == "refund" then
+    -- Return the tokens to the bucket, up to its capacity
+    tokens = math.min(capacity, tokens + requestedTokens)
+  elseif mode == "reserve" then
+    local maxDelay = modeArgs[1]
+
+    -- Tokens missing from the bucket are reserved from the refills to come
+    local proceedAt = now
+    if tokens < requestedTokens then
+      local refills = math.ceil((requestedTokens - tokens) / refillAmount)
+      proceedAt = lastUpdatedAt + (refills * refillInterval)
+    end
+
+    if requestedTokens > capacity or proceedAt - now > maxDelay then
+      -- Not refilled in time
+      return {false, tokens, lastUpdatedAt + refillInterval, now}
+    end
+
+    -- Consume the tokens, leaving the bucket in debt of the reserved ones
+    tokens = tokens - requestedTokens
+  elseif mode == "cancel" then
+    local proceedAt = modeArgs[1]
+
+    -- Reservations which have been proceeded with stay consumed
+    if now > proceedAt then
+      return {false, tokens, lastUpdatedAt + refillInterval, now}
+    end
+
+    -- Return the reserved tokens to the bucket, up to its capacity
+    tokens = math.min(capacity, tokens + requestedTokens)
+  elseif tokens < requestedTokens then
     -- Not enough tokens
-    return {false, tokens, lastUpdatedAt + refillInterval}
+    return {false, tokens, lastUpdatedAt + refillInterval, now}
+  elseif mode == "check" then
+    -- Enough tokens, which are left in the bucket
+    return {true, tokens, lastUpdatedAt + refillInterval, now}
   else
-    -- Consume the tokens, and update the token bucket
-    -- Expiration should be set so that full buckets do not take up space
+    -- Consume the tokens
     tokens = tokens - requestedTokens
-    local ttl = refillInterval * math.ceil((capacity - tokens) / refillAmount)
-
-    redis.call("SET", key, cjson.encode({tokens, lastUpdatedAt}), "EX", ttl)
+  end
 
-    return {true, tokens, lastUpdatedAt + refillInterval}
+  -- Update the token bucket if its tokens changed
+  -- Expiration should be set so that full buckets do not take up space
+  if tokens ~= available then
+    if tokens < capacity then
+      local ttl = refillInterval * math.ceil((capacity - tokens) / refillAmount)
+      local bucket = {tokens, lastUpdatedAt, capacity, refillInterval, refillAmount}
+      redis.call("SET", key, cjson.encode(bucket), "PX", ttl)
+    else
+      redis.call("DEL", key)
+    end
   end
-end
 
-return tokenBucket(
-  KEYS[1],
-  tonumber(ARGV[1]),
-  tonumber(ARGV[2]),
-  tonumber(ARGV[3]),
-  tonumber(ARGV[4]),
-  tonumber(ARGV[5])
-)
+  return {true, tokens, lastUpdatedAt + refillInterval, now}
+end
@@ -0,0 +1,11 @@
+return tokenBucket(
+  KEYS[1],
+  currentTime(ARGV[1]),
+  tonumber(ARGV[2]),
+  tonumber(ARGV[3]),
+  tonumber(ARGV[4]),
+  tonumber(ARGV[5]),
+  ARGV[6],
+  ARGV[7],
+  {tonumber(ARGV[8]), tonumber(ARGV[9]), tonumber(ARGV[10])}
+)
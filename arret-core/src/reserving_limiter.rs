@@ -0,0 +1,94 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{
+    error::Result,
+    rate_limiter::{Quota, RateLimiter},
+    store::Store,
+};
+
+/// A rate limiter which reserves tokens ahead of their refill, instead of throttling
+/// requests as soon as the tokens run out.
+///
+/// A [`Reservation`] tells the caller the exact instant it may proceed, so that it can
+/// wait for it rather than retry. Reserved tokens are deducted from future refills, and
+/// are restored if the reservation is [cancelled](Self::cancel).
+pub trait ReservingLimiter: RateLimiter {
+    /// Reserve `tokens` for the given `resource`.
+    ///
+    /// If the tokens are refilled within the maximum delay of the rule, they are reserved
+    /// and [`ReservationResult::Ok`] is returned, with the instant the caller may proceed.
+    /// Otherwise, nothing is reserved and [`ReservationResult::Throttled`] is returned.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn reserve<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<ReservationResult>
+    where
+        S: Store + ?Sized;
+
+    /// Cancel the given `reservation`, which restores its tokens up to the capacity of the
+    /// rule.
+    ///
+    /// Returns `false` if the instant to proceed had already passed, in which case the
+    /// tokens are considered used and nothing is restored.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    fn cancel<S>(&self, reservation: Reservation, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized;
+}
+
+/// A result from a reservation.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReservationResult {
+    /// The tokens were reserved.
+    Ok(Reservation, Quota),
+
+    /// The request was denied because the tokens would not be refilled within the
+    /// maximum delay of the rule.
+    Throttled(Quota),
+}
+
+/// Tokens reserved by a [`ReservingLimiter`], which the caller may use from a given
+/// instant.
+///
+/// A reservation is cancelled at most once, which is why it cannot be cloned.
+#[derive(Debug, PartialEq, Eq, Hash)]
+#[must_use = "a reservation should be waited for, or cancelled if the tokens are not used"]
+pub struct Reservation {
+    resource: String,
+    tokens: u64,
+    proceed_at: u64,
+}
+
+impl Reservation {
+    pub(crate) fn new(resource: &str, tokens: u64, proceed_at: u64) -> Self {
+        Self {
+            resource: resource.into(),
+            tokens,
+            proceed_at,
+        }
+    }
+
+    /// Returns the resource the tokens were reserved for.
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// Returns the number of tokens reserved.
+    pub fn tokens(&self) -> u64 {
+        self.tokens
+    }
+
+    /// Returns the epochmillis timestamp from which the caller may proceed.
+    pub fn proceed_at(&self) -> u64 {
+        self.proceed_at
+    }
+
+    /// Returns how long the caller should wait after `now` before proceeding.
+    ///
+    /// Returns [`Duration::ZERO`] if the caller may already proceed.
+    pub fn delay(&self, now: SystemTime) -> Duration {
+        (SystemTime::UNIX_EPOCH + Duration::from_millis(self.proceed_at))
+            .duration_since(now)
+            .unwrap_or(Duration::ZERO)
+    }
+}
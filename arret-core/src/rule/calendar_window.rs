@@ -0,0 +1,670 @@
+use std::sync::OnceLock;
+
+use chrono::{
+    DateTime, Datelike, Days, LocalResult, Months, NaiveDate, NaiveDateTime, Offset, TimeDelta,
+    TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+use crate::{
+    error::{Error, Result},
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock},
+    migration::MigrationPolicy,
+    Mode,
+};
+
+/// A fixed window aligned on the calendar of a timezone, such as a day from midnight to
+/// midnight, or a month from the first day of the month.
+///
+/// Windows start at an anchor, which is a local date and time in the timezone of the
+/// rule, and repeat every period from there, such as each month from the start date of a
+/// billing cycle. The bounds of the windows follow the calendar: a window of a day may
+/// last 23 or 25 hours across a change of daylight saving time, and a monthly window
+/// anchored on the 31st starts on the last day of shorter months. [`Quota::reset`] is
+/// the end of the current window.
+///
+/// Calendar windows always read the time of the client, from the [`Clock`] of the rule,
+/// and have no [`TimeSource`](super::TimeSource): the bounds of the windows are computed
+/// by the client from the timezone database, which Redis does not have, so the current
+/// time must be known before the request is sent. The entries of the windows also expire
+/// at their end according to the time of the client, so clients should keep their clocks
+/// in sync.
+///
+/// ```rust
+/// use arret_core::{
+///     rate_limiter::{AcquireResult, RateLimiter},
+///     rule::{CalendarPeriod, CalendarWindow},
+///     store::MemoryStore,
+/// };
+/// use chrono::NaiveDate;
+///
+/// // 10 000 requests per billing cycle, which starts on the 15th of each month in Paris
+/// let anchor = NaiveDate::from_ymd_opt(2024, 1, 15)
+///     .unwrap()
+///     .and_hms_opt(0, 0, 0)
+///     .unwrap();
+/// let calendar_window = CalendarWindow::new(10_000, CalendarPeriod::Months(1))
+///     .unwrap()
+///     .with_timezone(chrono_tz::Europe::Paris)
+///     .with_anchor(anchor);
+///
+/// let mut store = MemoryStore::new();
+///
+/// let res = calendar_window.acquire("customer:42", 1, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Ok(quota) if quota.remaining == 9_999));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CalendarWindow<K = SystemClock> {
+    capacity: u64,
+    period: CalendarPeriod,
+    timezone: Tz,
+    anchor: NaiveDateTime,
+    keyspace: Keyspace,
+    migration_policy: MigrationPolicy,
+    clock: K,
+}
+
+/// The period of a [`CalendarWindow`], in units of the calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CalendarPeriod {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+    Years(u32),
+}
+
+/// The longest period of a calendar window, in months, which is 10 000 years.
+const MAX_PERIOD_MONTHS: u32 = 12 * 10_000;
+
+/// The longest period of a calendar window, in days, which is about 10 000 years.
+const MAX_PERIOD_DAYS: u64 = 3_652_425;
+
+/// A period in the units it is added to a date in.
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Days(u64),
+    Months(u32),
+}
+
+impl CalendarPeriod {
+    fn step(&self) -> Option<Step> {
+        match *self {
+            Self::Days(days) => Some(Step::Days(u64::from(days))),
+            Self::Weeks(weeks) => Some(Step::Days(u64::from(weeks) * 7)),
+            Self::Months(months) => Some(Step::Months(months)),
+            Self::Years(years) => years.checked_mul(12).map(Step::Months),
+        }
+    }
+}
+
+impl CalendarWindow {
+    /// Creates a new [`CalendarWindow`] with the given capacity and period.
+    ///
+    /// Windows are anchored at midnight of January 1st, 1970, in UTC, so that days start
+    /// at midnight, months on their first day and years on January 1st. Weeks are
+    /// anchored on Monday, January 5th, 1970 instead.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if the period is zero or longer than 10 000 years.
+    pub fn new(capacity: u64, period: CalendarPeriod) -> Result<Self> {
+        match period.step() {
+            Some(Step::Days(0) | Step::Months(0)) => {
+                return Err(Error::InvalidRule("Period must not be zero".into()))
+            }
+            Some(Step::Days(days)) if days <= MAX_PERIOD_DAYS => {}
+            Some(Step::Months(months)) if months <= MAX_PERIOD_MONTHS => {}
+            _ => return Err(Error::InvalidRule("Period is too long".into())),
+        }
+
+        let anchor = match period {
+            CalendarPeriod::Weeks(_) => NaiveDate::from_ymd_opt(1970, 1, 5),
+            _ => NaiveDate::from_ymd_opt(1970, 1, 1),
+        };
+
+        Ok(Self {
+            capacity,
+            period,
+            timezone: Tz::UTC,
+            anchor: anchor
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .expect("Anchor is a valid date"),
+            keyspace: Keyspace::default(),
+            migration_policy: MigrationPolicy::default(),
+            clock: SystemClock,
+        })
+    }
+}
+
+impl<K> CalendarWindow<K> {
+    /// Returns the capacity of the calendar window rule.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the period of the calendar window rule.
+    pub fn period(&self) -> CalendarPeriod {
+        self.period
+    }
+
+    /// Returns the timezone the windows of the rule follow the calendar of.
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    /// Sets the timezone the windows of the rule follow the calendar of.
+    ///
+    /// Defaults to UTC.
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Returns the local date and time the windows of the rule start from.
+    pub fn anchor(&self) -> NaiveDateTime {
+        self.anchor
+    }
+
+    /// Sets the local date and time the windows of the rule start from, such as the
+    /// start of the billing cycle of a customer.
+    ///
+    /// Windows repeat every period from the anchor, before and after it. An anchor
+    /// skipped by a change of daylight saving time is shifted forward by the length of
+    /// the change, as clocks are, and an anchor repeated by one is taken the first time.
+    pub fn with_anchor(mut self, anchor: NaiveDateTime) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Loads the script of the calendar window rule into the script cache of Redis, so
+    /// that requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<CalendarWindowOperation>(con)
+    }
+
+    /// Loads the script of the calendar window rule into the script cache of Redis
+    /// asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<CalendarWindowOperation, C>(con).await
+    }
+
+    /// Returns the keyspace the keys of the calendar window rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the calendar window rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns what the calendar window rule does with the tokens left in the current
+    /// window of a resource written under another capacity.
+    pub fn migration_policy(&self) -> MigrationPolicy {
+        self.migration_policy
+    }
+
+    /// Sets what the calendar window rule does with the tokens left in the current window
+    /// of a resource written under another capacity, such as after a change of plan.
+    ///
+    /// Defaults to [`MigrationPolicy::Clamp`].
+    pub fn with_migration_policy(mut self, migration_policy: MigrationPolicy) -> Self {
+        self.migration_policy = migration_policy;
+        self
+    }
+
+    /// Returns the clock the calendar window rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the calendar window rule reads the current time from.
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> CalendarWindow<C> {
+        CalendarWindow {
+            capacity: self.capacity,
+            period: self.period,
+            timezone: self.timezone,
+            anchor: self.anchor,
+            keyspace: self.keyspace,
+            migration_policy: self.migration_policy,
+            clock,
+        }
+    }
+
+    /// Returns the local date and time the `index`-th window after the anchor starts at,
+    /// or `None` if it is out of the range of dates.
+    fn boundary(&self, index: i64) -> Option<NaiveDateTime> {
+        let step = self.period.step().expect("Period is validated");
+        match step {
+            Step::Days(days) => {
+                let days = Days::new(index.unsigned_abs().checked_mul(days)?);
+                if index >= 0 {
+                    self.anchor.checked_add_days(days)
+                } else {
+                    self.anchor.checked_sub_days(days)
+                }
+            }
+            // Months are added to the anchor rather than to the previous window, so that
+            // a window anchored on the 31st is back on the 31st after a shorter month
+            Step::Months(months) => index
+                .unsigned_abs()
+                .checked_mul(u64::from(months))
+                .and_then(|months| u32::try_from(months).ok())
+                .map(Months::new)
+                .and_then(|months| {
+                    if index >= 0 {
+                        self.anchor.checked_add_months(months)
+                    } else {
+                        self.anchor.checked_sub_months(months)
+                    }
+                }),
+        }
+    }
+
+    /// Returns the instant a local date and time happens at in the timezone of the rule.
+    fn instant(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self.timezone.from_local_datetime(&local) {
+            LocalResult::Single(instant) | LocalResult::Ambiguous(instant, _) => {
+                instant.with_timezone(&Utc)
+            }
+            LocalResult::None => {
+                // Local times skipped by a change of daylight saving time are read with
+                // the offset before the change, which shifts them forward by its length
+                let before = self
+                    .timezone
+                    .offset_from_utc_datetime(&(local - TimeDelta::days(1)))
+                    .fix();
+                Utc.from_utc_datetime(&(local - before))
+            }
+        }
+    }
+
+    /// Returns the start and the end of the window `now` is in, in milliseconds since
+    /// the Unix epoch.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if the window is out of the range of dates, such as with
+    ///   an anchor thousands of years away from the current time.
+    fn window(&self, now: u64) -> Result<(u64, u64)> {
+        let out_of_range =
+            || Error::InvalidRule("Current window is out of the range of dates".into());
+        let boundary = |index: i64| {
+            self.boundary(index)
+                .map(|local| self.instant(local))
+                .ok_or_else(out_of_range)
+        };
+
+        let now = i64::try_from(now)
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(out_of_range)?;
+        let local = now.with_timezone(&self.timezone).naive_local();
+
+        // The index of the window is estimated from the local dates, then moved to the
+        // window which actually contains the current time
+        let mut index = match self.period.step().expect("Period is validated") {
+            Step::Days(days) => (local.date() - self.anchor.date())
+                .num_days()
+                .div_euclid(days as i64),
+            Step::Months(months) => {
+                let elapsed = i64::from(local.year() - self.anchor.year()) * 12
+                    + i64::from(local.month())
+                    - i64::from(self.anchor.month());
+                elapsed.div_euclid(i64::from(months))
+            }
+        };
+        while boundary(index)? > now {
+            index -= 1;
+        }
+        while boundary(index + 1)? <= now {
+            index += 1;
+        }
+
+        let start = boundary(index)?;
+        let end = boundary(index + 1)?;
+        Ok((
+            start.timestamp_millis().max(0) as u64,
+            end.timestamp_millis() as u64,
+        ))
+    }
+}
+
+impl<K: Clock> CalendarWindow<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> Result<CalendarWindowOperation> {
+        let now = self.clock.now();
+        let (start, end) = self.window(now)?;
+
+        Ok(CalendarWindowOperation {
+            keys: [self.keyspace.key("calendar_window", resource)],
+            now,
+            capacity: self.capacity,
+            start,
+            end,
+            tokens,
+            mode: Mode::Acquire,
+            migration_policy: self.migration_policy,
+        })
+    }
+
+    fn refund_operation(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+    ) -> Result<CalendarWindowOperation> {
+        Ok(CalendarWindowOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)?
+        })
+    }
+
+    fn check_operation(&self, resource: &str) -> Result<CalendarWindowOperation> {
+        Ok(CalendarWindowOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)?
+        })
+    }
+
+    fn acquire_result(&self, result: CalendarWindowScriptResult) -> AcquireResult {
+        let quota = Quota::new(self.capacity, result.bucket, result.reset);
+
+        if result.accepted {
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Throttled(quota)
+        }
+    }
+}
+
+impl<K: Clock> RateLimiter for CalendarWindow<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens)?)?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at)?)?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        // Every window is computed from the same clock, so none can be if one cannot
+        let operations = match acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect::<Result<Vec<_>>>()
+        {
+            Ok(operations) => operations,
+            Err(err) => return acquisitions.iter().map(|_| Err(err.clone())).collect(),
+        };
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource)?)?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::RateLimiter for CalendarWindow<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)?).await?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at)?)
+            .await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        // Every window is computed from the same clock, so none can be if one cannot
+        let operations = match acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect::<Result<Vec<_>>>()
+        {
+            Ok(operations) => operations,
+            Err(err) => return acquisitions.iter().map(|_| Err(err.clone())).collect(),
+        };
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)?).await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+/// Acquisition or refund of tokens from the current calendar window of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct CalendarWindowOperation {
+    keys: [String; 1],
+    now: u64,
+    capacity: u64,
+    start: u64,
+    end: u64,
+    tokens: u64,
+    mode: Mode,
+    migration_policy: MigrationPolicy,
+}
+
+impl sealed::Script for CalendarWindowOperation {
+    const FUNCTION: &'static str = super::CALENDAR_WINDOW_FUNCTION;
+
+    const SOURCE: &'static str = super::CALENDAR_WINDOW_SOURCE;
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity)
+            .arg(self.start)
+            .arg(self.end)
+            .arg(self.tokens)
+            .arg(self.migration_policy.as_str());
+        self.mode.args(args);
+    }
+}
+
+impl Operation for CalendarWindowOperation {
+    type Output = CalendarWindowScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        Some(self.now)
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        // The entry holds the start of its window, the bucket of that window and the
+        // capacity it was written under
+        let bucket = match &entries[0] {
+            Some(entry) if entry.values[0] == self.start as f64 => {
+                if entry.values[2] == self.capacity as f64 {
+                    entry.values[1] as u64
+                } else {
+                    self.migration_policy
+                        .migrate(entry.values[1], entry.values[2], self.capacity as f64)
+                        .map_or(self.capacity, |bucket| bucket as u64)
+                }
+            }
+            _ => self.capacity,
+        };
+
+        let available = bucket;
+        let bucket = match self.mode {
+            Mode::Refund { acquired_at } if (self.start..self.end).contains(&acquired_at) => {
+                self.capacity.min(bucket + self.tokens)
+            }
+            Mode::Refund { .. } => bucket,
+            Mode::Commit { .. } | Mode::Reserve { .. } | Mode::Cancel { .. } => {
+                unreachable!("calendar windows only acquire and refund tokens")
+            }
+            Mode::Acquire | Mode::Check if bucket < self.tokens => {
+                return CalendarWindowScriptResult {
+                    accepted: false,
+                    bucket,
+                    reset: self.end,
+                    now,
+                };
+            }
+            Mode::Check => {
+                return CalendarWindowScriptResult {
+                    accepted: true,
+                    bucket,
+                    reset: self.end,
+                    now,
+                };
+            }
+            Mode::Acquire => bucket - self.tokens,
+        };
+
+        if bucket != available {
+            entries[0] = (bucket < self.capacity).then(|| {
+                let values = vec![self.start as f64, bucket as f64, self.capacity as f64];
+                Entry::new(values, self.end)
+            });
+        }
+
+        CalendarWindowScriptResult {
+            accepted: true,
+            bucket,
+            reset: self.end,
+            now,
+        }
+    }
+}
+
+/// Result of a calendar window operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CalendarWindowScriptResult {
+    accepted: bool,
+    bucket: u64,
+    reset: u64,
+    now: u64,
+}
+
+impl redis::FromRedisValue for CalendarWindowScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, bucket, reset, now): (bool, u64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            bucket,
+            reset,
+            now,
+        })
+    }
+}
@@ -1,9 +1,49 @@
 use std::time::SystemTime;
 
-/// Returns the current time in seconds since the Unix epoch.
-pub fn now() -> u64 {
-    SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
+/// A source of the current time for rules.
+///
+/// Rules read the [`SystemClock`] by default. Another clock may be used to control
+/// time explicitly, for example in tests.
+pub trait Clock {
+    /// Returns the current time in milliseconds since the Unix epoch.
+    fn now(&self) -> u64;
+}
+
+/// A [`Clock`] reading the system time of the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64
+    }
+}
+
+/// Where a rule reads the current time from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TimeSource {
+    /// The [`Clock`] of the rule, which is the clock of the host making the request
+    /// unless another one is given.
+    #[default]
+    Client,
+
+    /// The clock of the store, such as the Redis server whose time is read with `TIME`
+    /// within the rule's script.
+    ///
+    /// Hosts sharing a Redis then agree on the time even if their clocks are skewed.
+    Server,
+}
+
+impl TimeSource {
+    /// Returns the current time of a request read from `clock`, which is left out for
+    /// the store to read its own time.
+    pub(crate) fn now(&self, clock: &impl Clock) -> Option<u64> {
+        match self {
+            Self::Client => Some(clock.now()),
+            Self::Server => None,
+        }
+    }
 }
@@ -0,0 +1,861 @@
+use std::sync::OnceLock;
+
+use crate::{
+    error::{Error, Result},
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    fixed_window::{FixedWindow, FixedWindowOperation},
+    token_bucket::{TokenBucket, TokenBucketOperation},
+    Mode,
+};
+
+/// A rule made of several [`FixedWindow`] and [`TokenBucket`] rules, which only admits a
+/// request if every one of them does.
+///
+/// The rules are evaluated in a single operation, so that tokens are acquired from all
+/// of them or from none, unlike acquiring from each rule in turn, which consumes the
+/// tokens of the rules admitting a request even when a later one throttles it.
+///
+/// Each rule is named, and its state is keyed by its name, so that rules may be added,
+/// removed or reordered without the others losing their state. The composite reads the
+/// current time and builds the keys of its rules itself, from its own time source,
+/// keyspace and clock, so its rules may not set theirs.
+///
+/// Every rule applies to the same resource, or each rule to a resource of its own with
+/// [`acquire_each_for`](Self::acquire_each_for), such as a limit per user along with a
+/// limit per tenant. The keys of a request share a hash tag, so that a composite is also
+/// served by a single node of Redis Cluster.
+///
+/// ```rust
+/// use arret_core::{
+///     interval::Interval,
+///     rate_limiter::{AcquireResult, RateLimiter},
+///     rule::{Composite, FixedWindow, TokenBucket},
+///     store::MemoryStore,
+/// };
+///
+/// let composite = Composite::new([
+///     (
+///         "burst",
+///         TokenBucket::new(10, Interval::from_secs(1).unwrap(), 10).unwrap().into(),
+///     ),
+///     (
+///         "daily",
+///         FixedWindow::new(15, Interval::from_secs(86_400).unwrap()).unwrap().into(),
+///     ),
+/// ])
+/// .unwrap();
+///
+/// let mut store = MemoryStore::new();
+///
+/// let res = composite.acquire("user:42", 10, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Ok(quota) if quota.remaining == 0));
+///
+/// // The token bucket throttles the request, so nothing is taken from the fixed window
+/// let res = composite.acquire("user:42", 5, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Throttled(_)));
+///
+/// let (_, quotas) = composite.acquire_each("user:42", 0, &mut store).unwrap();
+/// assert_eq!(quotas[1].remaining, 5);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Composite<K = SystemClock> {
+    rules: Vec<(String, CompositeRule)>,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    clock: K,
+}
+
+/// A rule of a [`Composite`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompositeRule {
+    FixedWindow(FixedWindow),
+    TokenBucket(TokenBucket),
+}
+
+impl CompositeRule {
+    fn capacity(&self) -> u64 {
+        match self {
+            Self::FixedWindow(fixed_window) => fixed_window.capacity(),
+            Self::TokenBucket(token_bucket) => token_bucket.capacity(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::FixedWindow(_) => "fixed_window",
+            Self::TokenBucket(_) => "token_bucket",
+        }
+    }
+
+    fn time_source(&self) -> TimeSource {
+        match self {
+            Self::FixedWindow(fixed_window) => fixed_window.time_source(),
+            Self::TokenBucket(token_bucket) => token_bucket.time_source(),
+        }
+    }
+
+    fn keyspace(&self) -> &Keyspace {
+        match self {
+            Self::FixedWindow(fixed_window) => fixed_window.keyspace(),
+            Self::TokenBucket(token_bucket) => token_bucket.keyspace(),
+        }
+    }
+}
+
+impl From<FixedWindow> for CompositeRule {
+    fn from(fixed_window: FixedWindow) -> Self {
+        Self::FixedWindow(fixed_window)
+    }
+}
+
+impl From<TokenBucket> for CompositeRule {
+    fn from(token_bucket: TokenBucket) -> Self {
+        Self::TokenBucket(token_bucket)
+    }
+}
+
+impl Composite {
+    /// Creates a new [`Composite`] of the given rules, each along with its name.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if there is no rule, if a name is empty, repeated or
+    ///   contains `{` or `}`, or if a rule sets its own time source or keyspace.
+    pub fn new<N: Into<String>>(
+        rules: impl IntoIterator<Item = (N, CompositeRule)>,
+    ) -> Result<Self> {
+        let rules: Vec<_> = rules
+            .into_iter()
+            .map(|(name, rule)| (name.into(), rule))
+            .collect();
+        if rules.is_empty() {
+            return Err(Error::InvalidRule(
+                "Composite must have at least one rule".into(),
+            ));
+        }
+
+        for (index, (name, rule)) in rules.iter().enumerate() {
+            if name.is_empty() || name.contains(['{', '}']) {
+                return Err(Error::InvalidRule(format!(
+                    "Name of a rule must not be empty nor contain braces: {name}"
+                )));
+            }
+            if rules[..index].iter().any(|(other, _)| other == name) {
+                return Err(Error::InvalidRule(format!(
+                    "Name of a rule must be unique: {name}"
+                )));
+            }
+            if rule.time_source() != TimeSource::default()
+                || rule.keyspace() != &Keyspace::default()
+            {
+                return Err(Error::InvalidRule(format!(
+                    "Rule {name} must not set its time source nor its keyspace, which are the \
+                     ones of the composite"
+                )));
+            }
+        }
+
+        Ok(Self {
+            rules,
+            time_source: TimeSource::default(),
+            keyspace: Keyspace::default(),
+            clock: SystemClock,
+        })
+    }
+}
+
+impl<K> Composite<K> {
+    /// Returns the rules of the composite rule, each along with its name.
+    pub fn rules(&self) -> &[(String, CompositeRule)] {
+        &self.rules
+    }
+
+    /// Loads the script of the composite rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<CompositeOperation>(con)
+    }
+
+    /// Loads the script of the composite rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<CompositeOperation, C>(con).await
+    }
+
+    /// Returns where the composite rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the composite rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the composite rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the composite rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns the clock the composite rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the composite rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> Composite<C> {
+        Composite {
+            rules: self.rules,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            clock,
+        }
+    }
+}
+
+impl<K: Clock> Composite<K> {
+    /// Returns the operation acquiring `tokens` from every rule, each for the resource
+    /// of the same index in `resources`, all tagged with `tag`.
+    fn operation(&self, tag: &str, resources: &[&str], tokens: u64) -> Result<CompositeOperation> {
+        if resources.len() != self.rules.len() {
+            return Err(Error::InvalidPath(format!(
+                "Composite has {} rules, got {} resources",
+                self.rules.len(),
+                resources.len()
+            )));
+        }
+
+        let now = self.time_source.now(&self.clock);
+
+        // Rules are keyed by their name, so that rules of the same kind do not share
+        // their state, and a resource other than the tag follows it
+        let rules = self
+            .rules
+            .iter()
+            .zip(resources)
+            .map(|((name, rule), &resource)| {
+                let path = if resource == tag {
+                    vec![tag]
+                } else {
+                    vec![tag, resource]
+                };
+                let key = self
+                    .keyspace
+                    .path_key(&format!("composite:{name}:{}", rule.kind()), &path);
+                match rule {
+                    CompositeRule::FixedWindow(fixed_window) => {
+                        RuleOperation::FixedWindow(fixed_window.operation_at(key, now, tokens))
+                    }
+                    CompositeRule::TokenBucket(token_bucket) => {
+                        RuleOperation::TokenBucket(token_bucket.operation_at(key, now, tokens))
+                    }
+                }
+            })
+            .collect();
+
+        Ok(CompositeOperation::new(now, rules))
+    }
+
+    /// Returns the operation acquiring `tokens` from every rule for `resource`.
+    fn resource_operation(&self, resource: &str, tokens: u64) -> CompositeOperation {
+        let resources = vec![resource; self.rules.len()];
+        self.operation(resource, &resources, tokens)
+            .expect("Every rule is given the resource")
+    }
+
+    fn check_operation(&self, resource: &str) -> CompositeOperation {
+        self.resource_operation(resource, 0).with_mode(Mode::Check)
+    }
+
+    fn acquire_each_result(&self, result: CompositeScriptResult) -> (AcquireResult, Vec<Quota>) {
+        result.acquire_each_result(self.rules.iter().map(|(_, rule)| rule.capacity()))
+    }
+
+    /// Try to acquire `tokens` from every rule for the given `resource`, as by
+    /// [`acquire`](RateLimiter::acquire), and also returns the quota of each rule, in
+    /// the order of the rules.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    pub fn acquire_each<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Vec<Quota>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.resource_operation(resource, tokens))?;
+
+        Ok(self.acquire_each_result(result))
+    }
+
+    /// Try to acquire `tokens` from every rule for the given `resource` asynchronously,
+    /// and also returns the quota of each rule, in the order of the rules.
+    ///
+    /// Requires a [`Store`](aio::Store), such as a Redis connection, to be passed in.
+    #[cfg(feature = "aio")]
+    pub async fn acquire_each_async<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Vec<Quota>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.resource_operation(resource, tokens))
+            .await?;
+
+        Ok(self.acquire_each_result(result))
+    }
+
+    /// Try to acquire `tokens` from every rule, each for the resource of the same index
+    /// in `resources`, such as a user for a limit per user and its tenant for a limit per
+    /// tenant, and also returns the quota of each rule, in the order of the rules.
+    ///
+    /// The keys of every rule are tagged with `tag`, such as the tenant, so that they are
+    /// placed in the same slot on Redis Cluster. A rule given `tag` itself as its
+    /// resource shares its state with [`acquire`](RateLimiter::acquire) for `tag`.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidPath`] if there is not one resource per rule.
+    ///
+    /// ```rust
+    /// use arret_core::{
+    ///     interval::Interval,
+    ///     rate_limiter::AcquireResult,
+    ///     rule::{Composite, FixedWindow},
+    ///     store::MemoryStore,
+    /// };
+    ///
+    /// let composite = Composite::new([
+    ///     ("user", FixedWindow::new(5, Interval::from_secs(60).unwrap()).unwrap().into()),
+    ///     ("tenant", FixedWindow::new(8, Interval::from_secs(60).unwrap()).unwrap().into()),
+    /// ])
+    /// .unwrap();
+    ///
+    /// let mut store = MemoryStore::new();
+    ///
+    /// let (res, _) = composite
+    ///     .acquire_each_for("acme", &["alice", "acme"], 5, &mut store)
+    ///     .unwrap();
+    /// assert!(matches!(res, AcquireResult::Ok(_)));
+    ///
+    /// // Bob has a limit of his own, but shares the one of the tenant with Alice
+    /// let (res, quotas) = composite
+    ///     .acquire_each_for("acme", &["bob", "acme"], 5, &mut store)
+    ///     .unwrap();
+    /// assert!(matches!(res, AcquireResult::Throttled(_)));
+    /// assert_eq!(quotas[0].remaining, 5);
+    /// assert_eq!(quotas[1].remaining, 3);
+    /// ```
+    pub fn acquire_each_for<S>(
+        &self,
+        tag: &str,
+        resources: &[&str],
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Vec<Quota>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(tag, resources, tokens)?)?;
+
+        Ok(self.acquire_each_result(result))
+    }
+
+    /// Try to acquire `tokens` from every rule asynchronously, each for the resource of
+    /// the same index in `resources`, all tagged with `tag`, and also returns the quota
+    /// of each rule, in the order of the rules.
+    ///
+    /// Requires a [`Store`](aio::Store), such as a Redis connection, to be passed in.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidPath`] if there is not one resource per rule.
+    #[cfg(feature = "aio")]
+    pub async fn acquire_each_for_async<S>(
+        &self,
+        tag: &str,
+        resources: &[&str],
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Vec<Quota>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.operation(tag, resources, tokens)?)
+            .await?;
+
+        Ok(self.acquire_each_result(result))
+    }
+
+    /// Refunds `tokens` acquired at `acquired_at` to every rule, each for the resource of
+    /// the same index in `resources`, all tagged with `tag`, as acquired by
+    /// [`acquire_each_for`](Self::acquire_each_for), and returns the quota of the rule
+    /// with the fewest tokens left.
+    ///
+    /// Fixed windows only take the tokens back if they were acquired in the current
+    /// window, as by [`refund`](RateLimiter::refund).
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidPath`] if there is not one resource per rule.
+    pub fn refund_for<S>(
+        &self,
+        tag: &str,
+        resources: &[&str],
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let operation = self
+            .operation(tag, resources, tokens)?
+            .with_mode(Mode::Refund { acquired_at });
+        let result = store.execute(&operation)?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+
+    /// Refunds `tokens` acquired at `acquired_at` to every rule asynchronously, each for
+    /// the resource of the same index in `resources`, all tagged with `tag`, and returns
+    /// the quota of the rule with the fewest tokens left.
+    ///
+    /// Requires a [`Store`](aio::Store), such as a Redis connection, to be passed in.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidPath`] if there is not one resource per rule.
+    #[cfg(feature = "aio")]
+    pub async fn refund_for_async<S>(
+        &self,
+        tag: &str,
+        resources: &[&str],
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operation = self
+            .operation(tag, resources, tokens)?
+            .with_mode(Mode::Refund { acquired_at });
+        let result = store.execute(&operation).await?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+}
+
+impl<K: Clock> RateLimiter for Composite<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.resource_operation(resource, tokens))?;
+        let now = result.now;
+
+        Ok((self.acquire_each_result(result).0, Some(now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let operation = self
+            .resource_operation(resource, tokens)
+            .with_mode(Mode::Refund { acquired_at });
+        let result = store.execute(&operation)?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.resource_operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_each_result(result).0))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::RateLimiter for Composite<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.resource_operation(resource, tokens))
+            .await?;
+        let now = result.now;
+
+        Ok((self.acquire_each_result(result).0, Some(now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operation = self
+            .resource_operation(resource, tokens)
+            .with_mode(Mode::Refund { acquired_at });
+        let result = store.execute(&operation).await?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.resource_operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_each_result(result).0))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+}
+
+/// The operation of a rule of a composite.
+#[derive(Debug, Clone)]
+enum RuleOperation {
+    FixedWindow(FixedWindowOperation),
+    TokenBucket(TokenBucketOperation),
+}
+
+impl RuleOperation {
+    fn key(&self) -> &str {
+        match self {
+            Self::FixedWindow(operation) => &operation.keys()[0],
+            Self::TokenBucket(operation) => &operation.keys()[0],
+        }
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        match self {
+            Self::FixedWindow(operation) => {
+                args.arg("fixed_window");
+                sealed::Script::args(operation, args);
+            }
+            Self::TokenBucket(operation) => {
+                args.arg("token_bucket");
+                sealed::Script::args(operation, args);
+            }
+        }
+    }
+
+    fn apply(&self, mode: Mode, now: u64, entries: &mut [Option<Entry>]) -> RuleResult {
+        match self {
+            Self::FixedWindow(operation) => {
+                let result = operation.clone().with_mode(mode).apply(now, entries);
+                RuleResult {
+                    accepted: result.accepted,
+                    tokens: result.bucket as i64,
+                    reset: result.reset,
+                }
+            }
+            Self::TokenBucket(operation) => {
+                let result = operation.clone().with_mode(mode).apply(now, entries);
+                RuleResult {
+                    accepted: result.accepted,
+                    tokens: result.tokens,
+                    reset: result.reset,
+                }
+            }
+        }
+    }
+}
+
+/// Acquisition or refund of tokens from every rule of a composite for a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct CompositeOperation {
+    keys: Vec<String>,
+    now: Option<u64>,
+    mode: Mode,
+    rules: Vec<RuleOperation>,
+}
+
+impl CompositeOperation {
+    fn new(now: Option<u64>, rules: Vec<RuleOperation>) -> Self {
+        Self {
+            keys: rules.iter().map(|rule| rule.key().to_owned()).collect(),
+            now,
+            mode: Mode::Acquire,
+            rules,
+        }
+    }
+
+    /// Returns the acquisition of tokens from every token bucket at once.
+    pub(super) fn token_buckets(now: Option<u64>, operations: Vec<TokenBucketOperation>) -> Self {
+        Self::new(
+            now,
+            operations
+                .into_iter()
+                .map(RuleOperation::TokenBucket)
+                .collect(),
+        )
+    }
+
+    pub(super) fn with_mode(self, mode: Mode) -> Self {
+        Self { mode, ..self }
+    }
+}
+
+impl sealed::Script for CompositeOperation {
+    const FUNCTION: &'static str = "arret_composite";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Migration.lua"),
+        include_str!("../res/FixedWindow.lua"),
+        include_str!("../res/TokenBucket.lua"),
+        include_str!("../res/Composite.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        self.mode.args(args);
+        for rule in &self.rules {
+            rule.args(args);
+        }
+    }
+}
+
+impl Operation for CompositeOperation {
+    type Output = CompositeScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        // Every rule is checked before tokens are acquired from any of them
+        if self.mode == Mode::Acquire {
+            let rules: Vec<_> = self
+                .rules
+                .iter()
+                .zip(entries.chunks_mut(1))
+                .map(|(rule, entry)| rule.apply(Mode::Check, now, entry))
+                .collect();
+
+            if rules.iter().any(|rule| !rule.accepted) {
+                return CompositeScriptResult {
+                    accepted: false,
+                    rules,
+                    now,
+                };
+            }
+        }
+
+        let rules = self
+            .rules
+            .iter()
+            .zip(entries.chunks_mut(1))
+            .map(|(rule, entry)| rule.apply(self.mode, now, entry))
+            .collect();
+
+        CompositeScriptResult {
+            accepted: true,
+            rules,
+            now,
+        }
+    }
+}
+
+/// Result of a composite operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CompositeScriptResult {
+    accepted: bool,
+    rules: Vec<RuleResult>,
+    now: u64,
+}
+
+impl CompositeScriptResult {
+    /// Returns the result of the acquisition along with the quota of each rule, given
+    /// the capacities of the rules.
+    pub(super) fn acquire_each_result(
+        &self,
+        capacities: impl IntoIterator<Item = u64>,
+    ) -> (AcquireResult, Vec<Quota>) {
+        let quotas: Vec<_> = capacities
+            .into_iter()
+            .zip(&self.rules)
+            .map(|(capacity, result)| {
+                Quota::new(capacity, result.tokens.max(0) as u64, result.reset)
+            })
+            .collect();
+
+        // A throttled request is bound by the throttling rule which resets last, and an
+        // admitted one by the rule with the fewest tokens left
+        let binding = if self.accepted {
+            quotas
+                .iter()
+                .min_by_key(|quota| (quota.remaining, u64::MAX - quota.reset))
+        } else {
+            quotas
+                .iter()
+                .zip(&self.rules)
+                .filter(|(_, result)| !result.accepted)
+                .map(|(quota, _)| quota)
+                .max_by_key(|quota| quota.reset)
+        };
+        let binding = *binding.expect("Composite has at least one rule");
+
+        if self.accepted {
+            (AcquireResult::Ok(binding), quotas)
+        } else {
+            (AcquireResult::Throttled(binding), quotas)
+        }
+    }
+}
+
+/// Result of a rule of a composite operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RuleResult {
+    accepted: bool,
+    tokens: i64,
+    reset: u64,
+}
+
+impl redis::FromRedisValue for CompositeScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, rules, now): (bool, Vec<RuleResult>, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            rules,
+            now,
+        })
+    }
+}
+
+impl redis::FromRedisValue for RuleResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, tokens, reset, _): (bool, i64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            tokens,
+            reset,
+        })
+    }
+}
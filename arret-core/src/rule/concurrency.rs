@@ -0,0 +1,472 @@
+use std::sync::OnceLock;
+
+use crate::{
+    concurrency_limiter::{ConcurrencyLimiter, Lease, LeaseResult},
+    error::Result,
+    interval::Interval,
+    rate_limiter::Quota,
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::clock::{Clock, SystemClock, TimeSource};
+
+/// Concurrency rule limits the number of operations in flight on a resource, across
+/// every process sharing the same Redis.
+///
+/// Each operation holds a [`Lease`] until it is released. Leases expire after the lease
+/// timeout, so that leases held by crashed workers are eventually freed, and operations
+/// which may take longer extend their lease while it is still held.
+///
+/// [`Quota::reset`] is the time at which the oldest lease held expires.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Concurrency<K = SystemClock> {
+    capacity: u64,
+    lease_timeout: Interval,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    clock: K,
+}
+
+impl Concurrency {
+    /// Creates a new [`Concurrency`] with the given capacity and lease timeout.
+    pub fn new(capacity: u64, lease_timeout: Interval) -> Result<Self> {
+        Ok(Self {
+            capacity,
+            lease_timeout,
+            time_source: TimeSource::default(),
+            keyspace: Keyspace::default(),
+            clock: SystemClock,
+        })
+    }
+}
+
+impl<K> Concurrency<K> {
+    /// Returns the capacity of the concurrency rule.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the lease timeout of the concurrency rule.
+    pub fn lease_timeout(&self) -> Interval {
+        self.lease_timeout
+    }
+
+    /// Loads the scripts of the concurrency rule into the script cache of Redis, so that
+    /// requests need not upload them.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<ConcurrencyOperation>(con)?;
+        store::preload::<ConcurrencyReleaseOperation>(con)?;
+        store::preload::<ConcurrencyExtendOperation>(con)?;
+        Ok(())
+    }
+
+    /// Loads the scripts of the concurrency rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<ConcurrencyOperation, C>(con).await?;
+        store::preload_async::<ConcurrencyReleaseOperation, C>(con).await?;
+        store::preload_async::<ConcurrencyExtendOperation, C>(con).await?;
+        Ok(())
+    }
+
+    /// Returns where the concurrency rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the concurrency rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the concurrency rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the concurrency rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns the clock the concurrency rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the concurrency rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> Concurrency<C> {
+        Concurrency {
+            capacity: self.capacity,
+            lease_timeout: self.lease_timeout,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            clock,
+        }
+    }
+}
+
+impl<K: Clock> Concurrency<K> {
+    fn acquire_operation(&self, resource: &str) -> ConcurrencyOperation {
+        let key = self.keyspace.key("concurrency", resource);
+        let sequence_key = format!("{key}:sequence");
+
+        ConcurrencyOperation {
+            keys: [key, sequence_key],
+            now: self.time_source.now(&self.clock),
+            capacity: self.capacity,
+            lease_timeout: self.lease_timeout.as_millis(),
+        }
+    }
+
+    fn release_operation(&self, lease: &Lease) -> ConcurrencyReleaseOperation {
+        ConcurrencyReleaseOperation {
+            keys: [self.keyspace.key("concurrency", lease.resource())],
+            now: self.time_source.now(&self.clock),
+            id: lease.id().into(),
+        }
+    }
+
+    fn extend_operation(&self, lease: &Lease) -> ConcurrencyExtendOperation {
+        ConcurrencyExtendOperation {
+            keys: [self.keyspace.key("concurrency", lease.resource())],
+            now: self.time_source.now(&self.clock),
+            lease_timeout: self.lease_timeout.as_millis(),
+            id: lease.id().into(),
+        }
+    }
+
+    fn lease_result(&self, resource: &str, result: ConcurrencyScriptResult) -> LeaseResult {
+        let quota = Quota::new(self.capacity, result.remaining, result.reset);
+
+        if result.accepted {
+            LeaseResult::Ok(Lease::new(resource, result.id, result.expires_at), quota)
+        } else {
+            LeaseResult::Throttled(quota)
+        }
+    }
+}
+
+impl<K: Clock> ConcurrencyLimiter for Concurrency<K> {
+    fn acquire<S>(&self, resource: &str, store: &mut S) -> Result<LeaseResult>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.acquire_operation(resource))?;
+
+        Ok(self.lease_result(resource, result))
+    }
+
+    fn release<S>(&self, lease: &Lease, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized,
+    {
+        store.execute(&self.release_operation(lease))
+    }
+
+    fn extend<S>(&self, lease: &Lease, store: &mut S) -> Result<Option<Lease>>
+    where
+        S: Store + ?Sized,
+    {
+        let expires_at = store.execute(&self.extend_operation(lease))?;
+
+        Ok(
+            expires_at
+                .map(|expires_at| Lease::new(lease.resource(), lease.id().into(), expires_at)),
+        )
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::ConcurrencyLimiter for Concurrency<K> {
+    async fn acquire<S>(&self, resource: &str, store: &mut S) -> Result<LeaseResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.acquire_operation(resource)).await?;
+
+        Ok(self.lease_result(resource, result))
+    }
+
+    async fn release<S>(&self, lease: &Lease, store: &mut S) -> Result<bool>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        store.execute(&self.release_operation(lease)).await
+    }
+
+    async fn extend<S>(&self, lease: &Lease, store: &mut S) -> Result<Option<Lease>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let expires_at = store.execute(&self.extend_operation(lease)).await?;
+
+        Ok(
+            expires_at
+                .map(|expires_at| Lease::new(lease.resource(), lease.id().into(), expires_at)),
+        )
+    }
+}
+
+/// Returns the leases held in a concurrency entry, as their expiration time, the time
+/// they were granted at and their sequence number.
+fn leases(entry: Option<Entry>) -> Vec<(u64, u64, u64)> {
+    entry.map_or_else(Vec::new, |entry| {
+        entry
+            .values
+            .chunks(3)
+            .map(|lease| (lease[0] as u64, lease[1] as u64, lease[2] as u64))
+            .collect()
+    })
+}
+
+/// Returns the entry holding the given leases, which is missing if none is held.
+fn leases_entry(leases: &[(u64, u64, u64)], expires_at: u64) -> Option<Entry> {
+    if leases.is_empty() {
+        return None;
+    }
+
+    let values = leases
+        .iter()
+        .flat_map(|&(lease_expires_at, granted_at, sequence)| {
+            [lease_expires_at as f64, granted_at as f64, sequence as f64]
+        })
+        .collect();
+    Some(Entry::new(values, expires_at))
+}
+
+/// Acquisition of a lease on a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct ConcurrencyOperation {
+    keys: [String; 2],
+    now: Option<u64>,
+    capacity: u64,
+    lease_timeout: u64,
+}
+
+impl sealed::Script for ConcurrencyOperation {
+    const FUNCTION: &'static str = "arret_concurrency";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Concurrency.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity).arg(self.lease_timeout);
+    }
+}
+
+impl Operation for ConcurrencyOperation {
+    type Output = ConcurrencyScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let entry_expires_at = entries[0].as_ref().map_or(now, |entry| entry.expires_at);
+        let mut leases = leases(entries[0].take());
+        leases.retain(|&(expires_at, _, _)| expires_at > now);
+        let held = leases.len() as u64;
+
+        if held >= self.capacity {
+            let reset = leases
+                .iter()
+                .map(|&(expires_at, _, _)| expires_at)
+                .min()
+                .unwrap_or(now);
+            entries[0] = leases_entry(&leases, entry_expires_at);
+
+            return ConcurrencyScriptResult {
+                accepted: false,
+                remaining: 0,
+                reset,
+                id: String::new(),
+                expires_at: 0,
+            };
+        }
+
+        // The sequence entry holds the sequence number of the last lease granted
+        let sequence = entries[1]
+            .as_ref()
+            .map_or(0, |entry| entry.values[0] as u64)
+            + 1;
+        let expires_at = now + self.lease_timeout;
+
+        leases.push((expires_at, now, sequence));
+        let reset = leases
+            .iter()
+            .map(|&(expires_at, _, _)| expires_at)
+            .min()
+            .unwrap_or(expires_at);
+        entries[0] = leases_entry(&leases, expires_at);
+        entries[1] = Some(Entry::new(vec![sequence as f64], expires_at));
+
+        ConcurrencyScriptResult {
+            accepted: true,
+            remaining: self.capacity - held - 1,
+            reset,
+            id: format!("{now}-{sequence}"),
+            expires_at,
+        }
+    }
+}
+
+/// Release of a lease on a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct ConcurrencyReleaseOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    id: String,
+}
+
+impl sealed::Script for ConcurrencyReleaseOperation {
+    const FUNCTION: &'static str = "arret_concurrency_release";
+
+    const SOURCE: &'static str = include_str!("../res/ConcurrencyRelease.lua");
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(&self.id);
+    }
+}
+
+impl Operation for ConcurrencyReleaseOperation {
+    type Output = bool;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, _now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let Some(entry) = entries[0].take() else {
+            return false;
+        };
+
+        let expires_at = entry.expires_at;
+        let mut leases = leases(Some(entry));
+        let held = leases.len();
+        leases.retain(|&(_, granted_at, sequence)| format!("{granted_at}-{sequence}") != self.id);
+        entries[0] = leases_entry(&leases, expires_at);
+
+        leases.len() < held
+    }
+}
+
+/// Extension of a lease on a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct ConcurrencyExtendOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    lease_timeout: u64,
+    id: String,
+}
+
+impl sealed::Script for ConcurrencyExtendOperation {
+    const FUNCTION: &'static str = "arret_concurrency_extend";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/ConcurrencyExtend.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.lease_timeout).arg(&self.id);
+    }
+}
+
+impl Operation for ConcurrencyExtendOperation {
+    type Output = Option<u64>;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let entry = entries[0].take()?;
+
+        // Leases which already expired are dropped rather than brought back
+        let expires_at = entry.expires_at;
+        let mut leases = leases(Some(entry));
+        leases.retain(|&(lease_expires_at, _, _)| lease_expires_at > now);
+
+        // The extended lease expires last, so the entry expires along with it
+        let extended = leases
+            .iter_mut()
+            .find(|(_, granted_at, sequence)| format!("{granted_at}-{sequence}") == self.id)
+            .map(|lease| {
+                lease.0 = now + self.lease_timeout;
+                lease.0
+            });
+        entries[0] = leases_entry(&leases, extended.unwrap_or(expires_at));
+
+        extended
+    }
+}
+
+/// Result of a concurrency operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConcurrencyScriptResult {
+    accepted: bool,
+    remaining: u64,
+    reset: u64,
+    id: String,
+    expires_at: u64,
+}
+
+impl redis::FromRedisValue for ConcurrencyScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, remaining, reset, id, expires_at): (bool, u64, u64, String, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            remaining,
+            reset,
+            id,
+            expires_at,
+        })
+    }
+}
@@ -1,31 +1,52 @@
+use std::sync::OnceLock;
+
 use crate::{
-    error::{Error, Result},
+    error::Result,
+    estimating_limiter::{Estimate, EstimateResult, EstimatingLimiter},
     interval::Interval,
     rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
 };
 
 #[cfg(feature = "aio")]
 use crate::aio;
 
-use super::clock;
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    migration::MigrationPolicy,
+    Mode,
+};
 
 /// [Fixed window](https://developer.redis.com/develop/java/spring/rate-limiting/fixed-window/)
 /// is a simple algorithm for rate limiting. It allows a limited amount of traffic in a fixed
 /// time window. Once the window is full, no more traffic is allowed until the window is reset.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct FixedWindow {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedWindow<K = SystemClock> {
     capacity: u64,
     window: Interval,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    migration_policy: MigrationPolicy,
+    estimate_timeout: Interval,
+    clock: K,
 }
 
 impl FixedWindow {
-    const REDIS_SCRIPT: &str = include_str!("../res/FixedWindow.lua");
-
     /// Creates a new [`FixedWindow`] with the given capacity and window.
     pub fn new(capacity: u64, window: Interval) -> Result<Self> {
-        Ok(Self { capacity, window })
+        Ok(Self {
+            capacity,
+            window,
+            time_source: TimeSource::default(),
+            keyspace: Keyspace::default(),
+            migration_policy: MigrationPolicy::default(),
+            estimate_timeout: Interval::from_secs(60)?,
+            clock: SystemClock,
+        })
     }
+}
 
+impl<K> FixedWindow<K> {
     /// Returns the capacity of the fixed window rule.
     pub fn capacity(&self) -> u64 {
         self.capacity
@@ -35,93 +56,545 @@ impl FixedWindow {
     pub fn window(&self) -> Interval {
         self.window
     }
+
+    /// Loads the script of the fixed window rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<FixedWindowOperation>(con)
+    }
+
+    /// Loads the script of the fixed window rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<FixedWindowOperation, C>(con).await
+    }
+
+    /// Returns where the fixed window rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the fixed window rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the fixed window rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the fixed window rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns what the fixed window rule does with the state of a resource written under other
+    /// parameters.
+    pub fn migration_policy(&self) -> MigrationPolicy {
+        self.migration_policy
+    }
+
+    /// Sets what the fixed window rule does with the state of a resource written under other
+    /// parameters, such as after a rollout changing them.
+    ///
+    /// Defaults to [`MigrationPolicy::Clamp`].
+    pub fn with_migration_policy(mut self, migration_policy: MigrationPolicy) -> Self {
+        self.migration_policy = migration_policy;
+        self
+    }
+
+    /// Returns how long estimates of the fixed window rule may be committed for.
+    pub fn estimate_timeout(&self) -> Interval {
+        self.estimate_timeout
+    }
+
+    /// Sets how long estimates of the fixed window rule may be committed for, after which
+    /// they stay charged as estimated.
+    ///
+    /// Defaults to one minute.
+    pub fn with_estimate_timeout(mut self, estimate_timeout: Interval) -> Self {
+        self.estimate_timeout = estimate_timeout;
+        self
+    }
+
+    /// Returns the clock the fixed window rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the fixed window rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> FixedWindow<C> {
+        FixedWindow {
+            capacity: self.capacity,
+            window: self.window,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            migration_policy: self.migration_policy,
+            estimate_timeout: self.estimate_timeout,
+            clock,
+        }
+    }
+}
+
+impl<K> FixedWindow<K> {
+    /// Returns the acquisition of `tokens` from the window at `key`, at `now`.
+    pub(super) fn operation_at(
+        &self,
+        key: String,
+        now: Option<u64>,
+        tokens: u64,
+    ) -> FixedWindowOperation {
+        FixedWindowOperation {
+            keys: [key],
+            now,
+            capacity: self.capacity,
+            window: self.window.as_millis(),
+            tokens,
+            mode: Mode::Acquire,
+            migration_policy: self.migration_policy,
+        }
+    }
 }
 
-impl RateLimiter for FixedWindow {
-    fn acquire(
+impl<K: Clock> FixedWindow<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> FixedWindowOperation {
+        self.operation_at(
+            self.keyspace.key("fixed_window", resource),
+            self.time_source.now(&self.clock),
+            tokens,
+        )
+    }
+
+    fn refund_operation(
         &self,
         resource: &str,
         tokens: u64,
-        con: &mut dyn redis::ConnectionLike,
-    ) -> Result<crate::rate_limiter::AcquireResult> {
-        let script = redis::Script::new(Self::REDIS_SCRIPT);
-
-        let window_id = clock::now() / self.window.as_secs();
-        let slot = format!("fixed_window:{resource}:{window_id}");
-        let reset = (window_id + 1) * self.window.as_secs();
-
-        let result: FixedWindowScriptResult = script
-            .key(&slot)
-            .arg(self.capacity)
-            .arg(self.window.as_secs())
-            .arg(tokens)
-            .invoke(con)
-            .map_err(|err| Error::Internal(err.to_string()))?;
+        acquired_at: u64,
+    ) -> FixedWindowOperation {
+        FixedWindowOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn check_operation(&self, resource: &str) -> FixedWindowOperation {
+        FixedWindowOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)
+        }
+    }
+
+    fn commit_operation(&self, estimate: &Estimate, actual: u64) -> FixedWindowOperation {
+        FixedWindowOperation {
+            mode: Mode::Commit {
+                estimated: estimate.tokens(),
+                acquired_at: estimate.acquired_at(),
+                expires_at: estimate.expires_at(),
+            },
+            ..self.operation(estimate.resource(), actual)
+        }
+    }
+
+    fn acquire_result(&self, result: FixedWindowScriptResult) -> AcquireResult {
+        let quota = Quota::new(self.capacity, result.bucket, result.reset);
+
+        if result.accepted {
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Throttled(quota)
+        }
+    }
+
+    fn estimate_result(
+        &self,
+        resource: &str,
+        tokens: u64,
+        result: FixedWindowScriptResult,
+    ) -> EstimateResult {
+        let quota = Quota::new(self.capacity, result.bucket, result.reset);
 
         if result.accepted {
-            Ok(AcquireResult::Ok(Quota::new(
-                self.capacity,
-                result.bucket,
-                reset,
-            )))
+            let expires_at = result.now + self.estimate_timeout.as_millis();
+            let estimate = Estimate::new(resource, tokens, result.now, expires_at);
+            EstimateResult::Ok(estimate, quota)
         } else {
-            Ok(AcquireResult::Throttled(Quota::new(
-                self.capacity,
-                result.bucket,
-                reset,
-            )))
+            EstimateResult::Throttled(quota)
         }
     }
 }
 
+impl<K: Clock> RateLimiter for FixedWindow<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens))?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+impl<K: Clock> EstimatingLimiter for FixedWindow<K> {
+    fn acquire_estimate<S>(
+        &self,
+        resource: &str,
+        estimate: u64,
+        store: &mut S,
+    ) -> Result<EstimateResult>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, estimate))?;
+
+        Ok(self.estimate_result(resource, estimate, result))
+    }
+
+    fn commit<S>(&self, estimate: Estimate, actual: u64, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.commit_operation(&estimate, actual))?;
+
+        Ok(result.accepted)
+    }
+}
+
 #[cfg(feature = "aio")]
 #[async_trait::async_trait]
-impl aio::RateLimiter for FixedWindow {
-    async fn acquire<C>(&self, resource: &str, tokens: u64, con: &mut C) -> Result<AcquireResult>
+impl<K: Clock + Send + Sync> aio::RateLimiter for FixedWindow<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
     where
-        C: redis::aio::ConnectionLike + Send + Sync,
+        S: aio::Store + Send + Sync,
     {
-        let script = redis::Script::new(Self::REDIS_SCRIPT);
-
-        let window_id = clock::now() / self.window.as_secs();
-        let slot = format!("fixed_window:{resource}:{window_id}");
-        let reset = (window_id + 1) * self.window.as_secs();
-
-        let result: FixedWindowScriptResult = script
-            .key(&slot)
-            .arg(self.capacity)
-            .arg(self.window.as_secs())
-            .arg(tokens)
-            .invoke_async::<C, FixedWindowScriptResult>(con)
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
             .await
-            .map_err(|err| Error::Internal(err.to_string()))?;
+            .map(|(result, _)| result)
+    }
 
-        if result.accepted {
-            Ok(AcquireResult::Ok(Quota::new(
-                self.capacity,
-                result.bucket,
-                reset,
-            )))
-        } else {
-            Ok(AcquireResult::Throttled(Quota::new(
-                self.capacity,
-                result.bucket,
-                reset,
-            )))
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)).await?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at))
+            .await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::EstimatingLimiter for FixedWindow<K> {
+    async fn acquire_estimate<S>(
+        &self,
+        resource: &str,
+        estimate: u64,
+        store: &mut S,
+    ) -> Result<EstimateResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, estimate)).await?;
+
+        Ok(self.estimate_result(resource, estimate, result))
+    }
+
+    async fn commit<S>(&self, estimate: Estimate, actual: u64, store: &mut S) -> Result<bool>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.commit_operation(&estimate, actual))
+            .await?;
+
+        Ok(result.accepted)
+    }
+}
+
+/// Acquisition, refund or commit of tokens from the current window of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct FixedWindowOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    capacity: u64,
+    window: u64,
+    tokens: u64,
+    mode: Mode,
+    migration_policy: MigrationPolicy,
+}
+
+impl FixedWindowOperation {
+    /// Returns the operation doing `mode` with the tokens instead.
+    pub(super) fn with_mode(self, mode: Mode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Returns the parameters of the window, as recorded in its entry.
+    fn parameters(&self) -> [f64; 2] {
+        [self.capacity as f64, self.window as f64]
+    }
+}
+
+impl sealed::Script for FixedWindowOperation {
+    const FUNCTION: &'static str = "arret_fixed_window";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Migration.lua"),
+        include_str!("../res/FixedWindow.lua"),
+        include_str!("../res/FixedWindowMain.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity)
+            .arg(self.window)
+            .arg(self.tokens)
+            .arg(self.migration_policy.as_str());
+        self.mode.args(args);
+    }
+}
+
+impl Operation for FixedWindowOperation {
+    type Output = FixedWindowScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let window_id = now / self.window;
+        let reset = (window_id + 1) * self.window;
+
+        // The entry holds the window id, the bucket of that window and the parameters
+        // of the window it was written under
+        let bucket = match &entries[0] {
+            Some(entry) if entry.values[2..] == self.parameters() => {
+                if entry.values[0] == window_id as f64 {
+                    entry.values[1] as u64
+                } else {
+                    self.capacity
+                }
+            }
+            Some(entry) if entry.values[0] == (now / entry.values[3] as u64) as f64 => self
+                .migration_policy
+                .migrate(entry.values[1], entry.values[2], self.capacity as f64)
+                .map_or(self.capacity, |bucket| bucket as u64),
+            _ => self.capacity,
+        };
+
+        let available = bucket;
+        let bucket = match self.mode {
+            Mode::Commit {
+                estimated,
+                acquired_at,
+                expires_at,
+            } => {
+                if now >= expires_at {
+                    return FixedWindowScriptResult {
+                        accepted: false,
+                        bucket,
+                        reset,
+                        now,
+                    };
+                }
+                if self.tokens > estimated {
+                    bucket.saturating_sub(self.tokens - estimated)
+                } else if acquired_at / self.window == window_id {
+                    self.capacity.min(bucket + estimated - self.tokens)
+                } else {
+                    bucket
+                }
+            }
+            Mode::Refund { acquired_at } if acquired_at / self.window == window_id => {
+                self.capacity.min(bucket + self.tokens)
+            }
+            Mode::Refund { .. } => bucket,
+            Mode::Reserve { .. } | Mode::Cancel { .. } => {
+                unreachable!("fixed windows do not take reservations")
+            }
+            Mode::Acquire | Mode::Check if bucket < self.tokens => {
+                return FixedWindowScriptResult {
+                    accepted: false,
+                    bucket,
+                    reset,
+                    now,
+                };
+            }
+            Mode::Check => {
+                return FixedWindowScriptResult {
+                    accepted: true,
+                    bucket,
+                    reset,
+                    now,
+                };
+            }
+            Mode::Acquire => bucket - self.tokens,
+        };
+
+        if bucket != available {
+            entries[0] = (bucket < self.capacity).then(|| {
+                let mut values = vec![window_id as f64, bucket as f64];
+                values.extend(self.parameters());
+                Entry::new(values, reset)
+            });
+        }
+
+        FixedWindowScriptResult {
+            accepted: true,
+            bucket,
+            reset,
+            now,
         }
     }
 }
 
-/// Result of a fixed window Lua script execution.
+/// Result of a fixed window operation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct FixedWindowScriptResult {
-    accepted: bool,
-    bucket: u64,
+pub(crate) struct FixedWindowScriptResult {
+    pub(super) accepted: bool,
+    pub(super) bucket: u64,
+    pub(super) reset: u64,
+    pub(super) now: u64,
 }
 
 impl redis::FromRedisValue for FixedWindowScriptResult {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
-        let (accepted, bucket): (bool, u64) = redis::FromRedisValue::from_redis_value(v)?;
-        Ok(Self { accepted, bucket })
+        let (accepted, bucket, reset, now): (bool, u64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            bucket,
+            reset,
+            now,
+        })
     }
 }
@@ -0,0 +1,463 @@
+use std::sync::OnceLock;
+
+use crate::{
+    error::{Error, Result},
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    migration::MigrationPolicy,
+    Mode,
+};
+
+/// Tolerance of floating point errors, as a fraction of a token.
+const EPSILON: f64 = 1e-6;
+
+/// [Generic cell rate algorithm](https://en.wikipedia.org/wiki/Generic_cell_rate_algorithm)
+/// paces traffic at a constant rate of `capacity` tokens per `period`, while allowing bursts
+/// of up to `capacity` tokens. It behaves like a [`TokenBucket`](super::TokenBucket) that
+/// refills continuously, but only stores a single theoretical arrival time per resource.
+///
+/// When the request is throttled, [`Quota::reset`] is the earliest time at which it would
+/// be allowed. Otherwise, it is the time at which the full capacity is available again.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gcra<K = SystemClock> {
+    capacity: u64,
+    period: Interval,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    migration_policy: MigrationPolicy,
+    clock: K,
+}
+
+impl Gcra {
+    /// Creates a new [`Gcra`] with the given capacity and period.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if `capacity` is zero.
+    pub fn new(capacity: u64, period: Interval) -> Result<Self> {
+        if capacity == 0 {
+            Err(Error::InvalidRule(
+                "Capacity must be greater than zero".into(),
+            ))
+        } else {
+            Ok(Self {
+                capacity,
+                period,
+                time_source: TimeSource::default(),
+                keyspace: Keyspace::default(),
+                migration_policy: MigrationPolicy::default(),
+                clock: SystemClock,
+            })
+        }
+    }
+}
+
+impl<K> Gcra<K> {
+    /// Returns the capacity of the GCRA rule.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the period of the GCRA rule.
+    pub fn period(&self) -> Interval {
+        self.period
+    }
+
+    /// Loads the script of the GCRA rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<GcraOperation>(con)
+    }
+
+    /// Loads the script of the GCRA rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<GcraOperation, C>(con).await
+    }
+
+    /// Returns where the GCRA rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the GCRA rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the GCRA rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the GCRA rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns what the GCRA rule does with the state of a resource written under other
+    /// parameters.
+    pub fn migration_policy(&self) -> MigrationPolicy {
+        self.migration_policy
+    }
+
+    /// Sets what the GCRA rule does with the state of a resource written under other
+    /// parameters, such as after a rollout changing them.
+    ///
+    /// Defaults to [`MigrationPolicy::Clamp`].
+    pub fn with_migration_policy(mut self, migration_policy: MigrationPolicy) -> Self {
+        self.migration_policy = migration_policy;
+        self
+    }
+
+    /// Returns the clock the GCRA rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the GCRA rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> Gcra<C> {
+        Gcra {
+            capacity: self.capacity,
+            period: self.period,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            migration_policy: self.migration_policy,
+            clock,
+        }
+    }
+}
+
+impl<K: Clock> Gcra<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> GcraOperation {
+        GcraOperation {
+            keys: [self.keyspace.key("gcra", resource)],
+            now: self.time_source.now(&self.clock),
+            capacity: self.capacity,
+            period: self.period.as_millis(),
+            tokens,
+            mode: Mode::Acquire,
+            migration_policy: self.migration_policy,
+        }
+    }
+
+    fn refund_operation(&self, resource: &str, tokens: u64, acquired_at: u64) -> GcraOperation {
+        GcraOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn check_operation(&self, resource: &str) -> GcraOperation {
+        GcraOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)
+        }
+    }
+
+    fn acquire_result(&self, result: GcraScriptResult) -> AcquireResult {
+        let quota = Quota::new(self.capacity, result.remaining, result.reset);
+
+        if result.accepted {
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Throttled(quota)
+        }
+    }
+}
+
+impl<K: Clock> RateLimiter for Gcra<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens))?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::RateLimiter for Gcra<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)).await?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at))
+            .await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+/// Acquisition or refund of tokens by moving the theoretical arrival time of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct GcraOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    capacity: u64,
+    period: u64,
+    tokens: u64,
+    mode: Mode,
+    migration_policy: MigrationPolicy,
+}
+
+impl GcraOperation {
+    /// Returns the parameters of the rule, as recorded in its entry.
+    fn parameters(&self) -> [f64; 2] {
+        [self.capacity as f64, self.period as f64]
+    }
+}
+
+impl sealed::Script for GcraOperation {
+    const FUNCTION: &'static str = "arret_gcra";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Migration.lua"),
+        include_str!("../res/Gcra.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity)
+            .arg(self.period)
+            .arg(self.tokens)
+            .arg(self.migration_policy.as_str());
+        self.mode.args(args);
+    }
+}
+
+impl Operation for GcraOperation {
+    type Output = GcraScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let now = now as f64;
+        let emission_interval = self.period as f64 / self.capacity as f64;
+        let burst_tolerance = self.period as f64;
+        let ceil = |time: f64| (time - EPSILON * emission_interval).ceil();
+
+        // The entry holds the theoretical arrival time and the parameters of the rule it
+        // was written under
+        let tat = match &entries[0] {
+            Some(entry) if entry.values[1..] == self.parameters() => entry.values[0].max(now),
+            Some(entry) => {
+                // Migrate the tokens used, which are paid back over the emission interval
+                let capacity = entry.values[1];
+                let used = (entry.values[0].max(now) - now) / (entry.values[2] / capacity);
+                let used = self
+                    .migration_policy
+                    .migrate(used, capacity, self.capacity as f64)
+                    .unwrap_or(0.0);
+                now + used * emission_interval
+            }
+            None => now,
+        };
+
+        let new_tat = if matches!(self.mode, Mode::Refund { .. }) {
+            now.max(tat - self.tokens as f64 * emission_interval)
+        } else {
+            tat + self.tokens as f64 * emission_interval
+        };
+        let allow_at = new_tat - burst_tolerance;
+
+        if !matches!(self.mode, Mode::Refund { .. })
+            && (allow_at - now) / emission_interval > EPSILON
+        {
+            let remaining = ((burst_tolerance - (tat - now)) / emission_interval + EPSILON).floor();
+            return GcraScriptResult {
+                accepted: false,
+                remaining: remaining as u64,
+                reset: ceil(allow_at) as u64,
+                now: now as u64,
+            };
+        }
+
+        let remaining = ((burst_tolerance - (new_tat - now)) / emission_interval + EPSILON).floor();
+        if self.tokens > 0 {
+            entries[0] = (new_tat > now).then(|| {
+                let expires_at = now as u64 + ceil(new_tat - now) as u64;
+                let mut values = vec![new_tat];
+                values.extend(self.parameters());
+                Entry::new(values, expires_at)
+            });
+        }
+
+        GcraScriptResult {
+            accepted: true,
+            remaining: remaining as u64,
+            reset: ceil(new_tat) as u64,
+            now: now as u64,
+        }
+    }
+}
+
+/// Result of a GCRA operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GcraScriptResult {
+    accepted: bool,
+    remaining: u64,
+    reset: u64,
+    now: u64,
+}
+
+impl redis::FromRedisValue for GcraScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, remaining, reset, now): (bool, u64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            remaining,
+            reset,
+            now,
+        })
+    }
+}
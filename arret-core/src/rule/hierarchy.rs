@@ -0,0 +1,373 @@
+use crate::{
+    error::{Error, Result},
+    rate_limiter::{AcquireResult, Quota},
+    store::{self, Keyspace, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    composite::{CompositeOperation, CompositeScriptResult},
+    token_bucket::TokenBucket,
+    Mode,
+};
+
+/// A rule made of nested [`TokenBucket`] levels, such as a tenant, its users and their
+/// API keys, which only admits a request if every level on its path does.
+///
+/// A request is made for a path of resources, one for each level from the first one,
+/// such as `["acme", "alice", "key-1"]`. Tokens are acquired from the bucket of every
+/// resource on the path at once, or from none of them, so a level can only grant what
+/// its parents have left. A path may stop before the last level, to acquire tokens for a
+/// tenant as a whole.
+///
+/// A level is either a token bucket of its own, or a share of its parent, with the
+/// capacity and refill amount of the parent scaled down. The shares of the children of a
+/// resource may add up to more than its bucket, which then caps them together.
+///
+/// The keys of a path are tagged with its first resource, so that the whole path is
+/// served by a single node of Redis Cluster. The time source, keyspace and clock of the
+/// hierarchy apply to every level, so the levels may not set theirs, nor an estimate
+/// timeout or a max delay.
+///
+/// ```rust
+/// use arret_core::{
+///     interval::Interval,
+///     rate_limiter::AcquireResult,
+///     rule::{Hierarchy, HierarchyLevel, TokenBucket},
+///     store::MemoryStore,
+/// };
+///
+/// // Every user of a tenant may use up to 60% of its tokens
+/// let hierarchy = Hierarchy::new([
+///     TokenBucket::new(100, Interval::from_secs(60).unwrap(), 100).unwrap().into(),
+///     HierarchyLevel::Share(60),
+/// ])
+/// .unwrap();
+///
+/// let mut store = MemoryStore::new();
+///
+/// let res = hierarchy.acquire(&["acme", "alice"], 60, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Ok(quota) if quota.remaining == 0));
+///
+/// // Bob has a share of his own, but the tenant only has 40 tokens left
+/// let res = hierarchy.acquire(&["acme", "bob"], 60, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Throttled(quota) if quota.remaining == 40));
+///
+/// let res = hierarchy.acquire(&["acme", "bob"], 40, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Ok(_)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hierarchy<K = SystemClock> {
+    levels: Vec<TokenBucket>,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    clock: K,
+}
+
+/// A level of a [`Hierarchy`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HierarchyLevel {
+    /// A token bucket of its own.
+    TokenBucket(TokenBucket),
+
+    /// A share of the bucket of the parent, in percent.
+    Share(u64),
+}
+
+impl From<TokenBucket> for HierarchyLevel {
+    fn from(token_bucket: TokenBucket) -> Self {
+        Self::TokenBucket(token_bucket)
+    }
+}
+
+impl Hierarchy {
+    /// Creates a new [`Hierarchy`] of the given levels, from the outermost one.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if there is no level, if the first level is a share, if
+    ///   a share is not between 1 and 100 percent or leaves no refill amount, or if a level
+    ///   sets its own time source, keyspace, estimate timeout or max delay.
+    pub fn new(levels: impl IntoIterator<Item = HierarchyLevel>) -> Result<Self> {
+        let mut buckets: Vec<TokenBucket> = Vec::new();
+        for level in levels {
+            let bucket = match (level, buckets.last()) {
+                (HierarchyLevel::TokenBucket(token_bucket), _) => {
+                    check_unused_settings(&token_bucket, buckets.len())?;
+                    token_bucket
+                }
+                (HierarchyLevel::Share(_), None) => {
+                    return Err(Error::InvalidRule(
+                        "First level of a hierarchy must not be a share".into(),
+                    ))
+                }
+                (HierarchyLevel::Share(share), Some(parent)) => share_of(parent, share)?,
+            };
+            buckets.push(bucket);
+        }
+
+        if buckets.is_empty() {
+            Err(Error::InvalidRule(
+                "Hierarchy must have at least one level".into(),
+            ))
+        } else {
+            Ok(Self {
+                levels: buckets,
+                time_source: TimeSource::default(),
+                keyspace: Keyspace::default(),
+                clock: SystemClock,
+            })
+        }
+    }
+}
+
+/// Fails if the bucket of the level at `index` sets any of the settings which the
+/// hierarchy does not use, rather than ignoring them silently.
+fn check_unused_settings(token_bucket: &TokenBucket, index: usize) -> Result<()> {
+    let defaults = TokenBucket::new(
+        token_bucket.capacity(),
+        token_bucket.refill_interval(),
+        token_bucket.refill_amount(),
+    )?
+    .with_migration_policy(token_bucket.migration_policy());
+
+    if token_bucket == &defaults {
+        Ok(())
+    } else {
+        Err(Error::InvalidRule(format!(
+            "Level {index} must not set its time source, keyspace, estimate timeout nor max \
+             delay, which are not used by the hierarchy"
+        )))
+    }
+}
+
+/// Returns the bucket holding `share` percent of the bucket of `parent`.
+fn share_of(parent: &TokenBucket, share: u64) -> Result<TokenBucket> {
+    if !(1..=100).contains(&share) {
+        return Err(Error::InvalidRule(format!(
+            "Share must be between 1 and 100 percent, got {share}"
+        )));
+    }
+
+    let scale = |amount: u64| (u128::from(amount) * u128::from(share) / 100) as u64;
+    let refill_amount = scale(parent.refill_amount());
+    if refill_amount == 0 {
+        return Err(Error::InvalidRule(format!(
+            "Share of {share} percent leaves no refill amount"
+        )));
+    }
+
+    Ok(TokenBucket::new(
+        scale(parent.capacity()),
+        parent.refill_interval(),
+        refill_amount,
+    )?
+    .with_migration_policy(parent.migration_policy()))
+}
+
+impl<K> Hierarchy<K> {
+    /// Returns the token buckets of the levels of the hierarchy, with the shares resolved.
+    pub fn levels(&self) -> &[TokenBucket] {
+        &self.levels
+    }
+
+    /// Loads the script of the hierarchy into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<CompositeOperation>(con)
+    }
+
+    /// Loads the script of the hierarchy into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<CompositeOperation, C>(con).await
+    }
+
+    /// Returns where the hierarchy reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the hierarchy reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the hierarchy are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the hierarchy are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns the clock the hierarchy reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the hierarchy reads the current time from, when its time source
+    /// is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> Hierarchy<C> {
+        Hierarchy {
+            levels: self.levels,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            clock,
+        }
+    }
+}
+
+impl<K: Clock> Hierarchy<K> {
+    fn operation(&self, path: &[&str], tokens: u64) -> Result<CompositeOperation> {
+        if path.is_empty() || path.len() > self.levels.len() {
+            return Err(Error::InvalidPath(format!(
+                "Path must have between 1 and {} resources, got {}",
+                self.levels.len(),
+                path.len()
+            )));
+        }
+
+        let now = self.time_source.now(&self.clock);
+
+        // Each resource is keyed by its whole path, so that users of different tenants
+        // do not share their buckets
+        let operations = self
+            .levels
+            .iter()
+            .enumerate()
+            .take(path.len())
+            .map(|(index, level)| {
+                let key = self
+                    .keyspace
+                    .path_key("hierarchy:token_bucket", &path[..=index]);
+                level.operation_at(key, now, tokens)
+            })
+            .collect();
+
+        Ok(CompositeOperation::token_buckets(now, operations))
+    }
+
+    fn refund_operation(&self, path: &[&str], tokens: u64) -> Result<CompositeOperation> {
+        // Token buckets have no windows, so tokens are returned whenever they were acquired
+        let mode = Mode::Refund { acquired_at: 0 };
+        Ok(self.operation(path, tokens)?.with_mode(mode))
+    }
+
+    fn acquire_each_result(&self, result: CompositeScriptResult) -> (AcquireResult, Vec<Quota>) {
+        result.acquire_each_result(self.levels.iter().map(TokenBucket::capacity))
+    }
+
+    /// Try to acquire `tokens` for the resources on `path`, from the outermost one.
+    ///
+    /// The quota of the result is the one of the level which binds the request: the
+    /// throttling level which resets last, or the level with the fewest tokens left.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidPath`] if the path is empty or longer than the hierarchy.
+    pub fn acquire<S>(&self, path: &[&str], tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        self.acquire_each(path, tokens, store)
+            .map(|(result, _)| result)
+    }
+
+    /// Try to acquire `tokens` for the resources on `path`, and also returns the quota
+    /// of each resource, from the outermost one.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    pub fn acquire_each<S>(
+        &self,
+        path: &[&str],
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Vec<Quota>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(path, tokens)?)?;
+
+        Ok(self.acquire_each_result(result))
+    }
+
+    /// Returns unused `tokens` to every resource on `path`, and returns the quota of
+    /// the level binding the request.
+    ///
+    /// Requires a [`Store`], such as a Redis connection, to be passed in.
+    pub fn refund<S>(&self, path: &[&str], tokens: u64, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(path, tokens)?)?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+
+    /// Try to acquire `tokens` for the resources on `path` asynchronously.
+    ///
+    /// Requires a [`Store`](aio::Store), such as a Redis connection, to be passed in.
+    #[cfg(feature = "aio")]
+    pub async fn acquire_async<S>(
+        &self,
+        path: &[&str],
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        self.acquire_each_async(path, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    /// Try to acquire `tokens` for the resources on `path` asynchronously, and also
+    /// returns the quota of each resource, from the outermost one.
+    ///
+    /// Requires a [`Store`](aio::Store), such as a Redis connection, to be passed in.
+    #[cfg(feature = "aio")]
+    pub async fn acquire_each_async<S>(
+        &self,
+        path: &[&str],
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Vec<Quota>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(path, tokens)?).await?;
+
+        Ok(self.acquire_each_result(result))
+    }
+
+    /// Returns unused `tokens` to every resource on `path` asynchronously.
+    ///
+    /// Requires a [`Store`](aio::Store), such as a Redis connection, to be passed in.
+    #[cfg(feature = "aio")]
+    pub async fn refund_async<S>(&self, path: &[&str], tokens: u64, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.refund_operation(path, tokens)?).await?;
+
+        Ok(self.acquire_each_result(result).0.quota())
+    }
+}
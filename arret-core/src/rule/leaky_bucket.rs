@@ -0,0 +1,497 @@
+use std::{sync::OnceLock, time::Duration};
+
+use crate::{
+    error::{Error, Result},
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    migration::MigrationPolicy,
+    Mode,
+};
+
+/// Tolerance of floating point errors, as a fraction of a token.
+const EPSILON: f64 = 1e-6;
+
+/// [Leaky bucket](https://en.wikipedia.org/wiki/Leaky_bucket) algorithm shapes traffic
+/// instead of rejecting it. Requests are queued in the bucket, which drains at a constant
+/// rate, and the caller is told how long to wait until the request may proceed.
+///
+/// Requests are only throttled when the queue would hold more than `capacity` tokens. When
+/// a request has to wait, [`AcquireResult::Delayed`] is returned with the delay.
+///
+/// [`Quota::reset`] is the time at which the queue drains, or when the request is
+/// throttled, the earliest time at which it would fit in the queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeakyBucket<K = SystemClock> {
+    capacity: u64,
+    drain_interval: Interval,
+    drain_amount: u64,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    migration_policy: MigrationPolicy,
+    clock: K,
+}
+
+impl LeakyBucket {
+    /// Creates a new [`LeakyBucket`] with the given capacity, drain interval and drain amount.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if `drain_amount` is zero.
+    pub fn new(capacity: u64, drain_interval: Interval, drain_amount: u64) -> Result<Self> {
+        if drain_amount == 0 {
+            Err(Error::InvalidRule(
+                "Drain amount must be greater than zero".into(),
+            ))
+        } else {
+            Ok(Self {
+                capacity,
+                drain_interval,
+                drain_amount,
+                time_source: TimeSource::default(),
+                keyspace: Keyspace::default(),
+                migration_policy: MigrationPolicy::default(),
+                clock: SystemClock,
+            })
+        }
+    }
+}
+
+impl<K> LeakyBucket<K> {
+    /// Returns the capacity of the leaky bucket rule.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the drain interval of the leaky bucket rule.
+    pub fn drain_interval(&self) -> Interval {
+        self.drain_interval
+    }
+
+    /// Returns the drain amount of the leaky bucket rule.
+    pub fn drain_amount(&self) -> u64 {
+        self.drain_amount
+    }
+
+    /// Loads the script of the leaky bucket rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<LeakyBucketOperation>(con)
+    }
+
+    /// Loads the script of the leaky bucket rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<LeakyBucketOperation, C>(con).await
+    }
+
+    /// Returns where the leaky bucket rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the leaky bucket rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the leaky bucket rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the leaky bucket rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns what the leaky bucket rule does with the state of a resource written under other
+    /// parameters.
+    pub fn migration_policy(&self) -> MigrationPolicy {
+        self.migration_policy
+    }
+
+    /// Sets what the leaky bucket rule does with the state of a resource written under other
+    /// parameters, such as after a rollout changing them.
+    ///
+    /// Defaults to [`MigrationPolicy::Clamp`].
+    pub fn with_migration_policy(mut self, migration_policy: MigrationPolicy) -> Self {
+        self.migration_policy = migration_policy;
+        self
+    }
+
+    /// Returns the clock the leaky bucket rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the leaky bucket rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> LeakyBucket<C> {
+        LeakyBucket {
+            capacity: self.capacity,
+            drain_interval: self.drain_interval,
+            drain_amount: self.drain_amount,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            migration_policy: self.migration_policy,
+            clock,
+        }
+    }
+}
+
+impl<K: Clock> LeakyBucket<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> LeakyBucketOperation {
+        LeakyBucketOperation {
+            keys: [self.keyspace.key("leaky_bucket", resource)],
+            now: self.time_source.now(&self.clock),
+            capacity: self.capacity,
+            drain_interval: self.drain_interval.as_millis(),
+            drain_amount: self.drain_amount,
+            tokens,
+            mode: Mode::Acquire,
+            migration_policy: self.migration_policy,
+        }
+    }
+
+    fn refund_operation(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+    ) -> LeakyBucketOperation {
+        LeakyBucketOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn check_operation(&self, resource: &str) -> LeakyBucketOperation {
+        LeakyBucketOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)
+        }
+    }
+
+    fn acquire_result(&self, result: LeakyBucketScriptResult) -> AcquireResult {
+        let quota = Quota::new(self.capacity, result.remaining, result.reset);
+
+        if !result.accepted {
+            AcquireResult::Throttled(quota)
+        } else if result.delay == 0 {
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Delayed(quota, Duration::from_millis(result.delay))
+        }
+    }
+}
+
+impl<K: Clock> RateLimiter for LeakyBucket<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens))?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::RateLimiter for LeakyBucket<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)).await?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at))
+            .await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+/// Enqueueing or refund of tokens into the bucket of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct LeakyBucketOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    capacity: u64,
+    drain_interval: u64,
+    drain_amount: u64,
+    tokens: u64,
+    mode: Mode,
+    migration_policy: MigrationPolicy,
+}
+
+impl LeakyBucketOperation {
+    /// Returns the parameters of the bucket, as recorded in its entry.
+    fn parameters(&self) -> [f64; 3] {
+        [
+            self.capacity as f64,
+            self.drain_interval as f64,
+            self.drain_amount as f64,
+        ]
+    }
+}
+
+impl sealed::Script for LeakyBucketOperation {
+    const FUNCTION: &'static str = "arret_leaky_bucket";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Migration.lua"),
+        include_str!("../res/LeakyBucket.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity)
+            .arg(self.drain_interval)
+            .arg(self.drain_amount)
+            .arg(self.tokens)
+            .arg(self.migration_policy.as_str());
+        self.mode.args(args);
+    }
+}
+
+impl Operation for LeakyBucketOperation {
+    type Output = LeakyBucketScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let now = now as f64;
+        let capacity = self.capacity as f64;
+        let tokens = self.tokens as f64;
+        let drain_time = self.drain_interval as f64 / self.drain_amount as f64;
+        let ceil = |time: f64| (time - EPSILON * drain_time).ceil();
+
+        // The entry holds the time at which the queue of the bucket drains and the
+        // parameters of the bucket it was written under
+        let tail = match &entries[0] {
+            Some(entry) if entry.values[1..] == self.parameters() => entry.values[0].max(now),
+            Some(entry) => {
+                // Migrate the tokens queued, which then drain at the new rate
+                let queued = (entry.values[0].max(now) - now) / (entry.values[2] / entry.values[3]);
+                let queued = self
+                    .migration_policy
+                    .migrate(queued, entry.values[1], capacity)
+                    .unwrap_or(0.0);
+                now + queued * drain_time
+            }
+            None => now,
+        };
+
+        let queued = (tail - now) / drain_time;
+
+        let (new_tail, remaining) = if matches!(self.mode, Mode::Refund { .. }) {
+            let new_tail = now.max(tail - tokens * drain_time);
+            (
+                new_tail,
+                (capacity - (new_tail - now) / drain_time + EPSILON).floor(),
+            )
+        } else if queued + tokens > capacity + EPSILON {
+            let remaining = (capacity - queued + EPSILON).floor().max(0.0);
+            let retry_at = if tokens <= capacity {
+                tail - (capacity - tokens) * drain_time
+            } else {
+                tail
+            };
+            return LeakyBucketScriptResult {
+                accepted: false,
+                remaining: remaining as u64,
+                reset: ceil(retry_at) as u64,
+                delay: 0,
+                now: now as u64,
+            };
+        } else {
+            let new_tail = tail + tokens * drain_time;
+            (new_tail, (capacity - queued - tokens + EPSILON).floor())
+        };
+
+        if self.tokens > 0 {
+            entries[0] = (new_tail > now).then(|| {
+                let expires_at = now as u64 + ceil(new_tail - now) as u64;
+                let mut values = vec![new_tail];
+                values.extend(self.parameters());
+                Entry::new(values, expires_at)
+            });
+        }
+
+        LeakyBucketScriptResult {
+            accepted: true,
+            remaining: remaining as u64,
+            reset: ceil(new_tail) as u64,
+            delay: ceil(tail - now) as u64,
+            now: now as u64,
+        }
+    }
+}
+
+/// Result of a leaky bucket operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LeakyBucketScriptResult {
+    accepted: bool,
+    remaining: u64,
+    reset: u64,
+    delay: u64,
+    now: u64,
+}
+
+impl redis::FromRedisValue for LeakyBucketScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, remaining, reset, delay, now): (bool, u64, u64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            remaining,
+            reset,
+            delay,
+            now,
+        })
+    }
+}
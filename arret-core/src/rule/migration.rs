@@ -0,0 +1,49 @@
+/// What a rule does with the state of a resource written under other parameters, such as
+/// after its capacity or refill rate was changed.
+///
+/// The state of [`TokenBucket`](super::TokenBucket), [`FixedWindow`](super::FixedWindow),
+/// [`Gcra`](super::Gcra), [`LeakyBucket`](super::LeakyBucket) and
+/// [`SlidingWindowCounter`](super::SlidingWindowCounter) records the parameters it was
+/// written under, so that the change is detected on the next request of each resource.
+/// The policy then applies to the tokens left in the state: the remaining tokens of a
+/// bucket or a window, or the tokens used or queued by the other rules.
+///
+/// [`SlidingWindowLog`](super::SlidingWindowLog) and [`Concurrency`](super::Concurrency)
+/// record individual grants and leases, which are always checked against the parameters
+/// in force.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MigrationPolicy {
+    /// Discard the state, as if the resource had never been requested.
+    Reset,
+
+    /// Keep the tokens of the state, up to the new capacity.
+    #[default]
+    Clamp,
+
+    /// Keep the same fraction of the capacity, rounded down.
+    Proportional,
+}
+
+impl MigrationPolicy {
+    /// Returns the name of the policy, as passed to the scripts.
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Reset => "reset",
+            Self::Clamp => "clamp",
+            Self::Proportional => "proportional",
+        }
+    }
+
+    /// Returns the tokens left in a state written under `old_capacity`, or `None` if the
+    /// state is discarded.
+    pub(crate) fn migrate(&self, tokens: f64, old_capacity: f64, capacity: f64) -> Option<f64> {
+        match self {
+            Self::Reset => None,
+            Self::Clamp => Some(tokens.min(capacity)),
+            Self::Proportional if old_capacity > 0.0 => {
+                Some((tokens * capacity / old_capacity).floor())
+            }
+            Self::Proportional => Some(0.0),
+        }
+    }
+}
@@ -1,5 +1,46 @@
-mod clock;
+#[cfg(feature = "calendar")]
+pub mod calendar_window;
+pub mod clock;
+pub mod composite;
+pub mod concurrency;
 pub mod fixed_window;
+pub mod gcra;
+pub mod hierarchy;
+pub mod leaky_bucket;
+pub mod migration;
+mod mode;
+pub mod sliding_window_counter;
+pub mod sliding_window_log;
 pub mod token_bucket;
 
-pub use self::{fixed_window::FixedWindow, token_bucket::TokenBucket};
+#[cfg(feature = "calendar")]
+pub use self::calendar_window::{CalendarPeriod, CalendarWindow};
+
+pub use self::{
+    clock::{Clock, SystemClock, TimeSource},
+    composite::{Composite, CompositeRule},
+    concurrency::Concurrency,
+    fixed_window::FixedWindow,
+    gcra::Gcra,
+    hierarchy::{Hierarchy, HierarchyLevel},
+    leaky_bucket::LeakyBucket,
+    migration::MigrationPolicy,
+    sliding_window_counter::SlidingWindowCounter,
+    sliding_window_log::SlidingWindowLog,
+    token_bucket::TokenBucket,
+};
+
+pub(crate) use self::mode::Mode;
+
+/// The name of the function running the script of calendar windows.
+///
+/// The script does not depend on the `calendar` feature, so the library registers it
+/// whether or not the feature is enabled, and its version does not depend on it.
+pub(crate) const CALENDAR_WINDOW_FUNCTION: &str = "arret_calendar_window";
+
+/// The source of the script of calendar windows.
+pub(crate) const CALENDAR_WINDOW_SOURCE: &str = concat!(
+    include_str!("../res/Clock.lua"),
+    include_str!("../res/Migration.lua"),
+    include_str!("../res/CalendarWindow.lua")
+);
@@ -0,0 +1,63 @@
+use crate::store::sealed;
+
+/// What an operation does with the tokens it is given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    /// Acquire the tokens, unless the rule throttles them.
+    Acquire,
+
+    /// Tell whether the tokens would be acquired, leaving them to the rule.
+    Check,
+
+    /// Return the tokens acquired at `acquired_at` to the rule, up to its capacity, unless
+    /// they were acquired in a window which has ended.
+    Refund { acquired_at: u64 },
+
+    /// Commit an estimate of `estimated` tokens with the actual number of tokens, unless
+    /// it has expired.
+    Commit {
+        estimated: u64,
+        acquired_at: u64,
+        expires_at: u64,
+    },
+
+    /// Reserve the tokens ahead of their refill, unless they would be refilled after
+    /// `max_delay` milliseconds.
+    Reserve { max_delay: u64 },
+
+    /// Cancel a reservation of the tokens, unless the instant to proceed has passed.
+    Cancel { proceed_at: u64 },
+}
+
+impl Mode {
+    /// Appends the name of the mode and its parameters to the arguments of a script.
+    pub(crate) fn args<A: sealed::Args>(&self, args: &mut A) {
+        match self {
+            Self::Acquire => {
+                args.arg("acquire");
+            }
+            Self::Check => {
+                args.arg("check");
+            }
+            Self::Refund { acquired_at } => {
+                args.arg("refund").arg(acquired_at);
+            }
+            Self::Commit {
+                estimated,
+                acquired_at,
+                expires_at,
+            } => {
+                args.arg("commit")
+                    .arg(estimated)
+                    .arg(acquired_at)
+                    .arg(expires_at);
+            }
+            Self::Reserve { max_delay } => {
+                args.arg("reserve").arg(max_delay);
+            }
+            Self::Cancel { proceed_at } => {
+                args.arg("cancel").arg(proceed_at);
+            }
+        }
+    }
+}
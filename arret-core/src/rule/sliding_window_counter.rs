@@ -0,0 +1,532 @@
+use std::sync::OnceLock;
+
+use crate::{
+    error::{Error, Result},
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    migration::MigrationPolicy,
+    Mode,
+};
+
+/// Sliding window counter approximates a sliding window by keeping the counters of the
+/// current and the previous fixed window. The previous counter is weighted by how much of
+/// the previous window still overlaps the rolling window ending at the current time.
+///
+/// It is nearly as accurate as [`SlidingWindowLog`](super::SlidingWindowLog), while only
+/// storing two counters per resource.
+///
+/// When the request is throttled, [`Quota::reset`] is the earliest time at which it would
+/// be allowed if no other requests were made. Otherwise, it is the time at which every
+/// request made so far has slid out of the window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlidingWindowCounter<K = SystemClock> {
+    capacity: u64,
+    window: Interval,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    migration_policy: MigrationPolicy,
+    clock: K,
+}
+
+/// The largest product of the capacity and the window of a sliding window counter, in
+/// milliseconds, under which counters are weighted exactly by the numbers of Lua, which
+/// are doubles.
+const MAX_CAPACITY_WINDOW: u64 = 1 << 53;
+
+impl SlidingWindowCounter {
+    /// Creates a new [`SlidingWindowCounter`] with the given capacity and window.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidRule`] if `capacity` times the `window` in milliseconds is
+    ///   greater than 2^53.
+    pub fn new(capacity: u64, window: Interval) -> Result<Self> {
+        match capacity.checked_mul(window.as_millis()) {
+            Some(product) if product <= MAX_CAPACITY_WINDOW => Ok(Self {
+                capacity,
+                window,
+                time_source: TimeSource::default(),
+                keyspace: Keyspace::default(),
+                migration_policy: MigrationPolicy::default(),
+                clock: SystemClock,
+            }),
+            _ => Err(Error::InvalidRule(
+                "Capacity is too large for the window".into(),
+            )),
+        }
+    }
+}
+
+impl<K> SlidingWindowCounter<K> {
+    /// Returns the capacity of the sliding window counter rule.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the window of the sliding window counter rule.
+    pub fn window(&self) -> Interval {
+        self.window
+    }
+
+    /// Loads the script of the sliding window counter rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<SlidingWindowCounterOperation>(con)
+    }
+
+    /// Loads the script of the sliding window counter rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<SlidingWindowCounterOperation, C>(con).await
+    }
+
+    /// Returns where the sliding window counter rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the sliding window counter rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the sliding window counter rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the sliding window counter rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns what the sliding window counter rule does with the state of a resource written under other
+    /// parameters.
+    pub fn migration_policy(&self) -> MigrationPolicy {
+        self.migration_policy
+    }
+
+    /// Sets what the sliding window counter rule does with the state of a resource written under other
+    /// parameters, such as after a rollout changing them.
+    ///
+    /// Defaults to [`MigrationPolicy::Clamp`].
+    pub fn with_migration_policy(mut self, migration_policy: MigrationPolicy) -> Self {
+        self.migration_policy = migration_policy;
+        self
+    }
+
+    /// Returns the clock the sliding window counter rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the sliding window counter rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> SlidingWindowCounter<C> {
+        SlidingWindowCounter {
+            capacity: self.capacity,
+            window: self.window,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            migration_policy: self.migration_policy,
+            clock,
+        }
+    }
+
+    /// Returns the amount of resource counted in the rolling window, `elapsed` into the
+    /// current window.
+    ///
+    /// Counters never exceed the capacity, so weighting them cannot overflow once the
+    /// capacity is bounded by [`new`](SlidingWindowCounter::new).
+    fn used(&self, elapsed: u64, current: u64, previous: u64) -> u64 {
+        let window = self.window.as_millis();
+        current + previous * (window - elapsed) / window
+    }
+
+    /// Returns the elapsed time into a window at which `counter`, weighted as the
+    /// counter of the previous window, no longer exceeds `allowed`.
+    fn elapsed_until(&self, counter: u64, allowed: u64) -> u64 {
+        let window = self.window.as_millis();
+        window.saturating_sub(((allowed + 1) * window - 1) / counter)
+    }
+
+    fn quota(&self, result: SlidingWindowCounterScriptResult, tokens: u64) -> Quota {
+        let window = self.window.as_millis();
+        let now = result.now;
+        let start = now / window * window;
+        let elapsed = now - start;
+
+        let remaining =
+            self.capacity
+                .saturating_sub(self.used(elapsed, result.current, result.previous));
+
+        let reset = if result.accepted || tokens > self.capacity {
+            if result.current > 0 {
+                start + 2 * window
+            } else {
+                start + window
+            }
+        } else {
+            let allowed = self.capacity - tokens;
+            if result.current <= allowed {
+                start
+                    + self
+                        .elapsed_until(result.previous, allowed - result.current)
+                        .max(elapsed)
+            } else {
+                start + window + self.elapsed_until(result.current, allowed)
+            }
+        };
+
+        Quota::new(self.capacity, remaining, reset)
+    }
+}
+
+impl<K: Clock> SlidingWindowCounter<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> SlidingWindowCounterOperation {
+        SlidingWindowCounterOperation {
+            keys: [self.keyspace.key("sliding_window_counter", resource)],
+            now: self.time_source.now(&self.clock),
+            capacity: self.capacity,
+            window: self.window.as_millis(),
+            tokens,
+            mode: Mode::Acquire,
+            migration_policy: self.migration_policy,
+        }
+    }
+
+    fn refund_operation(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+    ) -> SlidingWindowCounterOperation {
+        SlidingWindowCounterOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn check_operation(&self, resource: &str) -> SlidingWindowCounterOperation {
+        SlidingWindowCounterOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)
+        }
+    }
+
+    fn acquire_result(
+        &self,
+        result: SlidingWindowCounterScriptResult,
+        tokens: u64,
+    ) -> AcquireResult {
+        let quota = self.quota(result, tokens);
+
+        if result.accepted {
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Throttled(quota)
+        }
+    }
+}
+
+impl<K: Clock> RateLimiter for SlidingWindowCounter<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens))?;
+
+        Ok((self.acquire_result(result, tokens), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at))?;
+
+        Ok(self.acquire_result(result, tokens).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .zip(acquisitions)
+            .map(|(result, &(_, tokens))| result.map(|result| self.acquire_result(result, tokens)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_result(result, 0).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::RateLimiter for SlidingWindowCounter<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)).await?;
+
+        Ok((self.acquire_result(result, tokens), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at))
+            .await?;
+
+        Ok(self.acquire_result(result, tokens).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .zip(acquisitions)
+            .map(|(result, &(_, tokens))| result.map(|result| self.acquire_result(result, tokens)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_result(result, 0).quota())
+    }
+}
+
+/// Acquisition or refund of tokens from the counters of the rolling window of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct SlidingWindowCounterOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    capacity: u64,
+    window: u64,
+    tokens: u64,
+    mode: Mode,
+    migration_policy: MigrationPolicy,
+}
+
+impl SlidingWindowCounterOperation {
+    /// Returns the parameters of the window, as recorded in its entry.
+    fn parameters(&self) -> [f64; 2] {
+        [self.capacity as f64, self.window as f64]
+    }
+}
+
+impl sealed::Script for SlidingWindowCounterOperation {
+    const FUNCTION: &'static str = "arret_sliding_window_counter";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Migration.lua"),
+        include_str!("../res/SlidingWindowCounter.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity)
+            .arg(self.window)
+            .arg(self.tokens)
+            .arg(self.migration_policy.as_str());
+        self.mode.args(args);
+    }
+}
+
+impl Operation for SlidingWindowCounterOperation {
+    type Output = SlidingWindowCounterScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        let window_id = now / self.window;
+        let elapsed = now - window_id * self.window;
+
+        // The entry holds the window id, the counters of that window and the window
+        // before, and the parameters of the window it was written under
+        let (current, previous) = match &entries[0] {
+            Some(entry) if entry.values[0] == window_id as f64 => {
+                (entry.values[1], entry.values[2])
+            }
+            Some(entry) if entry.values[0] + 1.0 == window_id as f64 => (0.0, entry.values[1]),
+            _ => (0.0, 0.0),
+        };
+
+        // Counters of windows of another length cannot be carried over, while counters
+        // written under another capacity are migrated
+        let (current, previous) = match &entries[0] {
+            Some(entry) if entry.values[3..] == self.parameters() => (current, previous),
+            Some(entry) if entry.values[4] == self.window as f64 => {
+                let migrate = |count| {
+                    self.migration_policy
+                        .migrate(count, entry.values[3], self.capacity as f64)
+                        .unwrap_or(0.0)
+                };
+                (migrate(current), migrate(previous))
+            }
+            Some(_) => (0.0, 0.0),
+            None => (current, previous),
+        };
+        let (current, previous) = (current as u64, previous as u64);
+
+        let used = current + previous * (self.window - elapsed) / self.window;
+
+        let (current, previous) = if let Mode::Refund { acquired_at } = self.mode {
+            // Tokens are taken back from the counter of the window they were acquired in
+            match acquired_at / self.window {
+                id if id == window_id => (current.saturating_sub(self.tokens), previous),
+                id if id + 1 == window_id => (current, previous.saturating_sub(self.tokens)),
+                _ => (current, previous),
+            }
+        } else if used.saturating_add(self.tokens) > self.capacity {
+            return SlidingWindowCounterScriptResult {
+                accepted: false,
+                current,
+                previous,
+                now,
+            };
+        } else {
+            (current + self.tokens, previous)
+        };
+
+        if self.tokens > 0 {
+            let mut values = vec![window_id as f64, current as f64, previous as f64];
+            values.extend(self.parameters());
+            entries[0] = Some(Entry::new(values, (window_id + 2) * self.window));
+        }
+
+        SlidingWindowCounterScriptResult {
+            accepted: true,
+            current,
+            previous,
+            now,
+        }
+    }
+}
+
+/// Result of a sliding window counter operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SlidingWindowCounterScriptResult {
+    accepted: bool,
+    current: u64,
+    previous: u64,
+    now: u64,
+}
+
+impl redis::FromRedisValue for SlidingWindowCounterScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, current, previous, now): (bool, u64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            current,
+            previous,
+            now,
+        })
+    }
+}
@@ -0,0 +1,444 @@
+use std::sync::OnceLock;
+
+use crate::{
+    error::Result,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    Mode,
+};
+
+/// Sliding window log is a precise algorithm for rate limiting. It records every request
+/// that was allowed, and only allows a limited amount of traffic within any window ending
+/// at the current time.
+///
+/// Unlike [`FixedWindow`](super::FixedWindow), it does not let twice the capacity through
+/// around window boundaries, at the cost of storing an entry per allowed request.
+///
+/// The tokens of the log are kept in a running total next to it, so that a request only
+/// reads the entries which have slid out of the window since the last one, and
+/// [`refund`](RateLimiter::refund) only the entries it takes tokens back from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlidingWindowLog<K = SystemClock> {
+    capacity: u64,
+    window: Interval,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    clock: K,
+}
+
+impl SlidingWindowLog {
+    /// Creates a new [`SlidingWindowLog`] with the given capacity and window.
+    pub fn new(capacity: u64, window: Interval) -> Result<Self> {
+        Ok(Self {
+            capacity,
+            window,
+            time_source: TimeSource::default(),
+            keyspace: Keyspace::default(),
+            clock: SystemClock,
+        })
+    }
+}
+
+impl<K> SlidingWindowLog<K> {
+    /// Returns the capacity of the sliding window log rule.
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Returns the window of the sliding window log rule.
+    pub fn window(&self) -> Interval {
+        self.window
+    }
+
+    /// Loads the script of the sliding window log rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<SlidingWindowLogOperation>(con)
+    }
+
+    /// Loads the script of the sliding window log rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<SlidingWindowLogOperation, C>(con).await
+    }
+
+    /// Returns where the sliding window log rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the sliding window log rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the sliding window log rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the sliding window log rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns the clock the sliding window log rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the sliding window log rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> SlidingWindowLog<C> {
+        SlidingWindowLog {
+            capacity: self.capacity,
+            window: self.window,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            clock,
+        }
+    }
+}
+
+impl<K: Clock> SlidingWindowLog<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> SlidingWindowLogOperation {
+        let key = self.keyspace.key("sliding_window_log", resource);
+        let total_key = format!("{key}:total");
+
+        SlidingWindowLogOperation {
+            keys: [key, total_key],
+            now: self.time_source.now(&self.clock),
+            capacity: self.capacity,
+            window: self.window.as_millis(),
+            tokens,
+            mode: Mode::Acquire,
+        }
+    }
+
+    fn refund_operation(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+    ) -> SlidingWindowLogOperation {
+        SlidingWindowLogOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn check_operation(&self, resource: &str) -> SlidingWindowLogOperation {
+        SlidingWindowLogOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)
+        }
+    }
+
+    fn acquire_result(&self, result: SlidingWindowLogScriptResult) -> AcquireResult {
+        let quota = Quota::new(self.capacity, result.remaining, result.reset);
+
+        if result.accepted {
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Throttled(quota)
+        }
+    }
+}
+
+impl<K: Clock> RateLimiter for SlidingWindowLog<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens))?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::RateLimiter for SlidingWindowLog<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)).await?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at))
+            .await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+/// Acquisition or refund of tokens from the log of grants of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct SlidingWindowLogOperation {
+    keys: [String; 2],
+    now: Option<u64>,
+    capacity: u64,
+    window: u64,
+    tokens: u64,
+    mode: Mode,
+}
+
+impl sealed::Script for SlidingWindowLogOperation {
+    const FUNCTION: &'static str = "arret_sliding_window_log";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/SlidingWindowLog.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity).arg(self.window).arg(self.tokens);
+        self.mode.args(args);
+    }
+}
+
+impl Operation for SlidingWindowLogOperation {
+    type Output = SlidingWindowLogScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        // The entry holds the time and the tokens of each grant still in the window
+        let (grants, expires_at) = match &entries[0] {
+            Some(entry) => (entry.values.as_slice(), entry.expires_at),
+            None => (&[][..], now),
+        };
+        let mut grants: Vec<(u64, u64)> = grants
+            .chunks(2)
+            .map(|grant| (grant[0] as u64, grant[1] as u64))
+            .filter(|&(granted_at, _)| granted_at + self.window > now)
+            .collect();
+
+        // The reset is computed from the oldest grant before any refund, as the script
+        // does, which a refund only removes along with every other grant
+        let mut oldest = grants.iter().map(|&(granted_at, _)| granted_at).min();
+
+        if matches!(self.mode, Mode::Refund { .. }) {
+            // Take the tokens back from the latest grants
+            let mut refunded = 0;
+            while refunded < self.tokens {
+                let Some(grant) = grants.last_mut() else {
+                    break;
+                };
+                let taken = grant.1.min(self.tokens - refunded);
+                grant.1 -= taken;
+                refunded += taken;
+                if grant.1 == 0 {
+                    grants.pop();
+                }
+            }
+            if grants.is_empty() {
+                oldest = None;
+            }
+        }
+
+        let used: u64 = grants.iter().map(|&(_, tokens)| tokens).sum();
+        let remaining = self.capacity.saturating_sub(used);
+        let reset = oldest.unwrap_or(now) + self.window;
+
+        let accepted = matches!(self.mode, Mode::Refund { .. }) || remaining >= self.tokens;
+        let mut expires_at = expires_at;
+        if accepted && self.mode == Mode::Acquire && self.tokens > 0 {
+            grants.push((now, self.tokens));
+            expires_at = now + self.window;
+        }
+
+        // Checks leave the log as it is, including the grants which have slid out of
+        // the window
+        if self.mode != Mode::Check {
+            entries[0] = (!grants.is_empty()).then(|| {
+                let values = grants
+                    .iter()
+                    .flat_map(|&(granted_at, tokens)| [granted_at as f64, tokens as f64])
+                    .collect();
+                Entry::new(values, expires_at)
+            });
+
+            // The total entry holds the tokens of the grants in the log
+            let total: u64 = grants.iter().map(|&(_, tokens)| tokens).sum();
+            entries[1] = (total > 0).then(|| Entry::new(vec![total as f64], expires_at));
+        }
+
+        SlidingWindowLogScriptResult {
+            accepted,
+            remaining: if accepted && self.mode == Mode::Acquire {
+                remaining - self.tokens
+            } else {
+                remaining
+            },
+            reset,
+            now,
+        }
+    }
+}
+
+/// Result of a sliding window log operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SlidingWindowLogScriptResult {
+    accepted: bool,
+    remaining: u64,
+    reset: u64,
+    now: u64,
+}
+
+impl redis::FromRedisValue for SlidingWindowLogScriptResult {
+    fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
+        let (accepted, remaining, reset, now): (bool, u64, u64, u64) =
+            redis::FromRedisValue::from_redis_value(v)?;
+        Ok(Self {
+            accepted,
+            remaining,
+            reset,
+            now,
+        })
+    }
+}
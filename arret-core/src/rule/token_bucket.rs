@@ -1,27 +1,40 @@
+use std::sync::OnceLock;
+
 use crate::{
     error::{Error, Result},
+    estimating_limiter::{Estimate, EstimateResult, EstimatingLimiter},
     interval::Interval,
     rate_limiter::{AcquireResult, Quota, RateLimiter},
+    reserving_limiter::{Reservation, ReservationResult, ReservingLimiter},
+    store::{self, sealed, Entry, Keyspace, Operation, Store},
 };
 
 #[cfg(feature = "aio")]
 use crate::aio;
 
-use super::clock;
+use super::{
+    clock::{Clock, SystemClock, TimeSource},
+    migration::MigrationPolicy,
+    Mode,
+};
 
 /// [Token bucket](https://en.wikipedia.org/wiki/Token_bucket) algorithm is a common
 /// algorithm for rate limiting. While it allows traffic to be passed at a constant rate,
 /// it also allows bursts of traffic to be passed over a short period of time.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct TokenBucket {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBucket<K = SystemClock> {
     capacity: u64,
     refill_interval: Interval,
     refill_amount: u64,
+    time_source: TimeSource,
+    keyspace: Keyspace,
+    migration_policy: MigrationPolicy,
+    estimate_timeout: Interval,
+    max_delay: Interval,
+    clock: K,
 }
 
 impl TokenBucket {
-    const REDIS_SCRIPT: &str = include_str!("../res/TokenBucket.lua");
-
     /// Creates a new [`TokenBucket`] with the given capacity, refill interval and refill amount.
     ///
     /// # Errors
@@ -36,10 +49,18 @@ impl TokenBucket {
                 capacity,
                 refill_interval,
                 refill_amount,
+                time_source: TimeSource::default(),
+                keyspace: Keyspace::default(),
+                migration_policy: MigrationPolicy::default(),
+                estimate_timeout: Interval::from_secs(60)?,
+                max_delay: Interval::from_secs(60)?,
+                clock: SystemClock,
             })
         }
     }
+}
 
+impl<K> TokenBucket<K> {
     /// Returns the capacity of the token bucket rule.
     pub fn capacity(&self) -> u64 {
         self.capacity
@@ -54,95 +75,691 @@ impl TokenBucket {
     pub fn refill_amount(&self) -> u64 {
         self.refill_amount
     }
+
+    /// Loads the script of the token bucket rule into the script cache of Redis, so that
+    /// requests need not upload it.
+    pub fn preload(&self, con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        store::preload::<TokenBucketOperation>(con)
+    }
+
+    /// Loads the script of the token bucket rule into the script cache of Redis asynchronously.
+    #[cfg(feature = "aio")]
+    pub async fn preload_async<C>(&self, con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        store::preload_async::<TokenBucketOperation, C>(con).await
+    }
+
+    /// Returns where the token bucket rule reads the current time from.
+    pub fn time_source(&self) -> TimeSource {
+        self.time_source
+    }
+
+    /// Sets where the token bucket rule reads the current time from.
+    ///
+    /// Defaults to [`TimeSource::Client`].
+    pub fn with_time_source(mut self, time_source: TimeSource) -> Self {
+        self.time_source = time_source;
+        self
+    }
+
+    /// Returns the keyspace the keys of the token bucket rule are built in.
+    pub fn keyspace(&self) -> &Keyspace {
+        &self.keyspace
+    }
+
+    /// Sets the keyspace the keys of the token bucket rule are built in.
+    ///
+    /// Defaults to [`Keyspace::new`], which has no namespace.
+    pub fn with_keyspace(mut self, keyspace: Keyspace) -> Self {
+        self.keyspace = keyspace;
+        self
+    }
+
+    /// Returns what the token bucket rule does with the state of a resource written under other
+    /// parameters.
+    pub fn migration_policy(&self) -> MigrationPolicy {
+        self.migration_policy
+    }
+
+    /// Sets what the token bucket rule does with the state of a resource written under other
+    /// parameters, such as after a rollout changing them.
+    ///
+    /// Defaults to [`MigrationPolicy::Clamp`].
+    pub fn with_migration_policy(mut self, migration_policy: MigrationPolicy) -> Self {
+        self.migration_policy = migration_policy;
+        self
+    }
+
+    /// Returns how long estimates of the token bucket rule may be committed for.
+    pub fn estimate_timeout(&self) -> Interval {
+        self.estimate_timeout
+    }
+
+    /// Sets how long estimates of the token bucket rule may be committed for, after which
+    /// they stay charged as estimated.
+    ///
+    /// Defaults to one minute.
+    pub fn with_estimate_timeout(mut self, estimate_timeout: Interval) -> Self {
+        self.estimate_timeout = estimate_timeout;
+        self
+    }
+
+    /// Returns how long reservations of the token bucket rule may wait for their tokens.
+    pub fn max_delay(&self) -> Interval {
+        self.max_delay
+    }
+
+    /// Sets how long reservations of the token bucket rule may wait for their tokens to be
+    /// refilled, beyond which they are throttled.
+    ///
+    /// Defaults to one minute.
+    pub fn with_max_delay(mut self, max_delay: Interval) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Returns the clock the token bucket rule reads the current time from.
+    pub fn clock(&self) -> &K {
+        &self.clock
+    }
+
+    /// Sets the clock the token bucket rule reads the current time from, when its
+    /// time source is [`TimeSource::Client`].
+    ///
+    /// Defaults to [`SystemClock`].
+    pub fn with_clock<C: Clock>(self, clock: C) -> TokenBucket<C> {
+        TokenBucket {
+            capacity: self.capacity,
+            refill_interval: self.refill_interval,
+            refill_amount: self.refill_amount,
+            time_source: self.time_source,
+            keyspace: self.keyspace,
+            migration_policy: self.migration_policy,
+            estimate_timeout: self.estimate_timeout,
+            max_delay: self.max_delay,
+            clock,
+        }
+    }
 }
 
-impl RateLimiter for TokenBucket {
-    fn acquire(
+impl<K> TokenBucket<K> {
+    /// Returns the acquisition of `tokens` from the bucket at `key`, at `now`.
+    pub(super) fn operation_at(
+        &self,
+        key: String,
+        now: Option<u64>,
+        tokens: u64,
+    ) -> TokenBucketOperation {
+        TokenBucketOperation {
+            keys: [key],
+            now,
+            capacity: self.capacity,
+            refill_interval: self.refill_interval.as_millis(),
+            refill_amount: self.refill_amount,
+            tokens,
+            mode: Mode::Acquire,
+            migration_policy: self.migration_policy,
+        }
+    }
+}
+
+impl<K: Clock> TokenBucket<K> {
+    fn operation(&self, resource: &str, tokens: u64) -> TokenBucketOperation {
+        self.operation_at(
+            self.keyspace.key("token_bucket", resource),
+            self.time_source.now(&self.clock),
+            tokens,
+        )
+    }
+
+    fn refund_operation(
         &self,
         resource: &str,
         tokens: u64,
-        con: &mut dyn redis::ConnectionLike,
-    ) -> Result<crate::rate_limiter::AcquireResult> {
-        let script = redis::Script::new(Self::REDIS_SCRIPT);
-        let key = format!("token_bucket:{resource}");
-        let result = script
-            .key(&key)
-            .arg(clock::now())
-            .arg(self.capacity)
-            .arg(self.refill_interval.as_secs())
-            .arg(self.refill_amount)
-            .arg(tokens)
-            .invoke::<TokenBucketScriptResult>(con)
-            .map_err(|err| Error::Internal(err.to_string()))?;
+        acquired_at: u64,
+    ) -> TokenBucketOperation {
+        TokenBucketOperation {
+            mode: Mode::Refund { acquired_at },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn check_operation(&self, resource: &str) -> TokenBucketOperation {
+        TokenBucketOperation {
+            mode: Mode::Check,
+            ..self.operation(resource, 0)
+        }
+    }
+
+    fn commit_operation(&self, estimate: &Estimate, actual: u64) -> TokenBucketOperation {
+        TokenBucketOperation {
+            mode: Mode::Commit {
+                estimated: estimate.tokens(),
+                acquired_at: estimate.acquired_at(),
+                expires_at: estimate.expires_at(),
+            },
+            ..self.operation(estimate.resource(), actual)
+        }
+    }
+
+    fn reserve_operation(&self, resource: &str, tokens: u64) -> TokenBucketOperation {
+        TokenBucketOperation {
+            mode: Mode::Reserve {
+                max_delay: self.max_delay.as_millis(),
+            },
+            ..self.operation(resource, tokens)
+        }
+    }
+
+    fn cancel_operation(&self, reservation: &Reservation) -> TokenBucketOperation {
+        TokenBucketOperation {
+            mode: Mode::Cancel {
+                proceed_at: reservation.proceed_at(),
+            },
+            ..self.operation(reservation.resource(), reservation.tokens())
+        }
+    }
+
+    fn quota(&self, result: TokenBucketScriptResult) -> Quota {
+        // Reserved tokens leave nothing remaining until the debt is refilled
+        Quota::new(self.capacity, result.tokens.max(0) as u64, result.reset)
+    }
+
+    fn acquire_result(&self, result: TokenBucketScriptResult) -> AcquireResult {
+        let quota = self.quota(result);
 
         if result.accepted {
-            Ok(AcquireResult::Ok(Quota::new(
-                self.capacity,
-                result.tokens,
-                result.reset,
-            )))
+            AcquireResult::Ok(quota)
+        } else {
+            AcquireResult::Throttled(quota)
+        }
+    }
+
+    fn estimate_result(
+        &self,
+        resource: &str,
+        tokens: u64,
+        result: TokenBucketScriptResult,
+    ) -> EstimateResult {
+        let quota = self.quota(result);
+
+        if result.accepted {
+            let expires_at = result.now + self.estimate_timeout.as_millis();
+            let estimate = Estimate::new(resource, tokens, result.now, expires_at);
+            EstimateResult::Ok(estimate, quota)
         } else {
-            Ok(AcquireResult::Throttled(Quota::new(
-                self.capacity,
+            EstimateResult::Throttled(quota)
+        }
+    }
+
+    fn reservation_result(
+        &self,
+        resource: &str,
+        tokens: u64,
+        result: TokenBucketScriptResult,
+    ) -> ReservationResult {
+        let quota = self.quota(result);
+
+        if result.accepted {
+            let last_updated_at = result.reset - self.refill_interval.as_millis();
+            let proceed_at = proceed_at(
                 result.tokens,
-                result.reset,
-            )))
+                last_updated_at,
+                self.refill_interval.as_millis(),
+                self.refill_amount,
+                result.now,
+            );
+            let reservation = Reservation::new(resource, tokens, proceed_at);
+            ReservationResult::Ok(reservation, quota)
+        } else {
+            ReservationResult::Throttled(quota)
         }
     }
 }
 
+impl<K: Clock> RateLimiter for TokenBucket<K> {
+    fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
+    where
+        S: Store + ?Sized,
+    {
+        RateLimiter::acquire_timed(self, resource, tokens, store).map(|(result, _)| result)
+    }
+
+    fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, tokens))?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.refund_operation(resource, tokens, acquired_at))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: Store + ?Sized,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.check_operation(resource))?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+impl<K: Clock> EstimatingLimiter for TokenBucket<K> {
+    fn acquire_estimate<S>(
+        &self,
+        resource: &str,
+        estimate: u64,
+        store: &mut S,
+    ) -> Result<EstimateResult>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.operation(resource, estimate))?;
+
+        Ok(self.estimate_result(resource, estimate, result))
+    }
+
+    fn commit<S>(&self, estimate: Estimate, actual: u64, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.commit_operation(&estimate, actual))?;
+
+        Ok(result.accepted)
+    }
+}
+
+impl<K: Clock> ReservingLimiter for TokenBucket<K> {
+    fn reserve<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<ReservationResult>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.reserve_operation(resource, tokens))?;
+
+        Ok(self.reservation_result(resource, tokens, result))
+    }
+
+    fn cancel<S>(&self, reservation: Reservation, store: &mut S) -> Result<bool>
+    where
+        S: Store + ?Sized,
+    {
+        let result = store.execute(&self.cancel_operation(&reservation))?;
+
+        Ok(result.accepted)
+    }
+}
+
 #[cfg(feature = "aio")]
 #[async_trait::async_trait]
-impl aio::RateLimiter for TokenBucket {
-    async fn acquire<C>(&self, resource: &str, tokens: u64, con: &mut C) -> Result<AcquireResult>
+impl<K: Clock + Send + Sync> aio::RateLimiter for TokenBucket<K> {
+    async fn acquire<S>(&self, resource: &str, tokens: u64, store: &mut S) -> Result<AcquireResult>
     where
-        C: redis::aio::ConnectionLike + Send + Sync,
+        S: aio::Store + Send + Sync,
     {
-        let script = redis::Script::new(Self::REDIS_SCRIPT);
-        let key = format!("token_bucket:{resource}");
-        let result = script
-            .key(&key)
-            .arg(clock::now())
-            .arg(self.capacity)
-            .arg(self.refill_interval.as_secs())
-            .arg(self.refill_amount)
-            .arg(tokens)
-            .invoke_async::<C, TokenBucketScriptResult>(con)
+        aio::RateLimiter::acquire_timed(self, resource, tokens, store)
             .await
-            .map_err(|err| Error::Internal(err.to_string()))?;
+            .map(|(result, _)| result)
+    }
 
-        if result.accepted {
-            Ok(AcquireResult::Ok(Quota::new(
-                self.capacity,
-                result.tokens,
-                result.reset,
-            )))
-        } else {
-            Ok(AcquireResult::Throttled(Quota::new(
-                self.capacity,
-                result.tokens,
-                result.reset,
-            )))
+    async fn acquire_timed<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<(AcquireResult, Option<u64>)>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, tokens)).await?;
+
+        Ok((self.acquire_result(result), Some(result.now)))
+    }
+
+    async fn refund<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        acquired_at: u64,
+        store: &mut S,
+    ) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.refund_operation(resource, tokens, acquired_at))
+            .await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+
+    async fn acquire_many<S>(
+        &self,
+        acquisitions: &[(&str, u64)],
+        store: &mut S,
+    ) -> Vec<Result<AcquireResult>>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let operations: Vec<_> = acquisitions
+            .iter()
+            .map(|&(resource, tokens)| self.operation(resource, tokens))
+            .collect();
+
+        store
+            .execute_many(&operations)
+            .await
+            .into_iter()
+            .map(|result| result.map(|result| self.acquire_result(result)))
+            .collect()
+    }
+
+    async fn peek<S>(&self, resource: &str, store: &mut S) -> Result<Quota>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.check_operation(resource)).await?;
+
+        Ok(self.acquire_result(result).quota())
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::EstimatingLimiter for TokenBucket<K> {
+    async fn acquire_estimate<S>(
+        &self,
+        resource: &str,
+        estimate: u64,
+        store: &mut S,
+    ) -> Result<EstimateResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.operation(resource, estimate)).await?;
+
+        Ok(self.estimate_result(resource, estimate, result))
+    }
+
+    async fn commit<S>(&self, estimate: Estimate, actual: u64, store: &mut S) -> Result<bool>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.commit_operation(&estimate, actual))
+            .await?;
+
+        Ok(result.accepted)
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<K: Clock + Send + Sync> aio::ReservingLimiter for TokenBucket<K> {
+    async fn reserve<S>(
+        &self,
+        resource: &str,
+        tokens: u64,
+        store: &mut S,
+    ) -> Result<ReservationResult>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store
+            .execute(&self.reserve_operation(resource, tokens))
+            .await?;
+
+        Ok(self.reservation_result(resource, tokens, result))
+    }
+
+    async fn cancel<S>(&self, reservation: Reservation, store: &mut S) -> Result<bool>
+    where
+        S: aio::Store + Send + Sync,
+    {
+        let result = store.execute(&self.cancel_operation(&reservation)).await?;
+
+        Ok(result.accepted)
+    }
+}
+
+/// Acquisition, refund, commit or reservation of tokens from the bucket of a resource.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucketOperation {
+    keys: [String; 1],
+    now: Option<u64>,
+    capacity: u64,
+    refill_interval: u64,
+    refill_amount: u64,
+    tokens: u64,
+    mode: Mode,
+    migration_policy: MigrationPolicy,
+}
+
+impl TokenBucketOperation {
+    /// Returns the operation doing `mode` with the tokens instead.
+    pub(super) fn with_mode(self, mode: Mode) -> Self {
+        Self { mode, ..self }
+    }
+
+    /// Returns the parameters of the bucket, as recorded in its entry.
+    fn parameters(&self) -> [f64; 3] {
+        [
+            self.capacity as f64,
+            self.refill_interval as f64,
+            self.refill_amount as f64,
+        ]
+    }
+}
+
+impl sealed::Script for TokenBucketOperation {
+    const FUNCTION: &'static str = "arret_token_bucket";
+
+    const SOURCE: &'static str = concat!(
+        include_str!("../res/Clock.lua"),
+        include_str!("../res/Migration.lua"),
+        include_str!("../res/TokenBucket.lua"),
+        include_str!("../res/TokenBucketMain.lua")
+    );
+
+    fn script() -> &'static redis::Script {
+        static SCRIPT: OnceLock<redis::Script> = OnceLock::new();
+        SCRIPT.get_or_init(|| redis::Script::new(Self::SOURCE))
+    }
+
+    fn args<A: sealed::Args>(&self, args: &mut A) {
+        args.arg(self.capacity)
+            .arg(self.refill_interval)
+            .arg(self.refill_amount)
+            .arg(self.tokens)
+            .arg(self.migration_policy.as_str());
+        self.mode.args(args);
+    }
+}
+
+impl Operation for TokenBucketOperation {
+    type Output = TokenBucketScriptResult;
+
+    fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    fn now(&self) -> Option<u64> {
+        self.now
+    }
+
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output {
+        // The entry holds the tokens of the bucket, when it was last refilled and the
+        // parameters of the bucket it was written under
+        let bucket = entries[0].as_ref().and_then(|entry| {
+            let values = &entry.values;
+            if values[2..] == self.parameters() {
+                return Some((values[0], values[1]));
+            }
+            self.migration_policy
+                .migrate(values[0], values[2], self.capacity as f64)
+                .map(|tokens| (tokens, values[1]))
+        });
+        // Tokens are signed, since reservations leave the bucket in debt of future refills
+        let capacity = self.capacity as i64;
+        let requested = self.tokens as i64;
+        let (tokens, last_updated_at) = match bucket {
+            Some((tokens, last_updated_at)) => (tokens as i64, last_updated_at as u64),
+            None => (capacity, now),
+        };
+
+        let intervals_passed = now.saturating_sub(last_updated_at) / self.refill_interval;
+        let tokens = capacity.min(tokens + (intervals_passed * self.refill_amount) as i64);
+        let last_updated_at = last_updated_at + intervals_passed * self.refill_interval;
+        let reset = last_updated_at + self.refill_interval;
+
+        let throttled = TokenBucketScriptResult {
+            accepted: false,
+            tokens,
+            reset,
+            now,
+        };
+
+        let available = tokens;
+        let tokens = match self.mode {
+            Mode::Commit {
+                estimated,
+                expires_at,
+                ..
+            } => {
+                if now >= expires_at {
+                    return throttled;
+                }
+                let settled = tokens + estimated as i64 - requested;
+                if requested > estimated as i64 {
+                    tokens.min(settled.max(0))
+                } else {
+                    capacity.min(settled)
+                }
+            }
+            Mode::Refund { .. } => capacity.min(tokens + requested),
+            Mode::Reserve { max_delay } => {
+                let proceed_at = proceed_at(
+                    tokens - requested,
+                    last_updated_at,
+                    self.refill_interval,
+                    self.refill_amount,
+                    now,
+                );
+                if requested > capacity || proceed_at.saturating_sub(now) > max_delay {
+                    return throttled;
+                }
+                tokens - requested
+            }
+            Mode::Cancel { proceed_at } => {
+                if now > proceed_at {
+                    return throttled;
+                }
+                capacity.min(tokens + requested)
+            }
+            Mode::Acquire | Mode::Check if tokens < requested => return throttled,
+            Mode::Check => {
+                return TokenBucketScriptResult {
+                    accepted: true,
+                    ..throttled
+                };
+            }
+            Mode::Acquire => tokens - requested,
+        };
+
+        if tokens != available {
+            entries[0] = (tokens < capacity).then(|| {
+                let missing = (capacity - tokens) as u64;
+                let ttl = self.refill_interval * missing.div_ceil(self.refill_amount);
+                let mut values = vec![tokens as f64, last_updated_at as f64];
+                values.extend(self.parameters());
+                Entry::new(values, now + ttl)
+            });
+        }
+
+        TokenBucketScriptResult {
+            accepted: true,
+            tokens,
+            reset,
+            now,
         }
     }
 }
 
-/// Result of a token bucket Lua script execution.
+/// Returns when a bucket left with `tokens` has been refilled out of debt, or `now` if it is
+/// not in debt.
+fn proceed_at(
+    tokens: i64,
+    last_updated_at: u64,
+    refill_interval: u64,
+    refill_amount: u64,
+    now: u64,
+) -> u64 {
+    if tokens >= 0 {
+        return now;
+    }
+    let refills = tokens.unsigned_abs().div_ceil(refill_amount);
+    last_updated_at + refills * refill_interval
+}
+
+/// Result of a token bucket operation.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-struct TokenBucketScriptResult {
-    accepted: bool,
-    tokens: u64,
-    reset: u64,
+pub(crate) struct TokenBucketScriptResult {
+    pub(super) accepted: bool,
+    pub(super) tokens: i64,
+    pub(super) reset: u64,
+    pub(super) now: u64,
 }
 
 impl redis::FromRedisValue for TokenBucketScriptResult {
     fn from_redis_value(v: &redis::Value) -> redis::RedisResult<Self> {
-        let (accepted, tokens, reset): (bool, u64, u64) =
+        let (accepted, tokens, reset, now): (bool, i64, u64, u64) =
             redis::FromRedisValue::from_redis_value(v)?;
         Ok(Self {
             accepted,
             tokens,
             reset,
+            now,
         })
     }
 }
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+use crate::error::{Error, Result};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{sealed::Args, Operation, Store};
+
+/// Prepares the invocation of the Lua script of `operation`.
+fn invocation<'a, O: Operation>(
+    script: &'a redis::Script,
+    operation: &O,
+) -> redis::ScriptInvocation<'a> {
+    let mut invocation = script.prepare_invoke();
+    for key in operation.keys() {
+        invocation.key(key);
+    }
+    invocation.arg(
+        operation
+            .now()
+            .map(|now| now.to_string())
+            .unwrap_or_default(),
+    );
+    operation.args(&mut invocation);
+    invocation
+}
+
+/// Runs the Lua script of `operation` with `EVALSHA`, which uploads the script first
+/// if Redis does not know it yet, such as after a restart or a failover.
+fn execute<O: Operation>(con: &mut dyn redis::ConnectionLike, operation: &O) -> Result<O::Output> {
+    invocation(O::script(), operation)
+        .invoke(con)
+        .map_err(|err| Error::Internal(err.to_string()))
+}
+
+/// The Lua script running a batch of operations, which is appended to the script of
+/// each operation, itself wrapped into the `operation` function.
+pub(super) const BATCH_SOURCE: &str = include_str!("../res/Batch.lua");
+
+/// Returns the Lua script running a batch of operations `O` in turn, which is prepared
+/// once per operation.
+fn batch_script<O: Operation>() -> &'static redis::Script {
+    static SCRIPTS: OnceLock<Mutex<HashMap<&'static str, &'static redis::Script>>> =
+        OnceLock::new();
+    let mut scripts = SCRIPTS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    scripts.entry(O::FUNCTION).or_insert_with(|| {
+        let source = format!(
+            "local operation = function(KEYS, ARGV)\n{}\nend\n{BATCH_SOURCE}",
+            O::SOURCE
+        );
+        Box::leak(Box::new(redis::Script::new(&source)))
+    })
+}
+
+/// Appends the arguments of a batch running `operations`, where the arguments of each
+/// operation are preceded by its number of keys and arguments.
+///
+/// The keys of the operations are given in the same order, as the keys of the batch.
+pub(super) fn batch_args<O: Operation, A: Args>(operations: &[O], args: &mut A) {
+    for operation in operations {
+        let mut cmd = redis::Cmd::new();
+        cmd.arg(
+            operation
+                .now()
+                .map(|now| now.to_string())
+                .unwrap_or_default(),
+        );
+        operation.args(&mut cmd);
+
+        args.arg(operation.keys().len()).arg(cmd.args_iter().len());
+        for arg in cmd.args_iter() {
+            if let redis::Arg::Simple(arg) = arg {
+                args.arg(arg);
+            }
+        }
+    }
+}
+
+/// Prepares the invocation of the Lua script running `operations` as a batch.
+fn batch<O: Operation>(operations: &[O]) -> redis::ScriptInvocation<'static> {
+    let mut invocation = batch_script::<O>().prepare_invoke();
+    for operation in operations {
+        for key in operation.keys() {
+            invocation.key(key);
+        }
+    }
+    batch_args(operations, &mut invocation);
+    invocation
+}
+
+/// Runs `operations` in a single batch script, which is uploaded first if Redis does
+/// not know it yet.
+///
+/// On Redis Cluster, the batch is refused before it runs if the keys of the operations
+/// span several slots, in which case they are executed one by one instead.
+fn execute_many<O: Operation>(
+    con: &mut dyn redis::ConnectionLike,
+    operations: &[O],
+) -> Vec<Result<O::Output>> {
+    if operations.is_empty() {
+        return Vec::new();
+    }
+
+    match batch(operations).invoke(con) {
+        Err(err) if err.kind() == redis::ErrorKind::CrossSlot => operations
+            .iter()
+            .map(|operation| execute(con, operation))
+            .collect(),
+        replies => outputs::<O>(replies, operations.len()),
+    }
+}
+
+/// Reads the output of each operation from the reply of a batch, in which the error
+/// of a failed operation is given as a status reply, or fails every operation with the
+/// error of the batch itself, such as a connection error.
+pub(super) fn outputs<O: Operation>(
+    replies: redis::RedisResult<Vec<redis::Value>>,
+    len: usize,
+) -> Vec<Result<O::Output>> {
+    match replies {
+        Ok(replies) => replies
+            .iter()
+            .map(|reply| match reply {
+                redis::Value::Status(err) => Err(Error::Internal(err.clone())),
+                reply => redis::FromRedisValue::from_redis_value(reply)
+                    .map_err(|err| Error::Internal(err.to_string())),
+            })
+            .collect(),
+        Err(err) => (0..len)
+            .map(|_| Err(Error::Internal(err.to_string())))
+            .collect(),
+    }
+}
+
+/// Loads the Lua script of the operation `O` into the script cache of Redis.
+pub(crate) fn preload<O: Operation>(con: &mut dyn redis::ConnectionLike) -> Result<()> {
+    O::script()
+        .prepare_invoke()
+        .load(con)
+        .map(drop)
+        .map_err(|err| Error::Internal(err.to_string()))
+}
+
+/// Loads the Lua script of the operation `O` into the script cache of Redis
+/// asynchronously.
+#[cfg(feature = "aio")]
+pub(crate) async fn preload_async<O, C>(con: &mut C) -> Result<()>
+where
+    O: Operation,
+    C: redis::aio::ConnectionLike + Send + Sync,
+{
+    O::script()
+        .prepare_invoke()
+        .load_async(con)
+        .await
+        .map(drop)
+        .map_err(|err| Error::Internal(err.to_string()))
+}
+
+impl<C: redis::ConnectionLike> Store for C {
+    fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output> {
+        execute(self, operation)
+    }
+
+    fn execute_many<O: Operation>(&mut self, operations: &[O]) -> Vec<Result<O::Output>> {
+        execute_many(self, operations)
+    }
+}
+
+impl Store for dyn redis::ConnectionLike + '_ {
+    fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output> {
+        execute(self, operation)
+    }
+
+    fn execute_many<O: Operation>(&mut self, operations: &[O]) -> Vec<Result<O::Output>> {
+        execute_many(self, operations)
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<C: redis::aio::ConnectionLike + Send + Sync> aio::Store for C {
+    async fn execute<O>(&mut self, operation: &O) -> Result<O::Output>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        invocation(O::script(), operation)
+            .invoke_async(self)
+            .await
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    async fn execute_many<O>(&mut self, operations: &[O]) -> Vec<Result<O::Output>>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        if operations.is_empty() {
+            return Vec::new();
+        }
+
+        let replies = batch(operations).invoke_async(self).await;
+        if matches!(&replies, Err(err) if err.kind() == redis::ErrorKind::CrossSlot) {
+            let mut outputs = Vec::with_capacity(operations.len());
+            for operation in operations {
+                outputs.push(aio::Store::execute(self, operation).await);
+            }
+            return outputs;
+        }
+        outputs::<O>(replies, operations.len())
+    }
+}
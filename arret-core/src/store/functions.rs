@@ -0,0 +1,556 @@
+use std::sync::OnceLock;
+
+use crate::{
+    error::{Error, Result},
+    rule::{
+        composite::CompositeOperation,
+        concurrency::{
+            ConcurrencyExtendOperation, ConcurrencyOperation, ConcurrencyReleaseOperation,
+        },
+        fixed_window::FixedWindowOperation,
+        gcra::GcraOperation,
+        leaky_bucket::LeakyBucketOperation,
+        sliding_window_counter::SlidingWindowCounterOperation,
+        sliding_window_log::SlidingWindowLogOperation,
+        token_bucket::TokenBucketOperation,
+        CALENDAR_WINDOW_FUNCTION, CALENDAR_WINDOW_SOURCE,
+    },
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{
+    connection::{batch_args, outputs, BATCH_SOURCE},
+    Operation, Store,
+};
+
+/// The name of the function returning the version of the library.
+const VERSION_FUNCTION: &str = "arret_version";
+
+/// The rules of this crate as a library of [Redis Functions](https://redis.io/docs/manual/programmability/functions-intro/),
+/// which requires Redis 7 or later.
+///
+/// Once [installed](Library::install), the library persists across restarts of Redis and
+/// is replicated along with the data, unlike the script cache. Its functions are called
+/// by the [`Functions`] store, and are named after the rules, such as
+/// `arret_fixed_window` and `arret_token_bucket`, along with functions running a batch of
+/// requests to a rule, such as `arret_fixed_window_batch`.
+///
+/// On Redis Cluster, cluster connections send `FUNCTION LOAD` to every primary, so the
+/// library is installed on the whole cluster at once. Primaries added to the cluster
+/// later on must be given the library as well, such as by installing it again, which
+/// `Library::check_cluster_async` verifies. The functions are then called by the
+/// `ClusterFunctions` store, over an asynchronous cluster connection only.
+///
+/// ```rust,no_run
+/// use arret_core::{
+///     interval::Interval,
+///     rate_limiter::RateLimiter,
+///     rule::FixedWindow,
+///     store::{Functions, Library},
+/// };
+///
+/// let client = redis::Client::open("redis://127.0.0.1:6379").unwrap();
+/// let mut con = client.get_connection().unwrap();
+///
+/// // Install the library once, for example when deploying
+/// Library::install(&mut con).unwrap();
+///
+/// // Make sure the library matches this version of the crate before serving requests
+/// Library::check(&mut con).unwrap();
+///
+/// let fixed_window = FixedWindow::new(10, Interval::from_secs(60).unwrap()).unwrap();
+/// let res = fixed_window.acquire("resource", 1, &mut Functions::new(&mut con));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Library;
+
+/// The source and the version of the library, which are built once.
+struct Source {
+    code: String,
+    version: String,
+}
+
+impl Library {
+    /// The name of the library.
+    pub const NAME: &'static str = "arret";
+
+    fn source() -> &'static Source {
+        static SOURCE: OnceLock<Source> = OnceLock::new();
+        SOURCE.get_or_init(|| {
+            let mut functions = String::new();
+            // Calendar windows are registered even without the `calendar` feature, so
+            // that services built with different features share the same library
+            register_source(
+                &mut functions,
+                CALENDAR_WINDOW_FUNCTION,
+                CALENDAR_WINDOW_SOURCE,
+            );
+            register::<CompositeOperation>(&mut functions);
+            register::<ConcurrencyOperation>(&mut functions);
+            register::<ConcurrencyExtendOperation>(&mut functions);
+            register::<ConcurrencyReleaseOperation>(&mut functions);
+            register::<FixedWindowOperation>(&mut functions);
+            register::<GcraOperation>(&mut functions);
+            register::<LeakyBucketOperation>(&mut functions);
+            register::<SlidingWindowCounterOperation>(&mut functions);
+            register::<SlidingWindowLogOperation>(&mut functions);
+            register::<TokenBucketOperation>(&mut functions);
+
+            // The version changes along with any of the functions
+            let hash = redis::Script::new(&functions).get_hash().to_owned();
+            let version = format!("{}+{}", env!("CARGO_PKG_VERSION"), &hash[..12]);
+
+            let code = format!(
+                "#!lua name={name}\n{functions}\n\
+                 redis.register_function{{\n  \
+                   function_name = \"{VERSION_FUNCTION}\",\n  \
+                   callback = function() return \"{version}\" end,\n  \
+                   flags = {{\"no-writes\"}},\n\
+                 }}\n",
+                name = Self::NAME,
+            );
+
+            Source { code, version }
+        })
+    }
+
+    /// Returns the Lua source of the library, as loaded with `FUNCTION LOAD`.
+    ///
+    /// ```rust
+    /// use arret_core::store::Library;
+    ///
+    /// assert!(Library::code().starts_with("#!lua name=arret\n"));
+    /// ```
+    pub fn code() -> &'static str {
+        &Self::source().code
+    }
+
+    /// Returns the version of the library, which changes whenever its source does.
+    pub fn version() -> &'static str {
+        &Self::source().version
+    }
+
+    /// Installs the library on Redis, replacing any other version of it.
+    pub fn install(con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        load_cmd()
+            .query::<String>(con)
+            .map(drop)
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    /// Returns the version of the library installed on Redis, or `None` if it is not
+    /// installed.
+    pub fn installed_version(con: &mut dyn redis::ConnectionLike) -> Result<Option<String>> {
+        let libraries: Vec<redis::Value> = list_cmd()
+            .query(con)
+            .map_err(|err| Error::Internal(err.to_string()))?;
+        if libraries.is_empty() {
+            return Ok(None);
+        }
+
+        version_cmd()
+            .query(con)
+            .map(Some)
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    /// Checks that the library installed on Redis is the version of this crate.
+    ///
+    /// On Redis Cluster, only the node the command is routed to is checked. The
+    /// synchronous cluster connection cannot ask every primary, which
+    /// `Library::check_cluster_async` does.
+    ///
+    /// # Errors
+    /// - [`Error::LibraryVersion`] if the library is missing or of another version.
+    pub fn check(con: &mut dyn redis::ConnectionLike) -> Result<()> {
+        check(Self::installed_version(con)?)
+    }
+
+    /// Installs the library on Redis asynchronously, replacing any other version of it.
+    #[cfg(feature = "aio")]
+    pub async fn install_async<C>(con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        load_cmd()
+            .query_async::<C, String>(con)
+            .await
+            .map(drop)
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    /// Returns the version of the library installed on Redis asynchronously, or `None`
+    /// if it is not installed.
+    #[cfg(feature = "aio")]
+    pub async fn installed_version_async<C>(con: &mut C) -> Result<Option<String>>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        let libraries: Vec<redis::Value> = list_cmd()
+            .query_async(con)
+            .await
+            .map_err(|err| Error::Internal(err.to_string()))?;
+        if libraries.is_empty() {
+            return Ok(None);
+        }
+
+        version_cmd()
+            .query_async(con)
+            .await
+            .map(Some)
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    /// Checks that the library installed on Redis is the version of this crate
+    /// asynchronously.
+    ///
+    /// On Redis Cluster, only the node the command is routed to is checked, use
+    /// `Library::check_cluster_async` to check every primary.
+    ///
+    /// # Errors
+    /// - [`Error::LibraryVersion`] if the library is missing or of another version.
+    #[cfg(feature = "aio")]
+    pub async fn check_async<C>(con: &mut C) -> Result<()>
+    where
+        C: redis::aio::ConnectionLike + Send + Sync,
+    {
+        check(Self::installed_version_async(con).await?)
+    }
+
+    /// Checks that the library installed on every primary of Redis Cluster is the version
+    /// of this crate asynchronously.
+    ///
+    /// Unlike [`Library::check_async`], which only asks the node the command happens to
+    /// be routed to, this catches primaries which missed an installation, such as those
+    /// added to the cluster later on.
+    ///
+    /// # Errors
+    /// - [`Error::LibraryVersion`] if the library is missing or of another version on any
+    ///   primary.
+    #[cfg(feature = "cluster-async")]
+    pub async fn check_cluster_async(
+        con: &mut redis::cluster_async::ClusterConnection,
+    ) -> Result<()> {
+        // Primaries without the library would fail the call of the version function
+        let libraries: Vec<(String, Vec<redis::Value>)> = query_primaries(con, &list_cmd()).await?;
+        if libraries.iter().any(|(_, libraries)| libraries.is_empty()) {
+            return check(None);
+        }
+
+        let versions: Vec<(String, String)> = query_primaries(con, &version_cmd()).await?;
+        for (_, version) in versions {
+            check(Some(version))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends `cmd` to every primary of Redis Cluster, returning the reply of each primary
+/// along with its address.
+#[cfg(feature = "cluster-async")]
+async fn query_primaries<T: redis::FromRedisValue>(
+    con: &mut redis::cluster_async::ClusterConnection,
+    cmd: &redis::Cmd,
+) -> Result<Vec<(String, T)>> {
+    use redis::cluster_routing::{MultipleNodeRoutingInfo, RoutingInfo};
+
+    let routing = RoutingInfo::MultiNode((MultipleNodeRoutingInfo::AllMasters, None));
+    con.route_command(cmd, routing)
+        .await
+        .and_then(|replies| redis::FromRedisValue::from_redis_value(&replies))
+        .map_err(|err| Error::Internal(err.to_string()))
+}
+
+/// Appends the registration of the function running the script of `O`, and of the
+/// function running a batch of them, suffixed with `_batch`.
+///
+/// The script reads its keys and arguments from `KEYS` and `ARGV`, which the function
+/// receives as its parameters under the same names.
+fn register<O: Operation>(functions: &mut String) {
+    register_source(functions, O::FUNCTION, O::SOURCE);
+}
+
+/// Appends the registration of the function `name` running the script `source`, and of
+/// the function running a batch of them.
+fn register_source(functions: &mut String, name: &str, source: &str) {
+    functions.push_str(&format!(
+        "\nlocal function {name}(KEYS, ARGV)\n{source}\nend\n\
+         redis.register_function(\"{name}\", {name})\n\
+         redis.register_function(\"{name}_batch\", function(KEYS, ARGV)\n\
+         local operation = {name}\n{BATCH_SOURCE}\nend)\n",
+    ));
+}
+
+fn check(installed: Option<String>) -> Result<()> {
+    if installed.as_deref() == Some(Library::version()) {
+        Ok(())
+    } else {
+        Err(Error::LibraryVersion {
+            expected: Library::version().into(),
+            installed,
+        })
+    }
+}
+
+fn load_cmd() -> redis::Cmd {
+    let mut cmd = redis::cmd("FUNCTION");
+    cmd.arg("LOAD").arg("REPLACE").arg(Library::code());
+    cmd
+}
+
+fn list_cmd() -> redis::Cmd {
+    let mut cmd = redis::cmd("FUNCTION");
+    cmd.arg("LIST").arg("LIBRARYNAME").arg(Library::NAME);
+    cmd
+}
+
+fn version_cmd() -> redis::Cmd {
+    let mut cmd = redis::cmd("FCALL_RO");
+    cmd.arg(VERSION_FUNCTION).arg(0);
+    cmd
+}
+
+/// Prepares the call of the function running `operation`.
+fn fcall<O: Operation>(operation: &O) -> redis::Cmd {
+    let mut cmd = redis::cmd("FCALL");
+    cmd.arg(O::FUNCTION)
+        .arg(operation.keys().len())
+        .arg(operation.keys())
+        .arg(
+            operation
+                .now()
+                .map(|now| now.to_string())
+                .unwrap_or_default(),
+        );
+    operation.args(&mut cmd);
+    cmd
+}
+
+/// Prepares the call of the function running `operations` as a batch.
+fn fcall_batch<O: Operation>(operations: &[O]) -> redis::Cmd {
+    let mut cmd = redis::cmd("FCALL");
+    cmd.arg(format!("{}_batch", O::FUNCTION)).arg(
+        operations
+            .iter()
+            .map(|operation| operation.keys().len())
+            .sum::<usize>(),
+    );
+    for operation in operations {
+        cmd.arg(operation.keys());
+    }
+    batch_args(operations, &mut cmd);
+    cmd
+}
+
+/// A [`Store`] running operations with `FCALL`, as the functions of the [`Library`]
+/// installed on Redis.
+///
+/// Unlike scripts, functions are not uploaded on demand, so the library must have been
+/// [installed](Library::install) beforehand.
+///
+/// Cluster connections route `FCALL` by the name of the function rather than by its keys,
+/// so on Redis Cluster use `ClusterFunctions` instead.
+#[derive(Debug, Clone)]
+pub struct Functions<C> {
+    con: C,
+}
+
+impl<C> Functions<C> {
+    /// Creates a new [`Functions`] store calling the functions over the given connection.
+    pub fn new(con: C) -> Self {
+        Self { con }
+    }
+
+    /// Returns the connection of the store.
+    pub fn get_mut(&mut self) -> &mut C {
+        &mut self.con
+    }
+
+    /// Returns the connection of the store, consuming it.
+    pub fn into_inner(self) -> C {
+        self.con
+    }
+}
+
+impl<C: redis::ConnectionLike> Store for Functions<C> {
+    fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output> {
+        fcall(operation)
+            .query(&mut self.con)
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    fn execute_many<O: Operation>(&mut self, operations: &[O]) -> Vec<Result<O::Output>> {
+        if operations.is_empty() {
+            return Vec::new();
+        }
+
+        match fcall_batch(operations).query(&mut self.con) {
+            Err(err) if err.kind() == redis::ErrorKind::CrossSlot => operations
+                .iter()
+                .map(|operation| self.execute(operation))
+                .collect(),
+            replies => outputs::<O>(replies, operations.len()),
+        }
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl<C: redis::aio::ConnectionLike + Send + Sync> aio::Store for Functions<C> {
+    async fn execute<O>(&mut self, operation: &O) -> Result<O::Output>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        fcall(operation)
+            .query_async(&mut self.con)
+            .await
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    async fn execute_many<O>(&mut self, operations: &[O]) -> Vec<Result<O::Output>>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        if operations.is_empty() {
+            return Vec::new();
+        }
+
+        let replies = fcall_batch(operations).query_async(&mut self.con).await;
+        if matches!(&replies, Err(err) if err.kind() == redis::ErrorKind::CrossSlot) {
+            let mut outputs = Vec::with_capacity(operations.len());
+            for operation in operations {
+                outputs.push(aio::Store::execute(self, operation).await);
+            }
+            return outputs;
+        }
+        outputs::<O>(replies, operations.len())
+    }
+}
+
+/// An asynchronous [`Store`](aio::Store) calling the functions of the [`Library`] over a
+/// connection to Redis Cluster.
+///
+/// Cluster connections route a command by its first argument, which is the name of the
+/// function for `FCALL` rather than a key, so [`Functions`] would send most calls to the
+/// wrong node only to be redirected. Instead, each call is routed to the primary owning
+/// the slot of the keys of its operation.
+///
+/// The synchronous `redis::cluster::ClusterConnection` cannot route a command to a given
+/// slot, so it cannot call the functions efficiently. Use it as a store itself, which runs
+/// the scripts of the rules routed by their keys.
+///
+/// ```rust,no_run
+/// use arret_core::{
+///     aio::RateLimiter,
+///     interval::Interval,
+///     rule::FixedWindow,
+///     store::{ClusterFunctions, Library},
+/// };
+///
+/// # async fn run() {
+/// let client = redis::cluster::ClusterClient::new(vec!["redis://127.0.0.1:7000"]).unwrap();
+/// let mut con = client.get_async_connection().await.unwrap();
+///
+/// Library::install_async(&mut con).await.unwrap();
+/// Library::check_cluster_async(&mut con).await.unwrap();
+///
+/// let fixed_window = FixedWindow::new(10, Interval::from_secs(60).unwrap()).unwrap();
+/// let res = fixed_window
+///     .acquire("resource", 1, &mut ClusterFunctions::new(con))
+///     .await;
+/// # }
+/// ```
+#[cfg(feature = "cluster-async")]
+#[derive(Clone)]
+pub struct ClusterFunctions {
+    con: redis::cluster_async::ClusterConnection,
+}
+
+#[cfg(feature = "cluster-async")]
+impl ClusterFunctions {
+    /// Creates a new [`ClusterFunctions`] store calling the functions over the given
+    /// cluster connection.
+    pub fn new(con: redis::cluster_async::ClusterConnection) -> Self {
+        Self { con }
+    }
+
+    /// Returns the connection of the store.
+    pub fn get_mut(&mut self) -> &mut redis::cluster_async::ClusterConnection {
+        &mut self.con
+    }
+
+    /// Returns the connection of the store, consuming it.
+    pub fn into_inner(self) -> redis::cluster_async::ClusterConnection {
+        self.con
+    }
+
+    async fn route<T: redis::FromRedisValue>(
+        &mut self,
+        cmd: &redis::Cmd,
+        key: Option<&String>,
+    ) -> redis::RedisResult<T> {
+        use redis::cluster_routing::{
+            get_slot, Route, RoutingInfo, SingleNodeRoutingInfo, SlotAddr,
+        };
+
+        let routing = match key {
+            Some(key) => SingleNodeRoutingInfo::SpecificNode(Route::new(
+                get_slot(key.as_bytes()),
+                SlotAddr::Master,
+            )),
+            None => SingleNodeRoutingInfo::Random,
+        };
+        let reply = self
+            .con
+            .route_command(cmd, RoutingInfo::SingleNode(routing))
+            .await?;
+        redis::FromRedisValue::from_redis_value(&reply)
+    }
+}
+
+#[cfg(feature = "cluster-async")]
+impl std::fmt::Debug for ClusterFunctions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClusterFunctions").finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "cluster-async")]
+#[async_trait::async_trait]
+impl aio::Store for ClusterFunctions {
+    async fn execute<O>(&mut self, operation: &O) -> Result<O::Output>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        self.route(&fcall(operation), operation.keys().first())
+            .await
+            .map_err(|err| Error::Internal(err.to_string()))
+    }
+
+    async fn execute_many<O>(&mut self, operations: &[O]) -> Vec<Result<O::Output>>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        let Some(first) = operations.first() else {
+            return Vec::new();
+        };
+
+        let replies = self
+            .route(&fcall_batch(operations), first.keys().first())
+            .await;
+        if matches!(&replies, Err(err) if err.kind() == redis::ErrorKind::CrossSlot) {
+            let mut outputs = Vec::with_capacity(operations.len());
+            for operation in operations {
+                outputs.push(aio::Store::execute(self, operation).await);
+            }
+            return outputs;
+        }
+        outputs::<O>(replies, operations.len())
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::error::{Error, Result};
+
+/// How the keys of the entries of a rule are built from its resources.
+///
+/// A key is made of the optional namespace, the name of the rule and the resource, such
+/// as `billing:token_bucket:{7:user:42}`. The resource is prefixed with its length in
+/// bytes, so that a resource containing `:` or `}` cannot alias the key of another
+/// resource. It is also the hash tag of the key, which places every key of a resource in
+/// the same slot on Redis Cluster.
+///
+/// Services sharing a Redis should each use their own namespace. Resources longer than
+/// the [maximum resource length](Keyspace::with_max_resource_len) are replaced by their
+/// SHA-1 digest, which keeps keys short when resources are user-provided.
+///
+/// ```rust
+/// use arret_core::store::Keyspace;
+///
+/// let keyspace = Keyspace::new().with_namespace("billing").unwrap();
+/// assert_eq!(
+///     keyspace.key("token_bucket", "user:42"),
+///     "billing:token_bucket:{7:user:42}"
+/// );
+///
+/// let keyspace = Keyspace::new().with_max_resource_len(8);
+/// assert_eq!(
+///     keyspace.key("token_bucket", "user:1234567890"),
+///     "token_bucket:{#498467340e9f18ea199a7d20e635001a88a63dc6}"
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Keyspace {
+    namespace: Option<Arc<str>>,
+    max_resource_len: Option<usize>,
+}
+
+impl Keyspace {
+    /// Creates a new [`Keyspace`] without namespace, which never hashes resources.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the namespace prefixing every key.
+    pub fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    /// Sets the namespace prefixing every key.
+    ///
+    /// # Errors
+    /// - [`Error::InvalidKeyspace`] if the namespace is empty or contains `{` or `}`,
+    ///   which would change the hash tag of the keys.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Result<Self> {
+        let namespace = namespace.into();
+        if namespace.is_empty() {
+            return Err(Error::InvalidKeyspace("Namespace must not be empty".into()));
+        }
+        if namespace.contains(['{', '}']) {
+            return Err(Error::InvalidKeyspace(format!(
+                "Namespace must not contain braces: {namespace}"
+            )));
+        }
+
+        self.namespace = Some(namespace.into());
+        Ok(self)
+    }
+
+    /// Returns the length in bytes above which resources are hashed.
+    pub fn max_resource_len(&self) -> Option<usize> {
+        self.max_resource_len
+    }
+
+    /// Sets the length in bytes above which resources are replaced by their digest.
+    pub fn with_max_resource_len(mut self, max_resource_len: usize) -> Self {
+        self.max_resource_len = Some(max_resource_len);
+        self
+    }
+
+    /// Returns the key of the entry holding the state of `rule` for `resource`.
+    pub fn key(&self, rule: &str, resource: &str) -> String {
+        let tag = self.segment(resource);
+
+        match &self.namespace {
+            Some(namespace) => format!("{namespace}:{rule}:{{{tag}}}"),
+            None => format!("{rule}:{{{tag}}}"),
+        }
+    }
+
+    /// Returns the key of the entry holding the state of `rule` for the resource at the
+    /// end of `path`, which is tagged with the first resource of the path, so that the
+    /// keys of a whole path are placed in the same slot on Redis Cluster.
+    pub(crate) fn path_key(&self, rule: &str, path: &[&str]) -> String {
+        let mut key = self.key(rule, path[0]);
+        for resource in &path[1..] {
+            key.push(':');
+            key.push_str(&self.segment(resource));
+        }
+        key
+    }
+
+    fn segment(&self, resource: &str) -> String {
+        // Digests are marked with `#`, which cannot start the length of a resource
+        match self.max_resource_len {
+            Some(max_resource_len) if resource.len() > max_resource_len => {
+                format!("#{}", sha1_smol::Sha1::from(resource).digest())
+            }
+            _ => format!("{}:{resource}", resource.len()),
+        }
+    }
+}
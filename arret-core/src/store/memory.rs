@@ -0,0 +1,171 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::BuildHasher,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    error::{Error, Result},
+    rule::{Clock, SystemClock},
+};
+
+#[cfg(feature = "aio")]
+use crate::aio;
+
+use super::{Entry, Operation, Store};
+
+/// How often a shard sweeps its expired entries, in milliseconds.
+const SWEEP_INTERVAL: u64 = 1_000;
+
+/// A [`Store`] keeping the state of rules in the memory of the process, so that rules
+/// may be used without Redis when the limit need not be shared across processes.
+///
+/// Entries are spread over shards, each guarded by its own lock, so that requests on
+/// different resources rarely contend. Expired entries are treated as missing, and are
+/// swept from a shard as it is accessed.
+///
+/// Clones of a [`MemoryStore`] share the same entries.
+///
+/// ```rust
+/// use arret_core::{
+///     interval::Interval,
+///     rate_limiter::{AcquireResult, RateLimiter},
+///     rule::FixedWindow,
+///     store::MemoryStore,
+/// };
+///
+/// let mut store = MemoryStore::new();
+/// let fixed_window = FixedWindow::new(1, Interval::from_secs(60).unwrap()).unwrap();
+///
+/// let res = fixed_window.acquire("resource", 1, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Ok(_)));
+///
+/// let res = fixed_window.acquire("resource", 1, &mut store).unwrap();
+/// assert!(matches!(res, AcquireResult::Throttled(_)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    shards: Arc<[Mutex<Shard>]>,
+    hasher: RandomState,
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+    entries: HashMap<String, Entry>,
+    swept_at: u64,
+}
+
+impl Shard {
+    /// Removes the expired entries of the shard, at most once per sweep interval.
+    fn sweep(&mut self, now: u64) {
+        if now >= self.swept_at.saturating_add(SWEEP_INTERVAL) {
+            self.entries.retain(|_, entry| !entry.is_expired(now));
+            self.swept_at = now;
+        }
+    }
+}
+
+impl MemoryStore {
+    /// Creates a new empty [`MemoryStore`], with a number of shards suited to the
+    /// parallelism of the host.
+    pub fn new() -> Self {
+        let parallelism = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_shards(parallelism * 4)
+    }
+
+    /// Creates a new empty [`MemoryStore`] with the given number of shards.
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    pub fn with_shards(shards: usize) -> Self {
+        assert!(shards > 0, "Number of shards must be greater than zero");
+        Self {
+            shards: (0..shards).map(|_| Mutex::default()).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns the number of shards of the store.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_of(&self, key: &str) -> usize {
+        (self.hasher.hash_one(key) % self.shards.len() as u64) as usize
+    }
+
+    fn execute<O: Operation>(&self, operation: &O) -> Result<O::Output> {
+        let keys = operation.keys();
+        let now = operation.now().unwrap_or_else(|| SystemClock.now());
+
+        // Lock the shards of every key in a consistent order, so that operations
+        // spanning several shards cannot deadlock
+        let shards: Vec<usize> = keys.iter().map(|key| self.shard_of(key)).collect();
+        let mut locked = shards.clone();
+        locked.sort_unstable();
+        locked.dedup();
+
+        let mut guards = locked
+            .iter()
+            .map(|&shard| self.shards[shard].lock())
+            .collect::<std::result::Result<Vec<MutexGuard<Shard>>, _>>()
+            .map_err(|err| Error::Internal(err.to_string()))?;
+        let guard_of = |shard: usize| locked.binary_search(&shard).unwrap();
+
+        for guard in guards.iter_mut() {
+            guard.sweep(now);
+        }
+
+        let mut entries: Vec<Option<Entry>> = keys
+            .iter()
+            .zip(&shards)
+            .map(|(key, &shard)| {
+                guards[guard_of(shard)]
+                    .entries
+                    .get(key)
+                    .filter(|entry| !entry.is_expired(now))
+                    .cloned()
+            })
+            .collect();
+
+        let output = operation.apply(now, &mut entries);
+
+        for ((key, &shard), entry) in keys.iter().zip(&shards).zip(entries) {
+            let shard = &mut guards[guard_of(shard)].entries;
+            match entry {
+                Some(entry) if !entry.is_expired(now) => {
+                    shard.insert(key.clone(), entry);
+                }
+                _ => {
+                    shard.remove(key);
+                }
+            }
+        }
+
+        Ok(output)
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Store for MemoryStore {
+    fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output> {
+        MemoryStore::execute(self, operation)
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl aio::Store for MemoryStore {
+    async fn execute<O>(&mut self, operation: &O) -> Result<O::Output>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        MemoryStore::execute(self, operation)
+    }
+}
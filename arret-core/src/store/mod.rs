@@ -0,0 +1,196 @@
+//! Storage of the state of rules.
+//!
+//! Rules never talk to a backend directly. Instead, each request is described as an
+//! [`Operation`] which a [`Store`] executes atomically: Redis runs the Lua script of the
+//! operation, while other stores such as [`MemoryStore`] apply its transition in process.
+//!
+//! On Redis 7 and later, the scripts may instead be installed as a [`Library`] of Redis
+//! Functions, which the [`Functions`] store calls (or `ClusterFunctions` on Redis
+//! Cluster, over an asynchronous connection).
+//!
+//! Connections to Redis Cluster, `redis::cluster::ClusterConnection` and its asynchronous
+//! counterpart behind the `cluster` and `cluster-async` features, are stores as well. The
+//! keys of a resource share a hash tag, which is laid out by the [`Keyspace`] of the rule,
+//! so that every operation is served by the node owning the slot of the resource.
+//!
+//! Other backends may be added by implementing [`Store`] (and
+//! [`aio::Store`](crate::aio::Store) for asynchronous requests). Such a store only has to
+//! load the [entries](Entry) at the keys of an operation, [apply](Operation::apply) it,
+//! and save the entries back, all within a single transaction.
+//!
+//! ```rust
+//! use std::collections::HashMap;
+//!
+//! use arret_core::{
+//!     error::Result,
+//!     interval::Interval,
+//!     rate_limiter::{AcquireResult, RateLimiter},
+//!     rule::{Clock, FixedWindow, SystemClock},
+//!     store::{Entry, Operation, Store},
+//! };
+//!
+//! /// A store which is not shared across threads.
+//! #[derive(Default)]
+//! struct LocalStore(HashMap<String, Entry>);
+//!
+//! impl Store for LocalStore {
+//!     fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output> {
+//!         let now = operation.now().unwrap_or_else(|| SystemClock.now());
+//!
+//!         let mut entries: Vec<Option<Entry>> = operation
+//!             .keys()
+//!             .iter()
+//!             .map(|key| self.0.get(key).filter(|entry| !entry.is_expired(now)).cloned())
+//!             .collect();
+//!
+//!         let output = operation.apply(now, &mut entries);
+//!
+//!         for (key, entry) in operation.keys().iter().zip(entries) {
+//!             match entry {
+//!                 Some(entry) => self.0.insert(key.clone(), entry),
+//!                 None => self.0.remove(key),
+//!             };
+//!         }
+//!
+//!         Ok(output)
+//!     }
+//! }
+//!
+//! let mut store = LocalStore::default();
+//! let fixed_window = FixedWindow::new(1, Interval::from_secs(60).unwrap()).unwrap();
+//!
+//! let res = fixed_window.acquire("resource", 1, &mut store).unwrap();
+//! assert!(matches!(res, AcquireResult::Ok(_)));
+//!
+//! let res = fixed_window.acquire("resource", 1, &mut store).unwrap();
+//! assert!(matches!(res, AcquireResult::Throttled(_)));
+//! ```
+mod connection;
+mod functions;
+mod keyspace;
+mod memory;
+
+pub use self::{
+    functions::{Functions, Library},
+    keyspace::Keyspace,
+    memory::MemoryStore,
+};
+
+#[cfg(feature = "cluster-async")]
+pub use self::functions::ClusterFunctions;
+
+pub(crate) use self::connection::preload;
+
+#[cfg(feature = "aio")]
+pub(crate) use self::connection::preload_async;
+
+use crate::error::Result;
+
+/// A backend holding the state of rules, such as a Redis connection or a [`MemoryStore`].
+pub trait Store {
+    /// Executes the given `operation` atomically, and returns its output.
+    ///
+    /// Either every entry written by the operation is saved, or none of them is.
+    fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output>;
+
+    /// Executes the given independent `operations`, each one atomically, and returns
+    /// their outputs in the same order.
+    ///
+    /// Redis connections run the operations in a single script, so that they take a
+    /// single round trip, unless their keys span several slots of Redis Cluster. An
+    /// operation failing within the script does not fail the others. Other stores
+    /// execute them one by one by default.
+    fn execute_many<O: Operation>(&mut self, operations: &[O]) -> Vec<Result<O::Output>> {
+        operations
+            .iter()
+            .map(|operation| self.execute(operation))
+            .collect()
+    }
+}
+
+/// An atomic read-modify-write of the state of a rule for a single request.
+///
+/// An operation reads and writes the [entries](Entry) at its [keys](Operation::keys),
+/// which are all executed as a whole or not at all.
+///
+/// Operations are defined by the rules of this crate, and may not be implemented
+/// outside of it.
+pub trait Operation: sealed::Script {
+    /// The output of the operation, which is also read back from the reply of its
+    /// Lua script.
+    type Output: redis::FromRedisValue;
+
+    /// Returns the keys of the entries the operation reads and writes.
+    fn keys(&self) -> &[String];
+
+    /// Returns the current time of the request in milliseconds since the Unix epoch,
+    /// or `None` if the store should use its own clock.
+    fn now(&self) -> Option<u64>;
+
+    /// Applies the operation at `now` on the entries at its keys, in the same order.
+    ///
+    /// Missing or expired entries are given as `None`, and an entry set to `None` is
+    /// removed from the store.
+    fn apply(&self, now: u64, entries: &mut [Option<Entry>]) -> Self::Output;
+}
+
+/// The state stored at a key by an [`Operation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// The values of the entry, whose meaning is up to the operation.
+    pub values: Vec<f64>,
+
+    /// The epochmillis timestamp from which the entry is considered missing.
+    pub expires_at: u64,
+}
+
+impl Entry {
+    /// Creates a new [`Entry`] with the given values, expiring at `expires_at`.
+    pub fn new(values: Vec<f64>, expires_at: u64) -> Self {
+        Self { values, expires_at }
+    }
+
+    /// Returns whether the entry has expired at `now`.
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at <= now
+    }
+}
+
+pub(crate) mod sealed {
+    /// The Lua script executing an [`Operation`](super::Operation) on Redis.
+    pub trait Script {
+        /// The name of the function running the script in the
+        /// [`Library`](super::Library).
+        const FUNCTION: &'static str;
+
+        /// The source of the script, which reads the current time from `ARGV[1]`.
+        const SOURCE: &'static str;
+
+        /// Returns the script, which is prepared once so that its hash is not computed
+        /// on every request.
+        fn script() -> &'static redis::Script;
+
+        /// Appends the arguments of the script following the current time.
+        fn args<A: Args>(&self, args: &mut A);
+    }
+
+    /// A Redis command to which the arguments of a script are appended, which is either
+    /// a script invocation or a function call.
+    pub trait Args {
+        /// Appends an argument to the command.
+        fn arg<T: redis::ToRedisArgs>(&mut self, arg: T) -> &mut Self;
+    }
+
+    impl Args for redis::ScriptInvocation<'_> {
+        fn arg<T: redis::ToRedisArgs>(&mut self, arg: T) -> &mut Self {
+            redis::ScriptInvocation::arg(self, arg);
+            self
+        }
+    }
+
+    impl Args for redis::Cmd {
+        fn arg<T: redis::ToRedisArgs>(&mut self, arg: T) -> &mut Self {
+            redis::Cmd::arg(self, arg)
+        }
+    }
+}
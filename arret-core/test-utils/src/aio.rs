@@ -15,6 +15,16 @@ pub async fn prepare_redis_async_connection() -> redis::aio::Connection {
         .expect("Failed to get Redis connection")
 }
 
+#[cfg(feature = "cluster-async")]
+pub async fn prepare_redis_cluster_async_connection() -> redis::cluster_async::ClusterConnection {
+    let client = redis::cluster::ClusterClient::new(vec!["redis://127.0.0.1:7000"])
+        .expect("Failed to connect to Redis Cluster");
+    client
+        .get_async_connection()
+        .await
+        .expect("Failed to get Redis Cluster connection")
+}
+
 pub fn block_on<F>(f: F) -> F::Output
 where
     F: future::Future,
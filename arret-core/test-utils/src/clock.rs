@@ -0,0 +1,48 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use arret_core::rule::{Clock, SystemClock};
+
+/// A [`Clock`] which only advances when told to.
+///
+/// Clones share the same time, so that a test can advance the clock of a rule it
+/// has handed out.
+#[derive(Debug, Clone)]
+pub struct MockClock(Arc<AtomicU64>);
+
+impl MockClock {
+    /// Creates a new [`MockClock`] stopped at the current system time.
+    ///
+    /// Starting from the system time keeps expirations set by rules in the future.
+    pub fn new() -> Self {
+        Self::at(SystemClock.now())
+    }
+
+    /// Creates a new [`MockClock`] stopped at the given epochmillis timestamp.
+    pub fn at(now: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(now)))
+    }
+
+    /// Advances the clock by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        self.0
+            .fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
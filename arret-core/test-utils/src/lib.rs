@@ -1,5 +1,8 @@
 #[cfg(feature = "aio")]
 pub mod aio;
+pub mod clock;
+
+pub use clock::MockClock;
 
 pub fn prepare_redis_connection() -> redis::Connection {
     let client = redis::Client::open("redis://127.0.0.1:6379").expect("Failed to connect to Redis");
@@ -8,8 +11,13 @@ pub fn prepare_redis_connection() -> redis::Connection {
         .expect("Failed to get Redis connection")
 }
 
-pub fn wait(seconds: u64) {
-    std::thread::sleep(std::time::Duration::from_secs(seconds));
+#[cfg(feature = "cluster")]
+pub fn prepare_redis_cluster_connection() -> redis::cluster::ClusterConnection {
+    let client = redis::cluster::ClusterClient::new(vec!["redis://127.0.0.1:7000"])
+        .expect("Failed to connect to Redis Cluster");
+    client
+        .get_connection()
+        .expect("Failed to get Redis Cluster connection")
 }
 
 #[macro_export]
@@ -27,6 +35,25 @@ macro_rules! assert_ok {
     };
 }
 
+#[macro_export]
+macro_rules! assert_delayed {
+    ($res:expr, $limit:expr, $remaining:expr) => {
+        match $res {
+            AcquireResult::Delayed(
+                Quota {
+                    limit, remaining, ..
+                },
+                delay,
+            ) => {
+                assert_eq!(limit, $limit);
+                assert_eq!(remaining, $remaining);
+                delay
+            }
+            _ => panic!("Expected Delayed, got {:?}", $res),
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! assert_throttled {
     ($res:expr, $limit:expr, $remaining:expr) => {
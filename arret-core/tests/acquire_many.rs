@@ -0,0 +1,186 @@
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{FixedWindow, SlidingWindowCounter},
+    store::{Functions, Keyspace, Library, MemoryStore},
+};
+use redis::Commands;
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn fixed_window() {
+    let mut con = prepare_redis_connection();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let results = fixed_window.acquire_many(
+        &[
+            ("res:acquire_many:fixed_window:a", 1),
+            ("res:acquire_many:fixed_window:b", 2),
+            ("res:acquire_many:fixed_window:a", 2),
+        ],
+        &mut con,
+    );
+
+    let [a, b, c] = &results[..] else {
+        panic!("Expected three results, got {results:?}");
+    };
+
+    assert_ok!(a.clone().unwrap(), 2, 1);
+    assert_ok!(b.clone().unwrap(), 2, 0);
+    assert_throttled!(c.clone().unwrap(), 2, 1);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn fixed_window_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let results = aio::RateLimiter::acquire_many(
+            &fixed_window,
+            &[
+                ("res:acquire_many:fixed_window_async:a", 1),
+                ("res:acquire_many:fixed_window_async:b", 2),
+                ("res:acquire_many:fixed_window_async:a", 2),
+            ],
+            &mut con,
+        )
+        .await;
+
+        let [a, b, c] = &results[..] else {
+            panic!("Expected three results, got {results:?}");
+        };
+
+        assert_ok!(a.clone().unwrap(), 2, 1);
+        assert_ok!(b.clone().unwrap(), 2, 0);
+        assert_throttled!(c.clone().unwrap(), 2, 1);
+    })
+}
+
+#[test]
+fn failed_operation() {
+    let mut con = prepare_redis_connection();
+
+    // A key holding a string cannot be read as a window
+    let key = Keyspace::new().key("fixed_window", "res:acquire_many:failed_operation:a");
+    let _: () = con.set(key, "value").unwrap();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let results = fixed_window.acquire_many(
+        &[
+            ("res:acquire_many:failed_operation:a", 1),
+            ("res:acquire_many:failed_operation:b", 1),
+        ],
+        &mut con,
+    );
+
+    let [a, b] = &results[..] else {
+        panic!("Expected two results, got {results:?}");
+    };
+
+    assert!(matches!(a, Err(Error::Internal(_))));
+    assert_ok!(b.clone().unwrap(), 2, 1);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn failed_operation_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let key = Keyspace::new().key("fixed_window", "res:acquire_many:failed_operation_async:a");
+        let _: () = redis::AsyncCommands::set(&mut con, key, "value")
+            .await
+            .unwrap();
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let results = aio::RateLimiter::acquire_many(
+            &fixed_window,
+            &[
+                ("res:acquire_many:failed_operation_async:a", 1),
+                ("res:acquire_many:failed_operation_async:b", 1),
+            ],
+            &mut con,
+        )
+        .await;
+
+        let [a, b] = &results[..] else {
+            panic!("Expected two results, got {results:?}");
+        };
+
+        assert!(matches!(a, Err(Error::Internal(_))));
+        assert_ok!(b.clone().unwrap(), 2, 1);
+    })
+}
+
+#[test]
+fn functions() {
+    let mut con = prepare_redis_connection();
+
+    Library::install(&mut con).expect("Failed to install library");
+
+    let mut functions = Functions::new(&mut con);
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let results = fixed_window.acquire_many(
+        &[
+            ("res:acquire_many:functions:a", 2),
+            ("res:acquire_many:functions:a", 1),
+        ],
+        &mut functions,
+    );
+
+    let [a, b] = &results[..] else {
+        panic!("Expected two results, got {results:?}");
+    };
+
+    assert_ok!(a.clone().unwrap(), 2, 0);
+    assert_throttled!(b.clone().unwrap(), 2, 0);
+}
+
+#[test]
+fn memory() {
+    let mut store = MemoryStore::new();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let results = sliding_window_counter.acquire_many(
+        &[
+            ("res:acquire_many:memory:a", 6),
+            ("res:acquire_many:memory:a", 6),
+            ("res:acquire_many:memory:b", 6),
+        ],
+        &mut store,
+    );
+
+    let [a, b, c] = &results[..] else {
+        panic!("Expected three results, got {results:?}");
+    };
+
+    assert_ok!(a.clone().unwrap(), 10, 4);
+    assert_throttled!(b.clone().unwrap(), 10, 4);
+    assert_ok!(c.clone().unwrap(), 10, 4);
+}
+
+#[test]
+fn empty() {
+    let mut store = MemoryStore::new();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    assert!(fixed_window.acquire_many(&[], &mut store).is_empty());
+}
@@ -0,0 +1,307 @@
+#![cfg(feature = "calendar")]
+
+use std::time::Duration;
+
+use arret_core::{
+    error::Error,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{CalendarPeriod, CalendarWindow, Clock},
+    store::MemoryStore,
+};
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+fn millis(instant: &str) -> u64 {
+    DateTime::parse_from_rfc3339(instant)
+        .unwrap()
+        .timestamp_millis() as u64
+}
+
+fn local(year: i32, month: u32, day: u32, hour: u32, min: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(year, month, day)
+        .unwrap()
+        .and_hms_opt(hour, min, 0)
+        .unwrap()
+}
+
+#[test]
+fn daylight_saving_time() {
+    let mut con = prepare_redis_connection();
+
+    // Clocks in Paris go forward on March 31st, 2024, which only lasts 23 hours
+    let clock = MockClock::at(millis("2024-03-31T12:00:00+02:00"));
+    let calendar_window = CalendarWindow::new(2, CalendarPeriod::Days(1))
+        .unwrap()
+        .with_timezone(chrono_tz::Europe::Paris)
+        .with_clock(clock.clone());
+
+    let res = calendar_window
+        .acquire("res:calendar_window:daylight_saving_time", 2, &mut con)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 2, 0);
+    assert_eq!(res.quota().reset, millis("2024-04-01T00:00:00+02:00"));
+
+    let res = calendar_window
+        .acquire("res:calendar_window:daylight_saving_time", 1, &mut con)
+        .expect("Failed to acquire from calendar window");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(12 * 3600));
+
+    let res = calendar_window
+        .acquire("res:calendar_window:daylight_saving_time", 1, &mut con)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 2, 1);
+    assert_eq!(res.quota().reset, millis("2024-04-02T00:00:00+02:00"));
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn daylight_saving_time_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::at(millis("2024-03-31T12:00:00+02:00"));
+        let calendar_window = CalendarWindow::new(2, CalendarPeriod::Days(1))
+            .unwrap()
+            .with_timezone(chrono_tz::Europe::Paris)
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(
+            &calendar_window,
+            "res:calendar_window:daylight_saving_time_async",
+            2,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from calendar window");
+
+        assert_ok!(res, 2, 0);
+        assert_eq!(res.quota().reset, millis("2024-04-01T00:00:00+02:00"));
+
+        let res = aio::RateLimiter::acquire(
+            &calendar_window,
+            "res:calendar_window:daylight_saving_time_async",
+            1,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from calendar window");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(12 * 3600));
+
+        let res = aio::RateLimiter::acquire(
+            &calendar_window,
+            "res:calendar_window:daylight_saving_time_async",
+            1,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from calendar window");
+
+        assert_ok!(res, 2, 1);
+        assert_eq!(res.quota().reset, millis("2024-04-02T00:00:00+02:00"));
+    })
+}
+
+#[test]
+fn billing_cycle() {
+    let mut store = MemoryStore::new();
+
+    // A cycle starting on January 31st starts on the last day of shorter months
+    let clock = MockClock::at(millis("2024-02-10T00:00:00Z"));
+    let calendar_window = CalendarWindow::new(100, CalendarPeriod::Months(1))
+        .unwrap()
+        .with_anchor(local(2024, 1, 31, 0, 0))
+        .with_clock(clock.clone());
+
+    let res = calendar_window
+        .acquire("res:calendar_window:billing_cycle", 60, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 100, 40);
+    assert_eq!(res.quota().reset, millis("2024-02-29T00:00:00Z"));
+
+    clock.advance(Duration::from_secs(19 * 86_400));
+
+    let res = calendar_window
+        .acquire("res:calendar_window:billing_cycle", 60, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 100, 40);
+    assert_eq!(res.quota().reset, millis("2024-03-31T00:00:00Z"));
+}
+
+#[test]
+fn skipped_anchor() {
+    let mut store = MemoryStore::new();
+
+    // 02:30 does not exist in New York on March 10th, 2024, so the window starts at 03:30
+    let clock = MockClock::at(millis("2024-03-10T03:00:00-04:00"));
+    let calendar_window = CalendarWindow::new(1, CalendarPeriod::Days(1))
+        .unwrap()
+        .with_timezone(chrono_tz::America::New_York)
+        .with_anchor(local(2024, 1, 1, 2, 30))
+        .with_clock(clock.clone());
+
+    let res = calendar_window
+        .acquire("res:calendar_window:skipped_anchor", 1, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 1, 0);
+    assert_eq!(res.quota().reset, millis("2024-03-10T03:30:00-04:00"));
+
+    clock.advance(Duration::from_secs(3600));
+
+    let res = calendar_window
+        .acquire("res:calendar_window:skipped_anchor", 1, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 1, 0);
+    assert_eq!(res.quota().reset, millis("2024-03-11T02:30:00-04:00"));
+}
+
+#[test]
+fn weeks() {
+    let mut store = MemoryStore::new();
+
+    // Weeks start on Monday
+    let clock = MockClock::at(millis("2024-05-16T10:00:00Z"));
+    let calendar_window = CalendarWindow::new(10, CalendarPeriod::Weeks(1))
+        .unwrap()
+        .with_clock(clock);
+
+    let quota = calendar_window
+        .peek("res:calendar_window:weeks", &mut store)
+        .expect("Failed to peek calendar window");
+
+    assert_eq!(quota.remaining, 10);
+    assert_eq!(quota.reset, millis("2024-05-20T00:00:00Z"));
+}
+
+#[test]
+fn change_of_plan() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::at(millis("2024-05-16T10:00:00Z"));
+    let calendar_window = CalendarWindow::new(100, CalendarPeriod::Months(1))
+        .unwrap()
+        .with_clock(clock.clone());
+
+    calendar_window
+        .acquire("res:calendar_window:change_of_plan", 80, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    // The tokens left in the month are kept by the new plan, up to its capacity
+    let upgraded = CalendarWindow::new(1_000, CalendarPeriod::Months(1))
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let quota = upgraded
+        .refund(
+            "res:calendar_window:change_of_plan",
+            0,
+            clock.now(),
+            &mut store,
+        )
+        .expect("Failed to refund calendar window");
+
+    assert_eq!(quota.remaining, 20);
+    assert_eq!(quota.reset, millis("2024-06-01T00:00:00Z"));
+}
+
+#[test]
+fn late_refund() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::at(millis("2024-05-31T23:00:00Z"));
+    let calendar_window = CalendarWindow::new(100, CalendarPeriod::Months(1))
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let acquired_at = clock.now();
+    calendar_window
+        .acquire("res:calendar_window:late_refund", 60, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    clock.advance(Duration::from_secs(2 * 3600));
+
+    let res = calendar_window
+        .acquire("res:calendar_window:late_refund", 30, &mut store)
+        .expect("Failed to acquire from calendar window");
+
+    assert_ok!(res, 100, 70);
+
+    // Tokens of May are not credited to June
+    let quota = calendar_window
+        .refund(
+            "res:calendar_window:late_refund",
+            60,
+            acquired_at,
+            &mut store,
+        )
+        .expect("Failed to refund calendar window");
+
+    assert_eq!(quota.remaining, 70);
+}
+
+#[test]
+fn invalid_period() {
+    for period in [
+        CalendarPeriod::Days(0),
+        CalendarPeriod::Days(u32::MAX),
+        CalendarPeriod::Weeks(u32::MAX),
+        CalendarPeriod::Months(u32::MAX),
+        CalendarPeriod::Years(1_000_000),
+        CalendarPeriod::Years(u32::MAX),
+    ] {
+        let err = CalendarWindow::new(1, period).expect_err("Expected an invalid rule");
+
+        assert!(matches!(err, Error::InvalidRule(_)));
+    }
+}
+
+#[test]
+fn out_of_range() {
+    let mut store = MemoryStore::new();
+
+    // The current window would end after the last date chrono supports
+    let calendar_window = CalendarWindow::new(1, CalendarPeriod::Years(10_000))
+        .unwrap()
+        .with_clock(MockClock::at(
+            local(262_000, 1, 1, 0, 0).and_utc().timestamp_millis() as u64,
+        ));
+
+    let err = calendar_window
+        .acquire("res:calendar_window:out_of_range", 1, &mut store)
+        .expect_err("Expected an invalid rule");
+
+    assert!(matches!(err, Error::InvalidRule(_)));
+
+    let calendar_window = CalendarWindow::new(1, CalendarPeriod::Days(1))
+        .unwrap()
+        .with_clock(MockClock::at(u64::MAX));
+
+    let results = calendar_window.acquire_many(
+        &[
+            ("res:calendar_window:out_of_range", 1),
+            ("res:calendar_window:out_of_range", 1),
+        ],
+        &mut store,
+    );
+
+    assert!(results
+        .iter()
+        .all(|res| matches!(res, Err(Error::InvalidRule(_)))));
+}
@@ -0,0 +1,234 @@
+#![cfg(feature = "cluster")]
+
+use arret_core::{
+    concurrency_limiter::{ConcurrencyLimiter, LeaseResult},
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Concurrency, FixedWindow, TimeSource, TokenBucket},
+    store::Library,
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_cluster_connection};
+
+#[cfg(feature = "cluster-async")]
+use arret_core::{aio, store::ClusterFunctions};
+
+#[cfg(feature = "cluster-async")]
+use test_utils::aio::{block_on, prepare_redis_cluster_async_connection};
+
+#[test]
+fn fixed_window() {
+    let mut con = prepare_redis_cluster_connection();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = fixed_window
+        .acquire("res:cluster:fixed_window", 2, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 0);
+
+    let res = fixed_window
+        .acquire("res:cluster:fixed_window", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "cluster-async")]
+#[test]
+fn fixed_window_async() {
+    block_on(async {
+        let mut con = prepare_redis_cluster_async_connection().await;
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res =
+            aio::RateLimiter::acquire(&fixed_window, "res:cluster:fixed_window_async", 2, &mut con)
+                .await
+                .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 0);
+
+        let res =
+            aio::RateLimiter::acquire(&fixed_window, "res:cluster:fixed_window_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
+
+#[test]
+fn token_bucket_server_time() {
+    let mut con = prepare_redis_cluster_connection();
+
+    let token_bucket = TokenBucket::new(2, Interval::from_secs(10).unwrap(), 1)
+        .unwrap()
+        .with_time_source(TimeSource::Server);
+
+    let res = token_bucket
+        .acquire("res:cluster:token_bucket_server_time", 2, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+
+    let res = token_bucket
+        .acquire("res:cluster:token_bucket_server_time", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "cluster-async")]
+#[test]
+fn token_bucket_server_time_async() {
+    block_on(async {
+        let mut con = prepare_redis_cluster_async_connection().await;
+
+        let token_bucket = TokenBucket::new(2, Interval::from_secs(10).unwrap(), 1)
+            .unwrap()
+            .with_time_source(TimeSource::Server);
+
+        let res = aio::RateLimiter::acquire(
+            &token_bucket,
+            "res:cluster:token_bucket_server_time_async",
+            2,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(
+            &token_bucket,
+            "res:cluster:token_bucket_server_time_async",
+            1,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
+
+/// `FUNCTION LOAD` is sent to every primary, so the library serves every slot.
+#[test]
+fn library() {
+    let mut con = prepare_redis_cluster_connection();
+
+    Library::install(&mut con).expect("Failed to install library");
+    Library::check(&mut con).expect("Failed to check library");
+}
+
+/// Calls are routed by the slot of their keys, for resources spread over several slots.
+#[cfg(feature = "cluster-async")]
+#[test]
+fn library_async() {
+    block_on(async {
+        let mut con = prepare_redis_cluster_async_connection().await;
+
+        Library::install_async(&mut con)
+            .await
+            .expect("Failed to install library");
+        Library::check_cluster_async(&mut con)
+            .await
+            .expect("Failed to check library");
+
+        let mut functions = ClusterFunctions::new(con);
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        for resource in ["res:cluster:library_async:a", "res:cluster:library_async:b"] {
+            let res = aio::RateLimiter::acquire(&fixed_window, resource, 2, &mut functions)
+                .await
+                .expect("Failed to acquire from fixed window");
+
+            assert_ok!(res, 2, 0);
+        }
+
+        let res = aio::RateLimiter::acquire_many(
+            &fixed_window,
+            &[
+                ("res:cluster:library_async:a", 1),
+                ("res:cluster:library_async:b", 1),
+            ],
+            &mut functions,
+        )
+        .await;
+
+        for res in res {
+            let res = res.expect("Failed to acquire from fixed window");
+
+            assert_throttled!(res, 2, 0);
+        }
+    })
+}
+
+/// The concurrency rule keeps a lease set and a sequence per resource, which must share
+/// a slot to be accessed by a single script.
+#[test]
+fn concurrency() {
+    let mut con = prepare_redis_cluster_connection();
+
+    let concurrency = Concurrency::new(1, Interval::from_secs(10).unwrap()).unwrap();
+
+    let lease = match concurrency
+        .acquire("res:cluster:concurrency", &mut con)
+        .expect("Failed to acquire from concurrency")
+    {
+        LeaseResult::Ok(lease, _) => lease,
+        res => panic!("Expected Ok, got {:?}", res),
+    };
+
+    let res = concurrency
+        .acquire("res:cluster:concurrency", &mut con)
+        .expect("Failed to acquire from concurrency");
+
+    assert!(matches!(res, LeaseResult::Throttled(_)));
+
+    let released = concurrency
+        .release(&lease, &mut con)
+        .expect("Failed to release from concurrency");
+
+    assert!(released);
+}
+
+#[cfg(feature = "cluster-async")]
+#[test]
+fn concurrency_async() {
+    block_on(async {
+        let mut con = prepare_redis_cluster_async_connection().await;
+
+        let concurrency = Concurrency::new(1, Interval::from_secs(10).unwrap()).unwrap();
+
+        let lease = match aio::ConcurrencyLimiter::acquire(
+            &concurrency,
+            "res:cluster:concurrency_async",
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from concurrency")
+        {
+            LeaseResult::Ok(lease, _) => lease,
+            res => panic!("Expected Ok, got {:?}", res),
+        };
+
+        let res = aio::ConcurrencyLimiter::acquire(
+            &concurrency,
+            "res:cluster:concurrency_async",
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from concurrency");
+
+        assert!(matches!(res, LeaseResult::Throttled(_)));
+
+        let released = aio::ConcurrencyLimiter::release(&concurrency, &lease, &mut con)
+            .await
+            .expect("Failed to release from concurrency");
+
+        assert!(released);
+    })
+}
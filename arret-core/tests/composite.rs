@@ -0,0 +1,318 @@
+use std::time::Duration;
+
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Clock, Composite, CompositeRule, FixedWindow, SystemClock, TimeSource, TokenBucket},
+    store::{Keyspace, MemoryStore},
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+fn burst() -> CompositeRule {
+    TokenBucket::new(10, Interval::from_secs(1).unwrap(), 10)
+        .unwrap()
+        .into()
+}
+
+fn daily() -> CompositeRule {
+    FixedWindow::new(15, Interval::from_secs(86_400).unwrap())
+        .unwrap()
+        .into()
+}
+
+fn composite(clock: MockClock) -> Composite<MockClock> {
+    Composite::new([("burst", burst()), ("daily", daily())])
+        .unwrap()
+        .with_clock(clock)
+}
+
+#[test]
+fn composite_rules() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+    let composite = composite(clock.clone());
+
+    let (res, quotas) = composite
+        .acquire_each("res:composite:composite_rules", 10, &mut con)
+        .expect("Failed to acquire from composite");
+
+    assert_ok!(res, 10, 0);
+    assert_eq!(quotas[1].remaining, 5);
+
+    // The token bucket throttles the request, so nothing is taken from the fixed window
+    let (res, quotas) = composite
+        .acquire_each("res:composite:composite_rules", 5, &mut con)
+        .expect("Failed to acquire from composite");
+
+    assert_throttled!(res, 10, 0);
+    assert_eq!(quotas[1].remaining, 5);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = composite
+        .acquire("res:composite:composite_rules", 5, &mut con)
+        .expect("Failed to acquire from composite");
+
+    assert_ok!(res, 15, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    // The fixed window throttles the request now, and binds it until the next day
+    let (res, quotas) = composite
+        .acquire_each("res:composite:composite_rules", 1, &mut con)
+        .expect("Failed to acquire from composite");
+
+    assert_throttled!(res, 15, 0);
+    assert_eq!(quotas[0].remaining, 10);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn composite_rules_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+        let composite = composite(clock.clone());
+
+        let (res, quotas) = composite
+            .acquire_each_async("res:composite:composite_rules_async", 10, &mut con)
+            .await
+            .expect("Failed to acquire from composite");
+
+        assert_ok!(res, 10, 0);
+        assert_eq!(quotas[1].remaining, 5);
+
+        let (res, quotas) = composite
+            .acquire_each_async("res:composite:composite_rules_async", 5, &mut con)
+            .await
+            .expect("Failed to acquire from composite");
+
+        assert_throttled!(res, 10, 0);
+        assert_eq!(quotas[1].remaining, 5);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(
+            &composite,
+            "res:composite:composite_rules_async",
+            5,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from composite");
+
+        assert_ok!(res, 15, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let (res, quotas) = composite
+            .acquire_each_async("res:composite:composite_rules_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from composite");
+
+        assert_throttled!(res, 15, 0);
+        assert_eq!(quotas[0].remaining, 10);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+    let composite = composite(clock.clone());
+
+    let res = composite
+        .acquire("res:composite:refund", 8, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_ok!(res, 10, 2);
+
+    let quota = composite
+        .refund("res:composite:refund", 3, clock.now(), &mut store)
+        .expect("Failed to refund composite");
+
+    assert_eq!(quota.remaining, 5);
+
+    let (_, quotas) = composite
+        .acquire_each("res:composite:refund", 0, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_eq!(quotas[1].remaining, 10);
+}
+
+#[test]
+fn late_refund() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+    let composite = composite(clock.clone());
+
+    let acquired_at = clock.now();
+    composite
+        .acquire("res:composite:late_refund", 8, &mut store)
+        .expect("Failed to acquire from composite");
+
+    clock.advance(Duration::from_secs(86_400));
+
+    let (_, quotas) = composite
+        .acquire_each("res:composite:late_refund", 5, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_eq!(quotas[1].remaining, 10);
+
+    // The fixed window keeps the tokens of the previous day, while the token bucket
+    // takes them back up to its capacity
+    composite
+        .refund("res:composite:late_refund", 8, acquired_at, &mut store)
+        .expect("Failed to refund composite");
+
+    let (_, quotas) = composite
+        .acquire_each("res:composite:late_refund", 0, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_eq!(quotas[0].remaining, 10);
+    assert_eq!(quotas[1].remaining, 10);
+}
+
+#[test]
+fn same_kind() {
+    let mut store = MemoryStore::new();
+
+    // Rules of the same kind keep their own state
+    let composite = Composite::new([
+        (
+            "second",
+            FixedWindow::new(10, Interval::from_secs(1).unwrap())
+                .unwrap()
+                .into(),
+        ),
+        (
+            "minute",
+            FixedWindow::new(100, Interval::from_secs(60).unwrap())
+                .unwrap()
+                .into(),
+        ),
+    ])
+    .unwrap()
+    .with_keyspace(Keyspace::new().with_namespace("same_kind").unwrap());
+
+    let (res, quotas) = composite
+        .acquire_each("res:composite:same_kind", 4, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert!(matches!(res, AcquireResult::Ok(_)));
+    assert_eq!(quotas[0].remaining, 6);
+    assert_eq!(quotas[1].remaining, 96);
+}
+
+#[test]
+fn reordered_rules() {
+    let mut store = MemoryStore::new();
+
+    composite(MockClock::new())
+        .acquire("res:composite:reordered_rules", 8, &mut store)
+        .expect("Failed to acquire from composite");
+
+    // Rules are keyed by their name, so they keep their state in another order
+    let reordered = Composite::new([("daily", daily()), ("burst", burst())])
+        .unwrap()
+        .with_clock(MockClock::new());
+
+    let (_, quotas) = reordered
+        .acquire_each("res:composite:reordered_rules", 0, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_eq!(quotas[0].remaining, 7);
+    assert_eq!(quotas[1].remaining, 2);
+}
+
+#[test]
+fn resource_per_rule() {
+    let mut store = MemoryStore::new();
+
+    let composite = Composite::new([
+        (
+            "user",
+            FixedWindow::new(5, Interval::from_secs(60).unwrap())
+                .unwrap()
+                .into(),
+        ),
+        (
+            "tenant",
+            FixedWindow::new(8, Interval::from_secs(60).unwrap())
+                .unwrap()
+                .into(),
+        ),
+    ])
+    .unwrap();
+
+    let (res, _) = composite
+        .acquire_each_for("acme", &["alice", "acme"], 5, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_ok!(res, 5, 0);
+
+    // Bob has a limit of his own, but shares the one of the tenant with Alice
+    let (res, quotas) = composite
+        .acquire_each_for("acme", &["bob", "acme"], 4, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_throttled!(res, 8, 3);
+    assert_eq!(quotas[0].remaining, 5);
+
+    let quota = composite
+        .refund_for("acme", &["alice", "acme"], 2, SystemClock.now(), &mut store)
+        .expect("Failed to refund composite");
+
+    assert_eq!(quota.remaining, 2);
+
+    // The tenant is also the resource of the whole composite
+    let (_, quotas) = composite
+        .acquire_each("acme", 0, &mut store)
+        .expect("Failed to acquire from composite");
+
+    assert_eq!(quotas[1].remaining, 5);
+
+    let err = composite
+        .acquire_each_for("acme", &["alice"], 1, &mut store)
+        .expect_err("Expected an invalid path");
+
+    assert!(matches!(err, Error::InvalidPath(_)));
+}
+
+#[test]
+fn invalid_rules() {
+    let no_rule: [(&str, CompositeRule); 0] = [];
+    let err = Composite::new(no_rule).expect_err("Expected an invalid rule");
+
+    assert!(matches!(err, Error::InvalidRule(_)));
+
+    let server_time = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 10)
+        .unwrap()
+        .with_time_source(TimeSource::Server);
+    let namespaced = FixedWindow::new(15, Interval::from_secs(60).unwrap())
+        .unwrap()
+        .with_keyspace(Keyspace::new().with_namespace("namespaced").unwrap());
+
+    for rules in [
+        vec![("", burst())],
+        vec![("{burst}", burst())],
+        vec![("burst", burst()), ("burst", daily())],
+        vec![("burst", server_time.into())],
+        vec![("daily", namespaced.into())],
+    ] {
+        let err = Composite::new(rules).expect_err("Expected an invalid rule");
+
+        assert!(matches!(err, Error::InvalidRule(_)));
+    }
+}
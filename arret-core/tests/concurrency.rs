@@ -0,0 +1,519 @@
+use std::time::Duration;
+
+use arret_core::{
+    concurrency_limiter::{ConcurrencyLimiter, Lease, LeaseResult},
+    interval::Interval,
+    rate_limiter::Quota,
+    rule::Concurrency,
+    store::MemoryStore,
+};
+use test_utils::{prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+fn expect_lease(res: LeaseResult, limit: u64, remaining: u64) -> Lease {
+    match res {
+        LeaseResult::Ok(lease, quota) => {
+            assert_eq!(quota.limit, limit);
+            assert_eq!(quota.remaining, remaining);
+            lease
+        }
+        _ => panic!("Expected Ok, got {res:?}"),
+    }
+}
+
+fn expect_throttled(res: LeaseResult, limit: u64) {
+    match res {
+        LeaseResult::Throttled(Quota {
+            limit: l,
+            remaining,
+            ..
+        }) => {
+            assert_eq!(l, limit);
+            assert_eq!(remaining, 0);
+        }
+        _ => panic!("Expected Throttled, got {res:?}"),
+    }
+}
+
+#[test]
+fn single_lease() {
+    let mut con = prepare_redis_connection();
+
+    let concurrency = Concurrency::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = concurrency
+        .acquire("res:single_lease", &mut con)
+        .expect("Failed to acquire from concurrency");
+
+    let lease = expect_lease(res, 10, 9);
+    assert_eq!(lease.resource(), "res:single_lease");
+
+    let released = concurrency
+        .release(&lease, &mut con)
+        .expect("Failed to release from concurrency");
+
+    assert!(released);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn single_lease_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let concurrency = Concurrency::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res =
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:single_lease_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency");
+
+        let lease = expect_lease(res, 10, 9);
+        assert_eq!(lease.resource(), "res:single_lease_async");
+
+        let released = aio::ConcurrencyLimiter::release(&concurrency, &lease, &mut con)
+            .await
+            .expect("Failed to release from concurrency");
+
+        assert!(released);
+    })
+}
+
+#[test]
+fn zero_capacity() {
+    let mut con = prepare_redis_connection();
+
+    let concurrency = Concurrency::new(0, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = concurrency
+        .acquire("res:zero_capacity", &mut con)
+        .expect("Failed to acquire from concurrency");
+
+    expect_throttled(res, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn zero_capacity_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let concurrency = Concurrency::new(0, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res =
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:zero_capacity_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency");
+
+        expect_throttled(res, 0);
+    })
+}
+
+#[test]
+fn release() {
+    let mut con = prepare_redis_connection();
+
+    let concurrency = Concurrency::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let first = expect_lease(
+        concurrency
+            .acquire("res:release", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        2,
+        1,
+    );
+
+    let second = expect_lease(
+        concurrency
+            .acquire("res:release", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        2,
+        0,
+    );
+
+    assert_ne!(first.id(), second.id());
+
+    expect_throttled(
+        concurrency
+            .acquire("res:release", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        2,
+    );
+
+    assert!(concurrency
+        .release(&first, &mut con)
+        .expect("Failed to release from concurrency"));
+    assert!(!concurrency
+        .release(&first, &mut con)
+        .expect("Failed to release from concurrency"));
+
+    let _ = expect_lease(
+        concurrency
+            .acquire("res:release", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        2,
+        0,
+    );
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn release_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let concurrency = Concurrency::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let first = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:release_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            2,
+            1,
+        );
+
+        let _ = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:release_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            2,
+            0,
+        );
+
+        expect_throttled(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:release_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            2,
+        );
+
+        assert!(
+            aio::ConcurrencyLimiter::release(&concurrency, &first, &mut con)
+                .await
+                .expect("Failed to release from concurrency")
+        );
+
+        let _ = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:release_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            2,
+            0,
+        );
+    })
+}
+
+#[test]
+fn expire() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let _ = expect_lease(
+        concurrency
+            .acquire("res:expire", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+
+    expect_throttled(
+        concurrency
+            .acquire("res:expire", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        1,
+    );
+
+    clock.advance(Duration::from_secs(1));
+
+    let _ = expect_lease(
+        concurrency
+            .acquire("res:expire", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn expire_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let _ = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:expire_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+            0,
+        );
+
+        expect_throttled(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:expire_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+        );
+
+        clock.advance(Duration::from_secs(1));
+
+        let _ = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:expire_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+            0,
+        );
+    })
+}
+
+#[test]
+fn extend() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let lease = expect_lease(
+        concurrency
+            .acquire("res:extend", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+
+    clock.advance(Duration::from_millis(500));
+
+    let extended = concurrency
+        .extend(&lease, &mut con)
+        .expect("Failed to extend from concurrency")
+        .expect("Expected the lease to be extended");
+
+    assert_eq!(extended.id(), lease.id());
+    assert_eq!(extended.expires_at(), lease.expires_at() + 500);
+
+    // The lease is still held past its original expiration
+    clock.advance(Duration::from_millis(700));
+
+    expect_throttled(
+        concurrency
+            .acquire("res:extend", &mut con)
+            .expect("Failed to acquire from concurrency"),
+        1,
+    );
+
+    assert!(concurrency
+        .release(&extended, &mut con)
+        .expect("Failed to release from concurrency"));
+
+    // Released leases are not granted again
+    assert!(concurrency
+        .extend(&lease, &mut con)
+        .expect("Failed to extend from concurrency")
+        .is_none());
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn extend_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let lease = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:extend_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+            0,
+        );
+
+        clock.advance(Duration::from_millis(500));
+
+        let extended = aio::ConcurrencyLimiter::extend(&concurrency, &lease, &mut con)
+            .await
+            .expect("Failed to extend from concurrency")
+            .expect("Expected the lease to be extended");
+
+        assert_eq!(extended.id(), lease.id());
+        assert_eq!(extended.expires_at(), lease.expires_at() + 500);
+
+        // The lease is still held past its original expiration
+        clock.advance(Duration::from_millis(700));
+
+        expect_throttled(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:extend_async", &mut con)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+        );
+
+        assert!(
+            aio::ConcurrencyLimiter::release(&concurrency, &extended, &mut con)
+                .await
+                .expect("Failed to release from concurrency")
+        );
+
+        // Released leases are not granted again
+        assert!(
+            aio::ConcurrencyLimiter::extend(&concurrency, &lease, &mut con)
+                .await
+                .expect("Failed to extend from concurrency")
+                .is_none()
+        );
+    })
+}
+
+#[test]
+fn extend_expired() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let lease = expect_lease(
+        concurrency
+            .acquire("res:extend_expired", &mut store)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+
+    clock.advance(Duration::from_secs(1));
+
+    // Expired leases may have been granted to another operation already
+    assert!(concurrency
+        .extend(&lease, &mut store)
+        .expect("Failed to extend from concurrency")
+        .is_none());
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let lease = expect_lease(
+        concurrency
+            .acquire("res:memory_store", &mut store)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+
+    expect_throttled(
+        concurrency
+            .acquire("res:memory_store", &mut store)
+            .expect("Failed to acquire from concurrency"),
+        1,
+    );
+
+    assert!(concurrency
+        .release(&lease, &mut store)
+        .expect("Failed to release from concurrency"));
+
+    let _ = expect_lease(
+        concurrency
+            .acquire("res:memory_store", &mut store)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+
+    // Leases which are not released expire
+    clock.advance(Duration::from_secs(1));
+
+    let _ = expect_lease(
+        concurrency
+            .acquire("res:memory_store", &mut store)
+            .expect("Failed to acquire from concurrency"),
+        1,
+        0,
+    );
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let concurrency = Concurrency::new(1, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let lease = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:memory_store_async", &mut store)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+            0,
+        );
+
+        expect_throttled(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:memory_store_async", &mut store)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+        );
+
+        assert!(
+            aio::ConcurrencyLimiter::release(&concurrency, &lease, &mut store)
+                .await
+                .expect("Failed to release from concurrency")
+        );
+
+        let _ = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:memory_store_async", &mut store)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+            0,
+        );
+
+        // Leases which are not released expire
+        clock.advance(Duration::from_secs(1));
+
+        let _ = expect_lease(
+            aio::ConcurrencyLimiter::acquire(&concurrency, "res:memory_store_async", &mut store)
+                .await
+                .expect("Failed to acquire from concurrency"),
+            1,
+            0,
+        );
+    })
+}
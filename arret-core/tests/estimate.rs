@@ -0,0 +1,294 @@
+use std::time::Duration;
+
+use arret_core::{
+    estimating_limiter::{EstimateResult, EstimatingLimiter},
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{FixedWindow, TokenBucket},
+    store::MemoryStore,
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn token_bucket() {
+    let mut con = prepare_redis_connection();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(10).unwrap(), 1)
+        .unwrap()
+        .with_clock(MockClock::new());
+
+    let res = token_bucket
+        .acquire_estimate("res:estimate:token_bucket", 6, &mut con)
+        .expect("Failed to acquire estimate from token bucket");
+
+    let EstimateResult::Ok(estimate, quota) = res else {
+        panic!("Expected an estimate, got {res:?}");
+    };
+
+    assert_eq!(quota.remaining, 4);
+
+    // The estimate was too high, so the difference is refunded
+    let committed = token_bucket
+        .commit(estimate, 2, &mut con)
+        .expect("Failed to commit estimate to token bucket");
+
+    assert!(committed);
+
+    let res = token_bucket
+        .acquire("res:estimate:token_bucket", 4, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 4);
+
+    // The estimate was too low, so the difference is charged beyond the tokens left
+    let res = token_bucket
+        .acquire_estimate("res:estimate:token_bucket", 2, &mut con)
+        .expect("Failed to acquire estimate from token bucket");
+
+    let EstimateResult::Ok(estimate, _) = res else {
+        panic!("Expected an estimate, got {res:?}");
+    };
+
+    let committed = token_bucket
+        .commit(estimate, 8, &mut con)
+        .expect("Failed to commit estimate to token bucket");
+
+    assert!(committed);
+
+    let res = token_bucket
+        .acquire("res:estimate:token_bucket", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn token_bucket_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let token_bucket = TokenBucket::new(10, Interval::from_secs(10).unwrap(), 1)
+            .unwrap()
+            .with_clock(MockClock::new());
+
+        let res = aio::EstimatingLimiter::acquire_estimate(
+            &token_bucket,
+            "res:estimate:token_bucket_async",
+            6,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire estimate from token bucket");
+
+        let EstimateResult::Ok(estimate, quota) = res else {
+            panic!("Expected an estimate, got {res:?}");
+        };
+
+        assert_eq!(quota.remaining, 4);
+
+        let committed = aio::EstimatingLimiter::commit(&token_bucket, estimate, 2, &mut con)
+            .await
+            .expect("Failed to commit estimate to token bucket");
+
+        assert!(committed);
+
+        let res = aio::RateLimiter::acquire(
+            &token_bucket,
+            "res:estimate:token_bucket_async",
+            4,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 4);
+
+        let res = aio::EstimatingLimiter::acquire_estimate(
+            &token_bucket,
+            "res:estimate:token_bucket_async",
+            2,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire estimate from token bucket");
+
+        let EstimateResult::Ok(estimate, _) = res else {
+            panic!("Expected an estimate, got {res:?}");
+        };
+
+        let committed = aio::EstimatingLimiter::commit(&token_bucket, estimate, 8, &mut con)
+            .await
+            .expect("Failed to commit estimate to token bucket");
+
+        assert!(committed);
+
+        let res = aio::RateLimiter::acquire(
+            &token_bucket,
+            "res:estimate:token_bucket_async",
+            1,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
+
+#[test]
+fn fixed_window() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let fixed_window = FixedWindow::new(10, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = fixed_window
+        .acquire_estimate("res:estimate:fixed_window", 6, &mut con)
+        .expect("Failed to acquire estimate from fixed window");
+
+    let EstimateResult::Ok(estimate, quota) = res else {
+        panic!("Expected an estimate, got {res:?}");
+    };
+
+    assert_eq!(quota.remaining, 4);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = fixed_window
+        .acquire("res:estimate:fixed_window", 8, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 2);
+
+    // The estimate was acquired in the previous window, which is not credited to this one
+    let committed = fixed_window
+        .commit(estimate, 0, &mut con)
+        .expect("Failed to commit estimate to fixed window");
+
+    assert!(committed);
+
+    let res = fixed_window
+        .acquire("res:estimate:fixed_window", 3, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 10, 2);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn fixed_window_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let fixed_window = FixedWindow::new(10, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::EstimatingLimiter::acquire_estimate(
+            &fixed_window,
+            "res:estimate:fixed_window_async",
+            6,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire estimate from fixed window");
+
+        let EstimateResult::Ok(estimate, quota) = res else {
+            panic!("Expected an estimate, got {res:?}");
+        };
+
+        assert_eq!(quota.remaining, 4);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(
+            &fixed_window,
+            "res:estimate:fixed_window_async",
+            8,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 2);
+
+        let committed = aio::EstimatingLimiter::commit(&fixed_window, estimate, 0, &mut con)
+            .await
+            .expect("Failed to commit estimate to fixed window");
+
+        assert!(committed);
+
+        let res = aio::RateLimiter::acquire(
+            &fixed_window,
+            "res:estimate:fixed_window_async",
+            3,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 10, 2);
+    })
+}
+
+#[test]
+fn throttled() {
+    let mut store = MemoryStore::new();
+
+    let fixed_window = FixedWindow::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = fixed_window
+        .acquire_estimate("res:estimate:throttled", 11, &mut store)
+        .expect("Failed to acquire estimate from fixed window");
+
+    assert!(matches!(res, EstimateResult::Throttled(quota) if quota.remaining == 10));
+}
+
+#[test]
+fn expired() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(60).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone())
+        .with_estimate_timeout(Interval::from_secs(1).unwrap());
+
+    let res = token_bucket
+        .acquire_estimate("res:estimate:expired", 6, &mut store)
+        .expect("Failed to acquire estimate from token bucket");
+
+    let EstimateResult::Ok(estimate, _) = res else {
+        panic!("Expected an estimate, got {res:?}");
+    };
+
+    assert_eq!(estimate.expires_at(), estimate.acquired_at() + 1_000);
+
+    clock.advance(Duration::from_secs(1));
+
+    // The estimate settled as estimated, so nothing is refunded
+    let committed = token_bucket
+        .commit(estimate, 0, &mut store)
+        .expect("Failed to commit estimate to token bucket");
+
+    assert!(!committed);
+
+    let quota = token_bucket
+        .peek("res:estimate:expired", &mut store)
+        .expect("Failed to peek token bucket");
+
+    assert_eq!(quota.remaining, 4);
+}
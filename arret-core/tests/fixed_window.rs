@@ -1,9 +1,12 @@
+use std::time::{Duration, SystemTime};
+
 use arret_core::{
     interval::Interval,
     rate_limiter::{AcquireResult, Quota, RateLimiter},
-    rule::FixedWindow,
+    rule::{Clock, FixedWindow, TimeSource},
+    store::{Keyspace, MemoryStore},
 };
-use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, wait};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
 
 #[cfg(feature = "aio")]
 use arret_core::aio;
@@ -143,7 +146,11 @@ fn throttled_async() {
 fn next_window() {
     let mut con = prepare_redis_connection();
 
-    let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap()).unwrap();
+    let clock = MockClock::new();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
 
     let res = fixed_window
         .acquire("res:next_window", 1, &mut con)
@@ -163,7 +170,7 @@ fn next_window() {
 
     assert_throttled!(res, 2, 0);
 
-    wait(1);
+    clock.advance(Duration::from_secs(1));
 
     let res = fixed_window
         .acquire("res:next_window", 1, &mut con)
@@ -175,12 +182,14 @@ fn next_window() {
 #[cfg(feature = "aio")]
 #[test]
 fn next_window_async() {
-    use std::time::Duration;
-
     block_on(async {
         let mut con = prepare_redis_async_connection().await;
 
-        let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap()).unwrap();
+        let clock = MockClock::new();
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
 
         let res = aio::RateLimiter::acquire(&fixed_window, "res:next_window_async", 1, &mut con)
             .await
@@ -200,7 +209,7 @@ fn next_window_async() {
 
         assert_throttled!(res, 2, 0);
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
+        clock.advance(Duration::from_secs(1));
 
         let res = aio::RateLimiter::acquire(&fixed_window, "res:next_window_async", 1, &mut con)
             .await
@@ -209,3 +218,418 @@ fn next_window_async() {
         assert_ok!(res, 2, 1);
     })
 }
+
+#[test]
+fn reset() {
+    let mut con = prepare_redis_connection();
+
+    let fixed_window = FixedWindow::new(1, Interval::from_secs(10).unwrap()).unwrap();
+
+    let now = SystemTime::now();
+
+    let res = fixed_window
+        .acquire("res:reset", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 1, 0);
+
+    let res = fixed_window
+        .acquire("res:reset", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    match res {
+        AcquireResult::Throttled(quota) => {
+            assert!(quota.reset_at() > now);
+            assert!(quota.retry_after(now) <= Duration::from_secs(10));
+        }
+        _ => panic!("Expected Throttled, got {:?}", res),
+    }
+}
+
+#[test]
+fn server_time() {
+    let mut con = prepare_redis_connection();
+
+    let fixed_window = FixedWindow::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_time_source(TimeSource::Server);
+
+    let res = fixed_window
+        .acquire("res:server_time", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn server_time_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let fixed_window = FixedWindow::new(10, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_time_source(TimeSource::Server);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:server_time_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn preload() {
+    let mut con = prepare_redis_connection();
+
+    let fixed_window = FixedWindow::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    fixed_window
+        .preload(&mut con)
+        .expect("Failed to preload fixed window");
+
+    let res = fixed_window
+        .acquire("res:preload", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 9);
+
+    // The script is uploaded again once Redis has forgotten it
+    redis::cmd("SCRIPT")
+        .arg("FLUSH")
+        .query::<()>(&mut con)
+        .expect("Failed to flush scripts");
+
+    let res = fixed_window
+        .acquire("res:preload", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 8);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn preload_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let fixed_window = FixedWindow::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        fixed_window
+            .preload_async(&mut con)
+            .await
+            .expect("Failed to preload fixed window");
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:preload_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 9);
+
+        redis::cmd("SCRIPT")
+            .arg("FLUSH")
+            .query_async::<_, ()>(&mut con)
+            .await
+            .expect("Failed to flush scripts");
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:preload_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 8);
+    })
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = fixed_window
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 1);
+
+    let res = fixed_window
+        .acquire("res:memory_store", 1, &mut store.clone())
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 0);
+
+    let res = fixed_window
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = fixed_window
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 1);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 1);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 1);
+    })
+}
+
+#[test]
+fn peek() {
+    let mut con = prepare_redis_connection();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let quota = fixed_window
+        .peek("res:peek", &mut con)
+        .expect("Failed to peek fixed window");
+
+    assert_eq!(quota.remaining, 2);
+
+    // Peeking a resource which was never requested does not create its state
+    let exists: bool = redis::cmd("EXISTS")
+        .arg(Keyspace::new().key("fixed_window", "res:peek"))
+        .query(&mut con)
+        .expect("Failed to check key");
+
+    assert!(!exists);
+
+    let res = fixed_window
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 1);
+
+    let quota = fixed_window
+        .peek("res:peek", &mut con)
+        .expect("Failed to peek fixed window");
+
+    assert_eq!(quota.remaining, 1);
+
+    let res = fixed_window
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let quota = aio::RateLimiter::peek(&fixed_window, "res:peek_async", &mut con)
+            .await
+            .expect("Failed to peek fixed window");
+
+        assert_eq!(quota.remaining, 2);
+
+        // Peeking a resource which was never requested does not create its state
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(Keyspace::new().key("fixed_window", "res:peek_async"))
+            .query_async(&mut con)
+            .await
+            .expect("Failed to check key");
+
+        assert!(!exists);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 1);
+
+        let quota = aio::RateLimiter::peek(&fixed_window, "res:peek_async", &mut con)
+            .await
+            .expect("Failed to peek fixed window");
+
+        assert_eq!(quota.remaining, 1);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 0);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let fixed_window = FixedWindow::new(10, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = fixed_window
+        .acquire("res:refund", 8, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 2);
+
+    let quota = fixed_window
+        .refund("res:refund", 3, clock.now(), &mut con)
+        .expect("Failed to refund fixed window");
+
+    assert_eq!(quota.remaining, 5);
+
+    // Refunds are clamped to the capacity
+    let quota = fixed_window
+        .refund("res:refund", 20, clock.now(), &mut con)
+        .expect("Failed to refund fixed window");
+
+    assert_eq!(quota.remaining, 10);
+
+    let acquired_at = clock.now();
+    let res = fixed_window
+        .acquire("res:refund", 8, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 2);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = fixed_window
+        .acquire("res:refund", 4, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 6);
+
+    // Tokens of a past window are not credited to the next one
+    let quota = fixed_window
+        .refund("res:refund", 8, acquired_at, &mut con)
+        .expect("Failed to refund fixed window");
+
+    assert_eq!(quota.remaining, 6);
+
+    let res = fixed_window
+        .acquire("res:refund", 6, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 0);
+
+    let res = fixed_window
+        .acquire("res:refund", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let fixed_window = FixedWindow::new(10, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:refund_async", 8, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 2);
+
+        let quota =
+            aio::RateLimiter::refund(&fixed_window, "res:refund_async", 3, clock.now(), &mut con)
+                .await
+                .expect("Failed to refund fixed window");
+
+        assert_eq!(quota.remaining, 5);
+
+        // Refunds are clamped to the capacity
+        let quota =
+            aio::RateLimiter::refund(&fixed_window, "res:refund_async", 20, clock.now(), &mut con)
+                .await
+                .expect("Failed to refund fixed window");
+
+        assert_eq!(quota.remaining, 10);
+
+        let acquired_at = clock.now();
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:refund_async", 8, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 2);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:refund_async", 4, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 6);
+
+        // Tokens of a past window are not credited to the next one
+        let quota =
+            aio::RateLimiter::refund(&fixed_window, "res:refund_async", 8, acquired_at, &mut con)
+                .await
+                .expect("Failed to refund fixed window");
+
+        assert_eq!(quota.remaining, 6);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:refund_async", 6, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:refund_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
@@ -0,0 +1,170 @@
+use arret_core::{
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{FixedWindow, TimeSource, TokenBucket},
+    store::{Functions, Library},
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn install() {
+    let mut con = prepare_redis_connection();
+
+    Library::install(&mut con).expect("Failed to install library");
+
+    let version = Library::installed_version(&mut con).expect("Failed to read library version");
+
+    assert_eq!(version.as_deref(), Some(Library::version()));
+    assert_eq!(Library::check(&mut con), Ok(()));
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn install_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        Library::install_async(&mut con)
+            .await
+            .expect("Failed to install library");
+
+        let version = Library::installed_version_async(&mut con)
+            .await
+            .expect("Failed to read library version");
+
+        assert_eq!(version.as_deref(), Some(Library::version()));
+        assert_eq!(Library::check_async(&mut con).await, Ok(()));
+    })
+}
+
+#[test]
+fn fixed_window() {
+    let mut con = prepare_redis_connection();
+
+    Library::install(&mut con).expect("Failed to install library");
+
+    let mut functions = Functions::new(&mut con);
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = fixed_window
+        .acquire("res:functions:fixed_window", 2, &mut functions)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 0);
+
+    let res = fixed_window
+        .acquire("res:functions:fixed_window", 1, &mut functions)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn fixed_window_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        Library::install_async(&mut con)
+            .await
+            .expect("Failed to install library");
+
+        let mut functions = Functions::new(con);
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(
+            &fixed_window,
+            "res:functions:fixed_window_async",
+            2,
+            &mut functions,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(
+            &fixed_window,
+            "res:functions:fixed_window_async",
+            1,
+            &mut functions,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
+
+#[test]
+fn token_bucket_server_time() {
+    let mut con = prepare_redis_connection();
+
+    Library::install(&mut con).expect("Failed to install library");
+
+    let mut functions = Functions::new(&mut con);
+
+    let token_bucket = TokenBucket::new(2, Interval::from_secs(10).unwrap(), 1)
+        .unwrap()
+        .with_time_source(TimeSource::Server);
+
+    let res = token_bucket
+        .acquire("res:functions:token_bucket_server_time", 2, &mut functions)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+
+    let res = token_bucket
+        .acquire("res:functions:token_bucket_server_time", 1, &mut functions)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn token_bucket_server_time_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        Library::install_async(&mut con)
+            .await
+            .expect("Failed to install library");
+
+        let mut functions = Functions::new(con);
+
+        let token_bucket = TokenBucket::new(2, Interval::from_secs(10).unwrap(), 1)
+            .unwrap()
+            .with_time_source(TimeSource::Server);
+
+        let res = aio::RateLimiter::acquire(
+            &token_bucket,
+            "res:functions:token_bucket_server_time_async",
+            2,
+            &mut functions,
+        )
+        .await
+        .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(
+            &token_bucket,
+            "res:functions:token_bucket_server_time_async",
+            1,
+            &mut functions,
+        )
+        .await
+        .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
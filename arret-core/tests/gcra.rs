@@ -0,0 +1,447 @@
+use std::time::Duration;
+
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Clock, Gcra},
+    store::MemoryStore,
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn single_token() {
+    let mut con = prepare_redis_connection();
+
+    let gcra = Gcra::new(10, Interval::from_secs(100).unwrap()).unwrap();
+
+    let res = gcra
+        .acquire("res:single_token", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn single_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let gcra = Gcra::new(10, Interval::from_secs(100).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:single_token_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn multiple_token() {
+    let mut con = prepare_redis_connection();
+
+    let gcra = Gcra::new(10, Interval::from_secs(100).unwrap()).unwrap();
+
+    let res = gcra
+        .acquire("res:multiple_token", 5, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 5);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn multiple_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let gcra = Gcra::new(10, Interval::from_secs(100).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:multiple_token_async", 5, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 5);
+    })
+}
+
+#[test]
+fn zero_capacity() {
+    let gcra = Gcra::new(0, Interval::from_secs(10).unwrap());
+
+    assert!(matches!(gcra, Err(Error::InvalidRule(_))));
+}
+
+#[test]
+fn throttled() {
+    let mut con = prepare_redis_connection();
+
+    let gcra = Gcra::new(10, Interval::from_secs(100).unwrap()).unwrap();
+
+    for i in 0..5 {
+        let res = gcra
+            .acquire("res:throttled", 3, &mut con)
+            .expect("Failed to acquire from GCRA");
+
+        if i < 3 {
+            assert_ok!(res, 10, 7 - i * 3);
+        } else {
+            assert_throttled!(res, 10, 1);
+        }
+    }
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn throttled_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let gcra = Gcra::new(10, Interval::from_secs(100).unwrap()).unwrap();
+
+        for i in 0..5 {
+            let res = aio::RateLimiter::acquire(&gcra, "res:throttled_async", 3, &mut con)
+                .await
+                .expect("Failed to acquire from GCRA");
+
+            if i < 3 {
+                assert_ok!(res, 10, 7 - i * 3);
+            } else {
+                assert_throttled!(res, 10, 1);
+            }
+        }
+    })
+}
+
+#[test]
+fn pacing() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let gcra = Gcra::new(2, Interval::from_secs(2).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = gcra
+        .acquire("res:pacing", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 2, 1);
+
+    let res = gcra
+        .acquire("res:pacing", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 2, 0);
+
+    let res = gcra
+        .acquire("res:pacing", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = gcra
+        .acquire("res:pacing", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 2, 0);
+
+    let res = gcra
+        .acquire("res:pacing", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn pacing_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let gcra = Gcra::new(2, Interval::from_secs(2).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:pacing_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 2, 1);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:pacing_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:pacing_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:pacing_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:pacing_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
+
+#[test]
+fn peek() {
+    let mut con = prepare_redis_connection();
+
+    let gcra = Gcra::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = gcra
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 2, 1);
+
+    for _ in 0..2 {
+        let quota = gcra
+            .peek("res:peek", &mut con)
+            .expect("Failed to peek GCRA");
+
+        assert_eq!(quota.remaining, 1);
+    }
+
+    let res = gcra
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let gcra = Gcra::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 2, 1);
+
+        for _ in 0..2 {
+            let quota = aio::RateLimiter::peek(&gcra, "res:peek_async", &mut con)
+                .await
+                .expect("Failed to peek GCRA");
+
+            assert_eq!(quota.remaining, 1);
+        }
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 2, 0);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let gcra = Gcra::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = gcra
+        .acquire("res:refund", 6, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = gcra
+        .refund("res:refund", 3, clock.now(), &mut con)
+        .expect("Failed to refund GCRA");
+
+    assert_eq!(quota.remaining, 7);
+
+    // Refunds are clamped to the capacity
+    let quota = gcra
+        .refund("res:refund", 20, clock.now(), &mut con)
+        .expect("Failed to refund GCRA");
+
+    assert_eq!(quota.remaining, 10);
+
+    let res = gcra
+        .acquire("res:refund", 10, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 0);
+
+    let res = gcra
+        .acquire("res:refund", 1, &mut con)
+        .expect("Failed to acquire from GCRA");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let gcra = Gcra::new(10, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:refund_async", 6, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 4);
+
+        let quota = aio::RateLimiter::refund(&gcra, "res:refund_async", 3, clock.now(), &mut con)
+            .await
+            .expect("Failed to refund GCRA");
+
+        assert_eq!(quota.remaining, 7);
+
+        // Refunds are clamped to the capacity
+        let quota = aio::RateLimiter::refund(&gcra, "res:refund_async", 20, clock.now(), &mut con)
+            .await
+            .expect("Failed to refund GCRA");
+
+        assert_eq!(quota.remaining, 10);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:refund_async", 10, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:refund_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let gcra = Gcra::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = gcra
+        .acquire("res:memory_store", 6, &mut store)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = gcra
+        .refund("res:memory_store", 3, clock.now(), &mut store)
+        .expect("Failed to refund GCRA");
+
+    assert_eq!(quota.remaining, 7);
+
+    let res = gcra
+        .acquire("res:memory_store", 7, &mut store)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 0);
+
+    let res = gcra
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from GCRA");
+
+    assert_throttled!(res, 10, 0);
+
+    // The tokens are replenished once the period has elapsed
+    clock.advance(Duration::from_secs(10));
+
+    let res = gcra
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from GCRA");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let gcra = Gcra::new(10, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:memory_store_async", 6, &mut store)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 4);
+
+        let quota =
+            aio::RateLimiter::refund(&gcra, "res:memory_store_async", 3, clock.now(), &mut store)
+                .await
+                .expect("Failed to refund GCRA");
+
+        assert_eq!(quota.remaining, 7);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:memory_store_async", 7, &mut store)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_throttled!(res, 10, 0);
+
+        // The tokens are replenished once the period has elapsed
+        clock.advance(Duration::from_secs(10));
+
+        let res = aio::RateLimiter::acquire(&gcra, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 9);
+    })
+}
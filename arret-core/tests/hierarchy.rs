@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota},
+    rule::{Hierarchy, HierarchyLevel, TimeSource, TokenBucket},
+    store::{Keyspace, MemoryStore},
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+fn hierarchy(clock: MockClock) -> Hierarchy<MockClock> {
+    Hierarchy::new([
+        TokenBucket::new(100, Interval::from_secs(10).unwrap(), 100)
+            .unwrap()
+            .into(),
+        HierarchyLevel::Share(50),
+        TokenBucket::new(20, Interval::from_secs(1).unwrap(), 20)
+            .unwrap()
+            .into(),
+    ])
+    .unwrap()
+    .with_clock(clock)
+}
+
+#[test]
+fn nested_levels() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+    let hierarchy = hierarchy(clock.clone());
+
+    let (res, quotas) = hierarchy
+        .acquire_each(
+            &["res:hierarchy:nested_levels", "alice", "key"],
+            20,
+            &mut con,
+        )
+        .expect("Failed to acquire from hierarchy");
+
+    assert_ok!(res, 20, 0);
+    assert_eq!(quotas[0].remaining, 80);
+    assert_eq!(quotas[1].remaining, 30);
+
+    // The key is empty, so nothing is taken from the user and the tenant
+    let (res, quotas) = hierarchy
+        .acquire_each(
+            &["res:hierarchy:nested_levels", "alice", "key"],
+            1,
+            &mut con,
+        )
+        .expect("Failed to acquire from hierarchy");
+
+    assert_throttled!(res, 20, 0);
+    assert_eq!(quotas[1].remaining, 30);
+
+    clock.advance(Duration::from_secs(1));
+
+    // The key is refilled, but the user is left with less than its share
+    let res = hierarchy
+        .acquire(
+            &["res:hierarchy:nested_levels", "alice", "key"],
+            20,
+            &mut con,
+        )
+        .expect("Failed to acquire from hierarchy");
+
+    assert_ok!(res, 20, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = hierarchy
+        .acquire(
+            &["res:hierarchy:nested_levels", "alice", "key"],
+            20,
+            &mut con,
+        )
+        .expect("Failed to acquire from hierarchy");
+
+    assert_throttled!(res, 50, 10);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn nested_levels_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+        let hierarchy = hierarchy(clock.clone());
+
+        let (res, quotas) = hierarchy
+            .acquire_each_async(
+                &["res:hierarchy:nested_levels_async", "alice", "key"],
+                20,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from hierarchy");
+
+        assert_ok!(res, 20, 0);
+        assert_eq!(quotas[0].remaining, 80);
+        assert_eq!(quotas[1].remaining, 30);
+
+        let (res, quotas) = hierarchy
+            .acquire_each_async(
+                &["res:hierarchy:nested_levels_async", "alice", "key"],
+                1,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from hierarchy");
+
+        assert_throttled!(res, 20, 0);
+        assert_eq!(quotas[1].remaining, 30);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = hierarchy
+            .acquire_async(
+                &["res:hierarchy:nested_levels_async", "alice", "key"],
+                20,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from hierarchy");
+
+        assert_ok!(res, 20, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = hierarchy
+            .acquire_async(
+                &["res:hierarchy:nested_levels_async", "alice", "key"],
+                20,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from hierarchy");
+
+        assert_throttled!(res, 50, 10);
+    })
+}
+
+#[test]
+fn shared_parent() {
+    let mut store = MemoryStore::new();
+
+    let hierarchy = hierarchy(MockClock::new());
+
+    for user in ["alice", "bob"] {
+        let res = hierarchy
+            .acquire(&["res:hierarchy:shared_parent", user], 50, &mut store)
+            .expect("Failed to acquire from hierarchy");
+
+        assert!(matches!(res, AcquireResult::Ok(_)));
+    }
+
+    // Carol has her whole share, but the tenant has nothing left
+    let res = hierarchy
+        .acquire(&["res:hierarchy:shared_parent", "carol"], 1, &mut store)
+        .expect("Failed to acquire from hierarchy");
+
+    assert_throttled!(res, 100, 0);
+
+    // Users of another tenant have buckets of their own
+    let res = hierarchy
+        .acquire(&["res:hierarchy:other_tenant", "alice"], 10, &mut store)
+        .expect("Failed to acquire from hierarchy");
+
+    assert_ok!(res, 50, 40);
+}
+
+#[test]
+fn refund() {
+    let mut store = MemoryStore::new();
+
+    let hierarchy = hierarchy(MockClock::new());
+
+    hierarchy
+        .acquire(&["res:hierarchy:refund", "alice", "key"], 20, &mut store)
+        .expect("Failed to acquire from hierarchy");
+
+    let quota = hierarchy
+        .refund(&["res:hierarchy:refund", "alice", "key"], 5, &mut store)
+        .expect("Failed to refund hierarchy");
+
+    assert_eq!(quota.remaining, 5);
+
+    let (_, quotas) = hierarchy
+        .acquire_each(&["res:hierarchy:refund", "alice", "key"], 0, &mut store)
+        .expect("Failed to acquire from hierarchy");
+
+    assert_eq!(quotas[0].remaining, 85);
+    assert_eq!(quotas[1].remaining, 35);
+}
+
+#[test]
+fn invalid_path() {
+    let mut store = MemoryStore::new();
+
+    let hierarchy = hierarchy(MockClock::new());
+
+    for path in [&[][..], &["tenant", "user", "key", "session"]] {
+        let err = hierarchy
+            .acquire(path, 1, &mut store)
+            .expect_err("Expected an invalid path");
+
+        assert!(matches!(err, Error::InvalidPath(_)));
+    }
+}
+
+#[test]
+fn invalid_levels() {
+    let token_bucket = TokenBucket::new(100, Interval::from_secs(1).unwrap(), 10).unwrap();
+
+    for levels in [
+        vec![],
+        vec![HierarchyLevel::Share(50)],
+        vec![token_bucket.clone().into(), HierarchyLevel::Share(0)],
+        vec![token_bucket.clone().into(), HierarchyLevel::Share(101)],
+        // A share of 5% of 10 tokens does not refill anything
+        vec![token_bucket.clone().into(), HierarchyLevel::Share(5)],
+        // Levels use the settings of the hierarchy
+        vec![token_bucket
+            .clone()
+            .with_time_source(TimeSource::Server)
+            .into()],
+        vec![token_bucket
+            .clone()
+            .with_keyspace(Keyspace::new().with_namespace("tenant").unwrap())
+            .into()],
+        vec![
+            token_bucket.clone().into(),
+            token_bucket
+                .clone()
+                .with_estimate_timeout(Interval::from_secs(1).unwrap())
+                .into(),
+        ],
+        vec![token_bucket
+            .with_max_delay(Interval::from_secs(1).unwrap())
+            .into()],
+    ] {
+        let err = Hierarchy::new(levels).expect_err("Expected an invalid rule");
+
+        assert!(matches!(err, Error::InvalidRule(_)));
+    }
+}
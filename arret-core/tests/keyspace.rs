@@ -0,0 +1,167 @@
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::FixedWindow,
+    store::{Keyspace, MemoryStore},
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn invalid_namespace() {
+    assert!(matches!(
+        Keyspace::new().with_namespace(""),
+        Err(Error::InvalidKeyspace(_))
+    ));
+    assert!(matches!(
+        Keyspace::new().with_namespace("tenant:{a}"),
+        Err(Error::InvalidKeyspace(_))
+    ));
+}
+
+#[test]
+fn namespaces() {
+    let mut con = prepare_redis_connection();
+
+    let interval = Interval::from_secs(10).unwrap();
+    let billing = FixedWindow::new(1, interval)
+        .unwrap()
+        .with_keyspace(Keyspace::new().with_namespace("billing").unwrap());
+    let search = FixedWindow::new(1, interval)
+        .unwrap()
+        .with_keyspace(Keyspace::new().with_namespace("search").unwrap());
+
+    let res = billing
+        .acquire("res:namespaces", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 1, 0);
+
+    let res = search
+        .acquire("res:namespaces", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 1, 0);
+
+    let res = billing
+        .acquire("res:namespaces", 1, &mut con)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 1, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn namespaces_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let interval = Interval::from_secs(10).unwrap();
+        let billing = FixedWindow::new(1, interval)
+            .unwrap()
+            .with_keyspace(Keyspace::new().with_namespace("billing").unwrap());
+        let search = FixedWindow::new(1, interval)
+            .unwrap()
+            .with_keyspace(Keyspace::new().with_namespace("search").unwrap());
+
+        let res = aio::RateLimiter::acquire(&billing, "res:namespaces_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 1, 0);
+
+        let res = aio::RateLimiter::acquire(&search, "res:namespaces_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 1, 0);
+
+        let res = aio::RateLimiter::acquire(&billing, "res:namespaces_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 1, 0);
+    })
+}
+
+#[test]
+fn separators_in_resource() {
+    let mut store = MemoryStore::new();
+
+    let fixed_window = FixedWindow::new(1, Interval::from_secs(10).unwrap()).unwrap();
+
+    for resource in ["res", "res}", "res}:", ":res", "res:{res}"] {
+        let res = fixed_window
+            .acquire(resource, 1, &mut store)
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 1, 0);
+    }
+}
+
+#[test]
+fn long_resources() {
+    let mut store = MemoryStore::new();
+
+    let fixed_window = FixedWindow::new(1, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_keyspace(Keyspace::new().with_max_resource_len(16));
+
+    let resource = "res:".repeat(64);
+
+    let res = fixed_window
+        .acquire(&resource, 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 1, 0);
+
+    let res = fixed_window
+        .acquire(&resource, 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 1, 0);
+
+    let res = fixed_window
+        .acquire(&resource[4..], 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 1, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn long_resources_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let fixed_window = FixedWindow::new(1, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_keyspace(Keyspace::new().with_max_resource_len(16));
+
+        let resource = "res:".repeat(64);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, &resource, 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 1, 0);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, &resource, 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 1, 0);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, &resource[4..], 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 1, 0);
+    })
+}
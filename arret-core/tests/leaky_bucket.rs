@@ -0,0 +1,464 @@
+use std::time::Duration;
+
+use arret_core::{
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Clock, LeakyBucket},
+    store::MemoryStore,
+};
+use test_utils::{
+    assert_delayed, assert_ok, assert_throttled, prepare_redis_connection, MockClock,
+};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn single_token() {
+    let mut con = prepare_redis_connection();
+
+    let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(10).unwrap(), 10).unwrap();
+
+    let res = leaky_bucket
+        .acquire("res:single_token", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn single_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(10).unwrap(), 10).unwrap();
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:single_token_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn multiple_token() {
+    let mut con = prepare_redis_connection();
+
+    let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(10).unwrap(), 10).unwrap();
+
+    let res = leaky_bucket
+        .acquire("res:multiple_token", 5, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 5);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn multiple_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(10).unwrap(), 10).unwrap();
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:multiple_token_async", 5, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 5);
+    })
+}
+
+#[test]
+fn zero_capacity() {
+    let mut con = prepare_redis_connection();
+
+    let leaky_bucket = LeakyBucket::new(0, Interval::from_secs(10).unwrap(), 10).unwrap();
+
+    let res = leaky_bucket
+        .acquire("res:zero_capacity", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_throttled!(res, 0, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn zero_capacity_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let leaky_bucket = LeakyBucket::new(0, Interval::from_secs(10).unwrap(), 10).unwrap();
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:zero_capacity_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_throttled!(res, 0, 0);
+    })
+}
+
+#[test]
+fn delayed() {
+    let mut con = prepare_redis_connection();
+
+    let leaky_bucket = LeakyBucket::new(3, Interval::from_secs(100).unwrap(), 10)
+        .unwrap()
+        .with_clock(MockClock::new());
+
+    for i in 0..4 {
+        let res = leaky_bucket
+            .acquire("res:delayed", 1, &mut con)
+            .expect("Failed to acquire from leaky bucket");
+
+        if i == 0 {
+            assert_ok!(res, 3, 2);
+        } else if i < 3 {
+            let delay = assert_delayed!(res, 3, 2 - i);
+            assert_eq!(delay, Duration::from_secs(10 * i));
+        } else {
+            assert_throttled!(res, 3, 0);
+        }
+    }
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn delayed_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let leaky_bucket = LeakyBucket::new(3, Interval::from_secs(100).unwrap(), 10)
+            .unwrap()
+            .with_clock(MockClock::new());
+
+        for i in 0..4 {
+            let res = aio::RateLimiter::acquire(&leaky_bucket, "res:delayed_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from leaky bucket");
+
+            if i == 0 {
+                assert_ok!(res, 3, 2);
+            } else if i < 3 {
+                let delay = assert_delayed!(res, 3, 2 - i);
+                assert_eq!(delay, Duration::from_secs(10 * i));
+            } else {
+                assert_throttled!(res, 3, 0);
+            }
+        }
+    })
+}
+
+#[test]
+fn drain() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let leaky_bucket = LeakyBucket::new(1, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = leaky_bucket
+        .acquire("res:drain", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 1, 0);
+
+    let res = leaky_bucket
+        .acquire("res:drain", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_throttled!(res, 1, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = leaky_bucket
+        .acquire("res:drain", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 1, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn drain_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let leaky_bucket = LeakyBucket::new(1, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:drain_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 1, 0);
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:drain_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_throttled!(res, 1, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:drain_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 1, 0);
+    })
+}
+
+#[test]
+fn peek() {
+    let mut con = prepare_redis_connection();
+
+    let leaky_bucket = LeakyBucket::new(2, Interval::from_secs(10).unwrap(), 1).unwrap();
+
+    let res = leaky_bucket
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 2, 1);
+
+    for _ in 0..2 {
+        let quota = leaky_bucket
+            .peek("res:peek", &mut con)
+            .expect("Failed to peek leaky bucket");
+
+        assert_eq!(quota.remaining, 1);
+    }
+
+    let res = leaky_bucket
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_delayed!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let leaky_bucket = LeakyBucket::new(2, Interval::from_secs(10).unwrap(), 1).unwrap();
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 2, 1);
+
+        for _ in 0..2 {
+            let quota = aio::RateLimiter::peek(&leaky_bucket, "res:peek_async", &mut con)
+                .await
+                .expect("Failed to peek leaky bucket");
+
+            assert_eq!(quota.remaining, 1);
+        }
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_delayed!(res, 2, 0);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = leaky_bucket
+        .acquire("res:refund", 6, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = leaky_bucket
+        .refund("res:refund", 3, clock.now(), &mut con)
+        .expect("Failed to refund leaky bucket");
+
+    assert_eq!(quota.remaining, 7);
+
+    // Refunds are clamped to the capacity
+    let quota = leaky_bucket
+        .refund("res:refund", 20, clock.now(), &mut con)
+        .expect("Failed to refund leaky bucket");
+
+    assert_eq!(quota.remaining, 10);
+
+    let res = leaky_bucket
+        .acquire("res:refund", 10, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 0);
+
+    let res = leaky_bucket
+        .acquire("res:refund", 1, &mut con)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:refund_async", 6, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 4);
+
+        let quota =
+            aio::RateLimiter::refund(&leaky_bucket, "res:refund_async", 3, clock.now(), &mut con)
+                .await
+                .expect("Failed to refund leaky bucket");
+
+        assert_eq!(quota.remaining, 7);
+
+        // Refunds are clamped to the capacity
+        let quota =
+            aio::RateLimiter::refund(&leaky_bucket, "res:refund_async", 20, clock.now(), &mut con)
+                .await
+                .expect("Failed to refund leaky bucket");
+
+        assert_eq!(quota.remaining, 10);
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:refund_async", 10, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:refund_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = leaky_bucket
+        .acquire("res:memory_store", 6, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = leaky_bucket
+        .refund("res:memory_store", 6, clock.now(), &mut store)
+        .expect("Failed to refund leaky bucket");
+
+    assert_eq!(quota.remaining, 10);
+
+    let res = leaky_bucket
+        .acquire("res:memory_store", 10, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 0);
+
+    let res = leaky_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_throttled!(res, 10, 0);
+
+    // The bucket is drained at one token per second
+    clock.advance(Duration::from_secs(10));
+
+    let res = leaky_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:memory_store_async", 6, &mut store)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 4);
+
+        let quota = aio::RateLimiter::refund(
+            &leaky_bucket,
+            "res:memory_store_async",
+            6,
+            clock.now(),
+            &mut store,
+        )
+        .await
+        .expect("Failed to refund leaky bucket");
+
+        assert_eq!(quota.remaining, 10);
+
+        let res =
+            aio::RateLimiter::acquire(&leaky_bucket, "res:memory_store_async", 10, &mut store)
+                .await
+                .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_throttled!(res, 10, 0);
+
+        // The bucket is drained at one token per second
+        clock.advance(Duration::from_secs(10));
+
+        let res = aio::RateLimiter::acquire(&leaky_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 9);
+    })
+}
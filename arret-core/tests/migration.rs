@@ -0,0 +1,333 @@
+use std::time::Duration;
+
+use arret_core::{
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{FixedWindow, Gcra, LeakyBucket, MigrationPolicy, SlidingWindowCounter, TokenBucket},
+    store::MemoryStore,
+};
+use test_utils::{
+    assert_delayed, assert_ok, assert_throttled, prepare_redis_connection, MockClock,
+};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+const POLICIES: [MigrationPolicy; 3] = [
+    MigrationPolicy::Reset,
+    MigrationPolicy::Clamp,
+    MigrationPolicy::Proportional,
+];
+
+#[test]
+fn token_bucket() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+    let interval = Interval::from_secs(1).unwrap();
+
+    for (policy, remaining) in POLICIES.into_iter().zip([4, 1, 0]) {
+        let resource = format!("res:migration:token_bucket:{policy:?}");
+
+        let before = TokenBucket::new(10, interval, 1)
+            .unwrap()
+            .with_clock(clock.clone());
+        let after = TokenBucket::new(5, interval, 1)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_migration_policy(policy);
+
+        let res = before
+            .acquire(&resource, 8, &mut con)
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 2);
+
+        let res = after
+            .acquire(&resource, 1, &mut con)
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 5, remaining);
+    }
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn token_bucket_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+        let interval = Interval::from_secs(1).unwrap();
+
+        for (policy, remaining) in POLICIES.into_iter().zip([4, 1, 0]) {
+            let resource = format!("res:migration:token_bucket_async:{policy:?}");
+
+            let before = TokenBucket::new(10, interval, 1)
+                .unwrap()
+                .with_clock(clock.clone());
+            let after = TokenBucket::new(5, interval, 1)
+                .unwrap()
+                .with_clock(clock.clone())
+                .with_migration_policy(policy);
+
+            let res = aio::RateLimiter::acquire(&before, &resource, 8, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+            assert_ok!(res, 10, 2);
+
+            let res = aio::RateLimiter::acquire(&after, &resource, 1, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+            assert_ok!(res, 5, remaining);
+        }
+    })
+}
+
+#[test]
+fn token_bucket_unchanged() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone())
+        .with_migration_policy(MigrationPolicy::Reset);
+
+    let res = token_bucket
+        .acquire("res:migration:token_bucket_unchanged", 8, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 2);
+
+    let res = token_bucket
+        .acquire("res:migration:token_bucket_unchanged", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 1);
+}
+
+#[test]
+fn fixed_window() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+    let window = Interval::from_secs(10).unwrap();
+
+    for (policy, remaining) in POLICIES.into_iter().zip([19, 1, 3]) {
+        let resource = format!("res:migration:fixed_window:{policy:?}");
+
+        let before = FixedWindow::new(10, window)
+            .unwrap()
+            .with_clock(clock.clone());
+        let after = FixedWindow::new(20, window)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_migration_policy(policy);
+
+        let res = before
+            .acquire(&resource, 8, &mut store)
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 10, 2);
+
+        let res = after
+            .acquire(&resource, 1, &mut store)
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 20, remaining);
+    }
+}
+
+#[test]
+fn fixed_window_past_window() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let before = FixedWindow::new(10, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+    let after = FixedWindow::new(10, Interval::from_secs(60).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = before
+        .acquire("res:migration:fixed_window_past_window", 8, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 2);
+
+    clock.advance(Duration::from_secs(1));
+
+    // The window of the bucket has ended, so there is nothing to carry over
+    let res = after
+        .acquire("res:migration:fixed_window_past_window", 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[test]
+fn gcra() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+    let period = Interval::from_secs(10).unwrap();
+
+    let before = Gcra::new(10, period).unwrap().with_clock(clock.clone());
+
+    for policy in POLICIES {
+        let resource = format!("res:migration:gcra:{policy:?}");
+
+        let res = before
+            .acquire(&resource, 6, &mut store)
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 10, 4);
+    }
+
+    for (policy, remaining) in [
+        (MigrationPolicy::Reset, 4),
+        (MigrationPolicy::Proportional, 1),
+    ] {
+        let resource = format!("res:migration:gcra:{policy:?}");
+
+        let after = Gcra::new(5, period)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_migration_policy(policy);
+
+        let res = after
+            .acquire(&resource, 1, &mut store)
+            .expect("Failed to acquire from GCRA");
+
+        assert_ok!(res, 5, remaining);
+    }
+
+    let after = Gcra::new(5, period)
+        .unwrap()
+        .with_clock(clock.clone())
+        .with_migration_policy(MigrationPolicy::Clamp);
+
+    let res = after
+        .acquire("res:migration:gcra:Clamp", 1, &mut store)
+        .expect("Failed to acquire from GCRA");
+
+    assert_throttled!(res, 5, 0);
+}
+
+#[test]
+fn leaky_bucket() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+    let drain_interval = Interval::from_secs(1).unwrap();
+
+    let before = LeakyBucket::new(10, drain_interval, 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    for policy in POLICIES {
+        let resource = format!("res:migration:leaky_bucket:{policy:?}");
+
+        let res = before
+            .acquire(&resource, 6, &mut store)
+            .expect("Failed to acquire from leaky bucket");
+
+        assert_ok!(res, 10, 4);
+    }
+
+    let after = |policy| {
+        LeakyBucket::new(20, drain_interval, 1)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_migration_policy(policy)
+    };
+
+    let res = after(MigrationPolicy::Reset)
+        .acquire("res:migration:leaky_bucket:Reset", 1, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_ok!(res, 20, 19);
+
+    let res = after(MigrationPolicy::Clamp)
+        .acquire("res:migration:leaky_bucket:Clamp", 1, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_delayed!(res, 20, 13);
+
+    let res = after(MigrationPolicy::Proportional)
+        .acquire("res:migration:leaky_bucket:Proportional", 1, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    assert_delayed!(res, 20, 7);
+}
+
+#[test]
+fn sliding_window_counter() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+    let window = Interval::from_secs(10).unwrap();
+
+    let before = SlidingWindowCounter::new(10, window)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    for policy in POLICIES {
+        let resource = format!("res:migration:sliding_window_counter:{policy:?}");
+
+        let res = before
+            .acquire(&resource, 6, &mut store)
+            .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 4);
+    }
+
+    for (policy, remaining) in [
+        (MigrationPolicy::Reset, 4),
+        (MigrationPolicy::Proportional, 1),
+    ] {
+        let resource = format!("res:migration:sliding_window_counter:{policy:?}");
+
+        let after = SlidingWindowCounter::new(5, window)
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_migration_policy(policy);
+
+        let res = after
+            .acquire(&resource, 1, &mut store)
+            .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 5, remaining);
+    }
+
+    let after = SlidingWindowCounter::new(5, window)
+        .unwrap()
+        .with_clock(clock.clone())
+        .with_migration_policy(MigrationPolicy::Clamp);
+
+    let res = after
+        .acquire("res:migration:sliding_window_counter:Clamp", 1, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_throttled!(res, 5, 0);
+
+    // Counters of windows of another length are never carried over
+    let after = SlidingWindowCounter::new(10, Interval::from_secs(20).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = after
+        .acquire("res:migration:sliding_window_counter:Clamp", 1, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 9);
+}
@@ -0,0 +1,223 @@
+use std::time::{Duration, UNIX_EPOCH};
+
+use arret_core::{
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    reserving_limiter::{ReservationResult, ReservingLimiter},
+    rule::{Clock, TokenBucket},
+    store::MemoryStore,
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn token_bucket() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let now = UNIX_EPOCH + Duration::from_millis(clock.now());
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = token_bucket
+        .reserve("res:reserve:token_bucket", 8, &mut con)
+        .expect("Failed to reserve from token bucket");
+
+    let ReservationResult::Ok(reservation, quota) = res else {
+        panic!("Expected a reservation, got {res:?}");
+    };
+
+    // The tokens are in the bucket, so the caller may proceed right away
+    assert_eq!(quota.remaining, 2);
+    assert_eq!(reservation.delay(now), Duration::ZERO);
+
+    let res = token_bucket
+        .reserve("res:reserve:token_bucket", 5, &mut con)
+        .expect("Failed to reserve from token bucket");
+
+    let ReservationResult::Ok(reservation, quota) = res else {
+        panic!("Expected a reservation, got {res:?}");
+    };
+
+    // The missing tokens are taken from the next three refills
+    assert_eq!(quota.remaining, 0);
+    assert_eq!(reservation.delay(now), Duration::from_secs(3));
+
+    let res = token_bucket
+        .acquire("res:reserve:token_bucket", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 10, 0);
+
+    let cancelled = token_bucket
+        .cancel(reservation, &mut con)
+        .expect("Failed to cancel reservation from token bucket");
+
+    assert!(cancelled);
+
+    let res = token_bucket
+        .acquire("res:reserve:token_bucket", 2, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn token_bucket_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let now = UNIX_EPOCH + Duration::from_millis(clock.now());
+
+        let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::ReservingLimiter::reserve(
+            &token_bucket,
+            "res:reserve:token_bucket_async",
+            8,
+            &mut con,
+        )
+        .await
+        .expect("Failed to reserve from token bucket");
+
+        let ReservationResult::Ok(reservation, quota) = res else {
+            panic!("Expected a reservation, got {res:?}");
+        };
+
+        assert_eq!(quota.remaining, 2);
+        assert_eq!(reservation.delay(now), Duration::ZERO);
+
+        let res = aio::ReservingLimiter::reserve(
+            &token_bucket,
+            "res:reserve:token_bucket_async",
+            5,
+            &mut con,
+        )
+        .await
+        .expect("Failed to reserve from token bucket");
+
+        let ReservationResult::Ok(reservation, quota) = res else {
+            panic!("Expected a reservation, got {res:?}");
+        };
+
+        assert_eq!(quota.remaining, 0);
+        assert_eq!(reservation.delay(now), Duration::from_secs(3));
+
+        let res =
+            aio::RateLimiter::acquire(&token_bucket, "res:reserve:token_bucket_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 10, 0);
+
+        let cancelled = aio::ReservingLimiter::cancel(&token_bucket, reservation, &mut con)
+            .await
+            .expect("Failed to cancel reservation from token bucket");
+
+        assert!(cancelled);
+
+        let res =
+            aio::RateLimiter::acquire(&token_bucket, "res:reserve:token_bucket_async", 2, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 0);
+    })
+}
+
+#[test]
+fn throttled() {
+    let mut store = MemoryStore::new();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_max_delay(Interval::from_secs(5).unwrap());
+
+    let res = token_bucket
+        .reserve("res:reserve:throttled", 11, &mut store)
+        .expect("Failed to reserve from token bucket");
+
+    assert!(matches!(res, ReservationResult::Throttled(quota) if quota.remaining == 10));
+
+    let res = token_bucket
+        .reserve("res:reserve:throttled", 10, &mut store)
+        .expect("Failed to reserve from token bucket");
+
+    assert!(matches!(res, ReservationResult::Ok(..)));
+
+    // The tokens would only be refilled after the maximum delay
+    let res = token_bucket
+        .reserve("res:reserve:throttled", 6, &mut store)
+        .expect("Failed to reserve from token bucket");
+
+    assert!(matches!(res, ReservationResult::Throttled(quota) if quota.remaining == 0));
+}
+
+#[test]
+fn proceeded() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = token_bucket
+        .reserve("res:reserve:proceeded", 12, &mut store)
+        .expect("Failed to reserve from token bucket");
+
+    assert!(matches!(res, ReservationResult::Throttled(_)));
+
+    let res = token_bucket
+        .reserve("res:reserve:proceeded", 10, &mut store)
+        .expect("Failed to reserve from token bucket");
+
+    let ReservationResult::Ok(reservation, _) = res else {
+        panic!("Expected a reservation, got {res:?}");
+    };
+
+    let res = token_bucket
+        .reserve("res:reserve:proceeded", 2, &mut store)
+        .expect("Failed to reserve from token bucket");
+
+    let ReservationResult::Ok(delayed, _) = res else {
+        panic!("Expected a reservation, got {res:?}");
+    };
+
+    assert_eq!(delayed.proceed_at(), reservation.proceed_at() + 2_000);
+
+    clock.advance(Duration::from_secs(1));
+
+    // The first reservation has been proceeded with, so its tokens stay consumed
+    let cancelled = token_bucket
+        .cancel(reservation, &mut store)
+        .expect("Failed to cancel reservation from token bucket");
+
+    assert!(!cancelled);
+
+    let cancelled = token_bucket
+        .cancel(delayed, &mut store)
+        .expect("Failed to cancel reservation from token bucket");
+
+    assert!(cancelled);
+
+    let quota = token_bucket
+        .peek("res:reserve:proceeded", &mut store)
+        .expect("Failed to peek token bucket");
+
+    assert_eq!(quota.remaining, 1);
+}
@@ -0,0 +1,634 @@
+use std::time::Duration;
+
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Clock, SlidingWindowCounter},
+    store::MemoryStore,
+};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn single_token() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_counter
+        .acquire("res:single_token", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn single_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:single_token_async",
+            1,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn multiple_token() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_counter
+        .acquire("res:multiple_token", 5, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 5);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn multiple_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:multiple_token_async",
+            5,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 5);
+    })
+}
+
+#[test]
+fn zero_capacity() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(0, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_counter
+        .acquire("res:zero_capacity", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_throttled!(res, 0, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn zero_capacity_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(0, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:zero_capacity_async",
+            1,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_throttled!(res, 0, 0);
+    })
+}
+
+#[test]
+fn throttled() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    for i in 0..5 {
+        let res = sliding_window_counter
+            .acquire("res:throttled", 3, &mut con)
+            .expect("Failed to acquire from sliding window counter");
+
+        if i < 3 {
+            assert_ok!(res, 10, 7 - i * 3);
+        } else {
+            assert_throttled!(res, 10, 1);
+        }
+    }
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn throttled_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        for i in 0..5 {
+            let res = aio::RateLimiter::acquire(
+                &sliding_window_counter,
+                "res:throttled_async",
+                3,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from sliding window counter");
+
+            if i < 3 {
+                assert_ok!(res, 10, 7 - i * 3);
+            } else {
+                assert_throttled!(res, 10, 1);
+            }
+        }
+    })
+}
+
+#[test]
+fn slide() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let sliding_window_counter = SlidingWindowCounter::new(2, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = sliding_window_counter
+        .acquire("res:slide", 2, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 2, 0);
+
+    let res = sliding_window_counter
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(2));
+
+    let res = sliding_window_counter
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 2, 1);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn slide_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let sliding_window_counter = SlidingWindowCounter::new(2, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_counter, "res:slide_async", 2, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 2, 0);
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_counter, "res:slide_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window counter");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(2));
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_counter, "res:slide_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 2, 1);
+    })
+}
+
+#[test]
+fn peek() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_counter =
+        SlidingWindowCounter::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_counter
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 2, 1);
+
+    for _ in 0..2 {
+        let quota = sliding_window_counter
+            .peek("res:peek", &mut con)
+            .expect("Failed to peek sliding window counter");
+
+        assert_eq!(quota.remaining, 1);
+    }
+
+    let res = sliding_window_counter
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(&sliding_window_counter, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 2, 1);
+
+        for _ in 0..2 {
+            let quota = aio::RateLimiter::peek(&sliding_window_counter, "res:peek_async", &mut con)
+                .await
+                .expect("Failed to peek sliding window counter");
+
+            assert_eq!(quota.remaining, 1);
+        }
+
+        let res = aio::RateLimiter::acquire(&sliding_window_counter, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 2, 0);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let sliding_window_counter = SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = sliding_window_counter
+        .acquire("res:refund", 6, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = sliding_window_counter
+        .refund("res:refund", 3, clock.now(), &mut con)
+        .expect("Failed to refund sliding window counter");
+
+    assert_eq!(quota.remaining, 7);
+
+    // Refunds are clamped to the capacity
+    let quota = sliding_window_counter
+        .refund("res:refund", 20, clock.now(), &mut con)
+        .expect("Failed to refund sliding window counter");
+
+    assert_eq!(quota.remaining, 10);
+
+    let res = sliding_window_counter
+        .acquire("res:refund", 10, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 0);
+
+    let res = sliding_window_counter
+        .acquire("res:refund", 1, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap())
+                .unwrap()
+                .with_clock(clock.clone());
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_counter, "res:refund_async", 6, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 4);
+
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_counter,
+            "res:refund_async",
+            3,
+            clock.now(),
+            &mut con,
+        )
+        .await
+        .expect("Failed to refund sliding window counter");
+
+        assert_eq!(quota.remaining, 7);
+
+        // Refunds are clamped to the capacity
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_counter,
+            "res:refund_async",
+            20,
+            clock.now(),
+            &mut con,
+        )
+        .await
+        .expect("Failed to refund sliding window counter");
+
+        assert_eq!(quota.remaining, 10);
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_counter, "res:refund_async", 10, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 0);
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_counter, "res:refund_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window counter");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let sliding_window_counter = SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = sliding_window_counter
+        .acquire("res:memory_store", 6, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = sliding_window_counter
+        .refund("res:memory_store", 3, clock.now(), &mut store)
+        .expect("Failed to refund sliding window counter");
+
+    assert_eq!(quota.remaining, 7);
+
+    let res = sliding_window_counter
+        .acquire("res:memory_store", 7, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 0);
+
+    let res = sliding_window_counter
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_throttled!(res, 10, 0);
+
+    // The estimate is cleared once the previous window is over as well
+    clock.advance(Duration::from_secs(20));
+
+    let res = sliding_window_counter
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let sliding_window_counter =
+            SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap())
+                .unwrap()
+                .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:memory_store_async",
+            6,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 4);
+
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_counter,
+            "res:memory_store_async",
+            3,
+            clock.now(),
+            &mut store,
+        )
+        .await
+        .expect("Failed to refund sliding window counter");
+
+        assert_eq!(quota.remaining, 7);
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:memory_store_async",
+            7,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:memory_store_async",
+            1,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_throttled!(res, 10, 0);
+
+        // The estimate is cleared once the previous window is over as well
+        clock.advance(Duration::from_secs(20));
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:memory_store_async",
+            1,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn late_refund() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::at(1_000_000);
+
+    let sliding_window_counter = SlidingWindowCounter::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let acquired_at = clock.now();
+    sliding_window_counter
+        .acquire("res:late_refund", 6, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    clock.advance(Duration::from_secs(10));
+
+    let res = sliding_window_counter
+        .acquire("res:late_refund", 2, &mut store)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 10, 2);
+
+    // The tokens are taken back from the previous window they were acquired in, and
+    // the current window keeps its own
+    let quota = sliding_window_counter
+        .refund("res:late_refund", 6, acquired_at, &mut store)
+        .expect("Failed to refund sliding window counter");
+
+    assert_eq!(quota.remaining, 8);
+
+    clock.advance(Duration::from_secs(10));
+
+    // Tokens of a window which no longer overlaps the rolling window are not refunded
+    let quota = sliding_window_counter
+        .refund("res:late_refund", 6, acquired_at, &mut store)
+        .expect("Failed to refund sliding window counter");
+
+    assert_eq!(quota.remaining, 8);
+}
+
+#[test]
+fn large_capacity() {
+    let mut con = prepare_redis_connection();
+
+    // The largest capacity of a daily window, whose weighted counters are still exact
+    let clock = MockClock::at(20_000 * 86_400_000);
+    let sliding_window_counter =
+        SlidingWindowCounter::new(104_249_991, Interval::from_secs(86_400).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+    let res = sliding_window_counter
+        .acquire("res:large_capacity", 104_249_991, &mut con)
+        .expect("Failed to acquire from sliding window counter");
+
+    assert_ok!(res, 104_249_991, 0);
+
+    clock.advance(Duration::from_millis(86_400_001));
+
+    let quota = sliding_window_counter
+        .peek("res:large_capacity", &mut con)
+        .expect("Failed to peek sliding window counter");
+
+    assert_eq!(quota.remaining, 2);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn large_capacity_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::at(20_000 * 86_400_000);
+        let sliding_window_counter =
+            SlidingWindowCounter::new(104_249_991, Interval::from_secs(86_400).unwrap())
+                .unwrap()
+                .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_counter,
+            "res:large_capacity_async",
+            104_249_991,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from sliding window counter");
+
+        assert_ok!(res, 104_249_991, 0);
+
+        clock.advance(Duration::from_millis(86_400_001));
+
+        let quota = aio::RateLimiter::peek(
+            &sliding_window_counter,
+            "res:large_capacity_async",
+            &mut con,
+        )
+        .await
+        .expect("Failed to peek sliding window counter");
+
+        assert_eq!(quota.remaining, 2);
+    })
+}
+
+#[test]
+fn invalid_capacity() {
+    for (capacity, window) in [(104_249_992, 86_400), (u64::MAX, 1)] {
+        let err = SlidingWindowCounter::new(capacity, Interval::from_secs(window).unwrap())
+            .expect_err("Expected an invalid rule");
+
+        assert!(matches!(err, Error::InvalidRule(_)));
+    }
+}
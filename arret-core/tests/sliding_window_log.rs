@@ -0,0 +1,681 @@
+use std::time::Duration;
+
+use arret_core::{
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Clock, SlidingWindowLog},
+    store::{Keyspace, MemoryStore},
+};
+use redis::Commands;
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::{block_on, prepare_redis_async_connection};
+
+#[test]
+fn single_token() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_log
+        .acquire("res:single_token", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn single_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_log =
+            SlidingWindowLog::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:single_token_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn multiple_token() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_log
+        .acquire("res:multiple_token", 5, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 5);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn multiple_token_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_log =
+            SlidingWindowLog::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:multiple_token_async", 5, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 5);
+    })
+}
+
+#[test]
+fn zero_capacity() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_log = SlidingWindowLog::new(0, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_log
+        .acquire("res:zero_capacity", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_throttled!(res, 0, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn zero_capacity_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_log =
+            SlidingWindowLog::new(0, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:zero_capacity_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_throttled!(res, 0, 0);
+    })
+}
+
+#[test]
+fn throttled() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+    for i in 0..5 {
+        let res = sliding_window_log
+            .acquire("res:throttled", 3, &mut con)
+            .expect("Failed to acquire from sliding window log");
+
+        if i < 3 {
+            assert_ok!(res, 10, 7 - i * 3);
+        } else {
+            assert_throttled!(res, 10, 1);
+        }
+    }
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn throttled_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_log =
+            SlidingWindowLog::new(10, Interval::from_secs(10).unwrap()).unwrap();
+
+        for i in 0..5 {
+            let res =
+                aio::RateLimiter::acquire(&sliding_window_log, "res:throttled_async", 3, &mut con)
+                    .await
+                    .expect("Failed to acquire from sliding window log");
+
+            if i < 3 {
+                assert_ok!(res, 10, 7 - i * 3);
+            } else {
+                assert_throttled!(res, 10, 1);
+            }
+        }
+    })
+}
+
+#[test]
+fn slide() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let sliding_window_log = SlidingWindowLog::new(2, Interval::from_secs(2).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = sliding_window_log
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 2, 1);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = sliding_window_log
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 2, 0);
+
+    let res = sliding_window_log
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = sliding_window_log
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 2, 0);
+
+    let res = sliding_window_log
+        .acquire("res:slide", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn slide_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let sliding_window_log = SlidingWindowLog::new(2, Interval::from_secs(2).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:slide_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 2, 1);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:slide_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:slide_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:slide_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:slide_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
+
+#[test]
+fn peek() {
+    let mut con = prepare_redis_connection();
+
+    let sliding_window_log = SlidingWindowLog::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+    let res = sliding_window_log
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 2, 1);
+
+    for _ in 0..2 {
+        let quota = sliding_window_log
+            .peek("res:peek", &mut con)
+            .expect("Failed to peek sliding window log");
+
+        assert_eq!(quota.remaining, 1);
+    }
+
+    let res = sliding_window_log
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let sliding_window_log =
+            SlidingWindowLog::new(2, Interval::from_secs(10).unwrap()).unwrap();
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 2, 1);
+
+        for _ in 0..2 {
+            let quota = aio::RateLimiter::peek(&sliding_window_log, "res:peek_async", &mut con)
+                .await
+                .expect("Failed to peek sliding window log");
+
+            assert_eq!(quota.remaining, 1);
+        }
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 2, 0);
+    })
+}
+
+#[test]
+fn peek_read_only() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+    let sliding_window_log = SlidingWindowLog::new(2, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    sliding_window_log
+        .acquire("res:peek_read_only", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    clock.advance(Duration::from_secs(11));
+
+    let quota = sliding_window_log
+        .peek("res:peek_read_only", &mut con)
+        .expect("Failed to peek sliding window log");
+
+    assert_eq!(quota.remaining, 2);
+
+    // The grant has slid out of the window, but peeking leaves it to the next acquisition
+    let key = Keyspace::new().key("sliding_window_log", "res:peek_read_only");
+    let grants: u64 = con.zcard(key).unwrap();
+
+    assert_eq!(grants, 1);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_read_only_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+        let sliding_window_log = SlidingWindowLog::new(2, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        aio::RateLimiter::acquire(&sliding_window_log, "res:peek_read_only_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        clock.advance(Duration::from_secs(11));
+
+        let quota =
+            aio::RateLimiter::peek(&sliding_window_log, "res:peek_read_only_async", &mut con)
+                .await
+                .expect("Failed to peek sliding window log");
+
+        assert_eq!(quota.remaining, 2);
+
+        let key = Keyspace::new().key("sliding_window_log", "res:peek_read_only_async");
+        let grants: u64 = redis::AsyncCommands::zcard(&mut con, key).await.unwrap();
+
+        assert_eq!(grants, 1);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = sliding_window_log
+        .acquire("res:refund", 3, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 7);
+
+    let res = sliding_window_log
+        .acquire("res:refund", 4, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 3);
+
+    // Tokens are taken back from the latest grants
+    let quota = sliding_window_log
+        .refund("res:refund", 5, clock.now(), &mut con)
+        .expect("Failed to refund sliding window log");
+
+    assert_eq!(quota.remaining, 8);
+
+    // Refunds are clamped to the capacity
+    let quota = sliding_window_log
+        .refund("res:refund", 20, clock.now(), &mut con)
+        .expect("Failed to refund sliding window log");
+
+    assert_eq!(quota.remaining, 10);
+
+    let res = sliding_window_log
+        .acquire("res:refund", 10, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 0);
+
+    let res = sliding_window_log
+        .acquire("res:refund", 1, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:refund_async", 3, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 7);
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:refund_async", 4, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 3);
+
+        // Tokens are taken back from the latest grants
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_log,
+            "res:refund_async",
+            5,
+            clock.now(),
+            &mut con,
+        )
+        .await
+        .expect("Failed to refund sliding window log");
+
+        assert_eq!(quota.remaining, 8);
+
+        // Refunds are clamped to the capacity
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_log,
+            "res:refund_async",
+            20,
+            clock.now(),
+            &mut con,
+        )
+        .await
+        .expect("Failed to refund sliding window log");
+
+        assert_eq!(quota.remaining, 10);
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:refund_async", 10, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&sliding_window_log, "res:refund_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
+
+/// Grants of the same time are told apart by their sequence, which refunds must not
+/// reuse, or a later grant would overwrite another one and its tokens would never leave
+/// the window.
+#[test]
+fn refund_same_time() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let sliding_window_log = SlidingWindowLog::new(12, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    for _ in 0..11 {
+        sliding_window_log
+            .acquire("res:refund_same_time", 1, &mut con)
+            .expect("Failed to acquire from sliding window log");
+    }
+
+    let quota = sliding_window_log
+        .refund("res:refund_same_time", 1, clock.now(), &mut con)
+        .expect("Failed to refund sliding window log");
+
+    assert_eq!(quota.remaining, 2);
+
+    for remaining in [1, 0] {
+        let res = sliding_window_log
+            .acquire("res:refund_same_time", 1, &mut con)
+            .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 12, remaining);
+    }
+
+    clock.advance(Duration::from_secs(10));
+
+    let res = sliding_window_log
+        .acquire("res:refund_same_time", 12, &mut con)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 12, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_same_time_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let sliding_window_log = SlidingWindowLog::new(12, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        for _ in 0..11 {
+            aio::RateLimiter::acquire(
+                &sliding_window_log,
+                "res:refund_same_time_async",
+                1,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from sliding window log");
+        }
+
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_log,
+            "res:refund_same_time_async",
+            1,
+            clock.now(),
+            &mut con,
+        )
+        .await
+        .expect("Failed to refund sliding window log");
+
+        assert_eq!(quota.remaining, 2);
+
+        for remaining in [1, 0] {
+            let res = aio::RateLimiter::acquire(
+                &sliding_window_log,
+                "res:refund_same_time_async",
+                1,
+                &mut con,
+            )
+            .await
+            .expect("Failed to acquire from sliding window log");
+
+            assert_ok!(res, 12, remaining);
+        }
+
+        clock.advance(Duration::from_secs(10));
+
+        let res = aio::RateLimiter::acquire(
+            &sliding_window_log,
+            "res:refund_same_time_async",
+            12,
+            &mut con,
+        )
+        .await
+        .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 12, 0);
+    })
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = sliding_window_log
+        .acquire("res:memory_store", 6, &mut store)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 4);
+
+    let quota = sliding_window_log
+        .refund("res:memory_store", 3, clock.now(), &mut store)
+        .expect("Failed to refund sliding window log");
+
+    assert_eq!(quota.remaining, 7);
+
+    let res = sliding_window_log
+        .acquire("res:memory_store", 7, &mut store)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 0);
+
+    let res = sliding_window_log
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_throttled!(res, 10, 0);
+
+    // The grants leave the window once it has slid past them
+    clock.advance(Duration::from_secs(10));
+
+    let res = sliding_window_log
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from sliding window log");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let sliding_window_log = SlidingWindowLog::new(10, Interval::from_secs(10).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:memory_store_async", 6, &mut store)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 4);
+
+        let quota = aio::RateLimiter::refund(
+            &sliding_window_log,
+            "res:memory_store_async",
+            3,
+            clock.now(),
+            &mut store,
+        )
+        .await
+        .expect("Failed to refund sliding window log");
+
+        assert_eq!(quota.remaining, 7);
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:memory_store_async", 7, &mut store)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 0);
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:memory_store_async", 1, &mut store)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_throttled!(res, 10, 0);
+
+        // The grants leave the window once it has slid past them
+        clock.advance(Duration::from_secs(10));
+
+        let res =
+            aio::RateLimiter::acquire(&sliding_window_log, "res:memory_store_async", 1, &mut store)
+                .await
+                .expect("Failed to acquire from sliding window log");
+
+        assert_ok!(res, 10, 9);
+    })
+}
@@ -0,0 +1,263 @@
+use std::{collections::HashMap, time::Duration};
+
+use arret_core::{
+    concurrency_limiter::{ConcurrencyLimiter, LeaseResult},
+    error::Result,
+    interval::Interval,
+    rate_limiter::{AcquireResult, Quota, RateLimiter},
+    rule::{Clock, Concurrency, FixedWindow, SystemClock, TokenBucket},
+    store::{Entry, Operation, Store},
+};
+use test_utils::{assert_ok, assert_throttled, MockClock};
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::block_on;
+
+/// A store implemented outside of the crate, keeping its entries in a plain map.
+#[derive(Debug, Default)]
+struct MapStore {
+    entries: HashMap<String, Entry>,
+}
+
+impl Store for MapStore {
+    fn execute<O: Operation>(&mut self, operation: &O) -> Result<O::Output> {
+        let now = operation.now().unwrap_or_else(|| SystemClock.now());
+
+        let mut entries: Vec<Option<Entry>> = operation
+            .keys()
+            .iter()
+            .map(|key| {
+                self.entries
+                    .get(key)
+                    .filter(|entry| !entry.is_expired(now))
+                    .cloned()
+            })
+            .collect();
+
+        let output = operation.apply(now, &mut entries);
+
+        for (key, entry) in operation.keys().iter().zip(entries) {
+            match entry {
+                Some(entry) => self.entries.insert(key.clone(), entry),
+                None => self.entries.remove(key),
+            };
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(feature = "aio")]
+#[async_trait::async_trait]
+impl aio::Store for MapStore {
+    async fn execute<O>(&mut self, operation: &O) -> Result<O::Output>
+    where
+        O: Operation + Sync,
+        O::Output: Send,
+    {
+        Store::execute(self, operation)
+    }
+}
+
+#[test]
+fn fixed_window() {
+    let mut store = MapStore::default();
+
+    let clock = MockClock::new();
+
+    let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap())
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = fixed_window
+        .acquire("res:fixed_window", 2, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 0);
+
+    let res = fixed_window
+        .acquire("res:fixed_window", 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = fixed_window
+        .acquire("res:fixed_window", 1, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_ok!(res, 2, 1);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn fixed_window_async() {
+    block_on(async {
+        let mut store = MapStore::default();
+
+        let clock = MockClock::new();
+
+        let fixed_window = FixedWindow::new(2, Interval::from_secs(1).unwrap())
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:fixed_window", 2, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:fixed_window", 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&fixed_window, "res:fixed_window", 1, &mut store)
+            .await
+            .expect("Failed to acquire from fixed window");
+
+        assert_ok!(res, 2, 1);
+    })
+}
+
+#[test]
+fn token_bucket() {
+    let mut store = MapStore::default();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = token_bucket
+        .acquire("res:token_bucket", 2, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+
+    let res = token_bucket
+        .acquire("res:token_bucket", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = token_bucket
+        .acquire("res:token_bucket", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn token_bucket_async() {
+    block_on(async {
+        let mut store = MapStore::default();
+
+        let clock = MockClock::new();
+
+        let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:token_bucket", 2, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:token_bucket", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:token_bucket", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+    })
+}
+
+#[test]
+fn concurrency() {
+    let mut store = MapStore::default();
+
+    let concurrency = Concurrency::new(1, Interval::from_secs(10).unwrap()).unwrap();
+
+    let lease = match concurrency
+        .acquire("res:concurrency", &mut store)
+        .expect("Failed to acquire from concurrency")
+    {
+        LeaseResult::Ok(lease, _) => lease,
+        res => panic!("Expected Ok, got {:?}", res),
+    };
+
+    let res = concurrency
+        .acquire("res:concurrency", &mut store)
+        .expect("Failed to acquire from concurrency");
+
+    assert!(matches!(res, LeaseResult::Throttled(_)));
+
+    let released = concurrency
+        .release(&lease, &mut store)
+        .expect("Failed to release from concurrency");
+
+    assert!(released);
+
+    let res = concurrency
+        .acquire("res:concurrency", &mut store)
+        .expect("Failed to acquire from concurrency");
+
+    assert!(matches!(res, LeaseResult::Ok(..)));
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn concurrency_async() {
+    block_on(async {
+        let mut store = MapStore::default();
+
+        let concurrency = Concurrency::new(1, Interval::from_secs(10).unwrap()).unwrap();
+
+        let lease =
+            match aio::ConcurrencyLimiter::acquire(&concurrency, "res:concurrency", &mut store)
+                .await
+                .expect("Failed to acquire from concurrency")
+            {
+                LeaseResult::Ok(lease, _) => lease,
+                res => panic!("Expected Ok, got {:?}", res),
+            };
+
+        let res = aio::ConcurrencyLimiter::acquire(&concurrency, "res:concurrency", &mut store)
+            .await
+            .expect("Failed to acquire from concurrency");
+
+        assert!(matches!(res, LeaseResult::Throttled(_)));
+
+        let released = aio::ConcurrencyLimiter::release(&concurrency, &lease, &mut store)
+            .await
+            .expect("Failed to release from concurrency");
+
+        assert!(released);
+
+        let res = aio::ConcurrencyLimiter::acquire(&concurrency, "res:concurrency", &mut store)
+            .await
+            .expect("Failed to acquire from concurrency");
+
+        assert!(matches!(res, LeaseResult::Ok(..)));
+    })
+}
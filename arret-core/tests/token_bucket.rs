@@ -1,9 +1,12 @@
+use std::time::{Duration, SystemTime};
+
 use arret_core::{
     interval::Interval,
     rate_limiter::{AcquireResult, Quota, RateLimiter},
-    rule::TokenBucket,
+    rule::{Clock, TimeSource, TokenBucket},
+    store::{Keyspace, MemoryStore},
 };
-use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, wait};
+use test_utils::{assert_ok, assert_throttled, prepare_redis_connection, MockClock};
 
 #[cfg(feature = "aio")]
 use arret_core::aio;
@@ -143,7 +146,11 @@ fn throttled_async() {
 fn refill() {
     let mut con = prepare_redis_connection();
 
-    let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1).unwrap();
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
 
     let res = token_bucket
         .acquire("res:refill", 1, &mut con)
@@ -163,7 +170,7 @@ fn refill() {
 
     assert_throttled!(res, 2, 0);
 
-    wait(1);
+    clock.advance(Duration::from_secs(1));
 
     let res = token_bucket
         .acquire("res:refill", 1, &mut con)
@@ -184,7 +191,11 @@ fn refill_async() {
     block_on(async {
         let mut con = prepare_redis_async_connection().await;
 
-        let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1).unwrap();
+        let clock = MockClock::new();
+
+        let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
 
         let res = aio::RateLimiter::acquire(&token_bucket, "res:refill_async", 1, &mut con)
             .await
@@ -204,7 +215,7 @@ fn refill_async() {
 
         assert_throttled!(res, 2, 0);
 
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        clock.advance(Duration::from_secs(1));
 
         let res = aio::RateLimiter::acquire(&token_bucket, "res:refill_async", 1, &mut con)
             .await
@@ -219,3 +230,453 @@ fn refill_async() {
         assert_throttled!(res, 2, 0);
     })
 }
+
+#[test]
+fn sub_second_refill() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(1, Interval::from_millis(100).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = token_bucket
+        .acquire("res:sub_second_refill", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 1, 0);
+
+    let res = token_bucket
+        .acquire("res:sub_second_refill", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 1, 0);
+
+    clock.advance(Duration::from_millis(100));
+
+    let res = token_bucket
+        .acquire("res:sub_second_refill", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 1, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn sub_second_refill_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let token_bucket = TokenBucket::new(1, Interval::from_millis(100).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res =
+            aio::RateLimiter::acquire(&token_bucket, "res:sub_second_refill_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 1, 0);
+
+        let res =
+            aio::RateLimiter::acquire(&token_bucket, "res:sub_second_refill_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 1, 0);
+
+        clock.advance(Duration::from_millis(100));
+
+        let res =
+            aio::RateLimiter::acquire(&token_bucket, "res:sub_second_refill_async", 1, &mut con)
+                .await
+                .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 1, 0);
+    })
+}
+
+#[test]
+fn reset() {
+    let mut con = prepare_redis_connection();
+
+    let token_bucket = TokenBucket::new(1, Interval::from_secs(10).unwrap(), 1).unwrap();
+
+    let now = SystemTime::now();
+
+    let res = token_bucket
+        .acquire("res:reset", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 1, 0);
+
+    let res = token_bucket
+        .acquire("res:reset", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    match res {
+        AcquireResult::Throttled(quota) => {
+            assert!(quota.reset_at() > now);
+            assert!(quota.retry_after(now) <= Duration::from_secs(10));
+        }
+        _ => panic!("Expected Throttled, got {:?}", res),
+    }
+}
+
+#[test]
+fn server_time() {
+    let mut con = prepare_redis_connection();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(10).unwrap(), 10)
+        .unwrap()
+        .with_time_source(TimeSource::Server);
+
+    let res = token_bucket
+        .acquire("res:server_time", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 9);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn server_time_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let token_bucket = TokenBucket::new(10, Interval::from_secs(10).unwrap(), 10)
+            .unwrap()
+            .with_time_source(TimeSource::Server);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:server_time_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 9);
+    })
+}
+
+#[test]
+fn preload() {
+    let mut con = prepare_redis_connection();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(10).unwrap(), 1).unwrap();
+
+    token_bucket
+        .preload(&mut con)
+        .expect("Failed to preload token bucket");
+
+    let res = token_bucket
+        .acquire("res:preload", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 9);
+
+    // The script is uploaded again once Redis has forgotten it
+    redis::cmd("SCRIPT")
+        .arg("FLUSH")
+        .query::<()>(&mut con)
+        .expect("Failed to flush scripts");
+
+    let res = token_bucket
+        .acquire("res:preload", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 8);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn preload_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let token_bucket = TokenBucket::new(10, Interval::from_secs(10).unwrap(), 1).unwrap();
+
+        token_bucket
+            .preload_async(&mut con)
+            .await
+            .expect("Failed to preload token bucket");
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:preload_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 9);
+
+        redis::cmd("SCRIPT")
+            .arg("FLUSH")
+            .query_async::<_, ()>(&mut con)
+            .await
+            .expect("Failed to flush scripts");
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:preload_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 8);
+    })
+}
+
+#[test]
+fn memory_store() {
+    let mut store = MemoryStore::new();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = token_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 1);
+
+    let res = token_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+
+    let res = token_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 2, 0);
+
+    clock.advance(Duration::from_secs(1));
+
+    let res = token_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+
+    let res = token_bucket
+        .acquire("res:memory_store", 1, &mut store)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn memory_store_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let clock = MockClock::new();
+
+        let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 1);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 2, 0);
+
+        clock.advance(Duration::from_secs(1));
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:memory_store_async", 1, &mut store)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 2, 0);
+    })
+}
+
+#[test]
+fn peek() {
+    let mut con = prepare_redis_connection();
+
+    let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1).unwrap();
+
+    let quota = token_bucket
+        .peek("res:peek", &mut con)
+        .expect("Failed to peek token bucket");
+
+    assert_eq!(quota.remaining, 2);
+
+    // Peeking a resource which was never requested does not create its state
+    let exists: bool = redis::cmd("EXISTS")
+        .arg(Keyspace::new().key("token_bucket", "res:peek"))
+        .query(&mut con)
+        .expect("Failed to check key");
+
+    assert!(!exists);
+
+    let res = token_bucket
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 1);
+
+    let quota = token_bucket
+        .peek("res:peek", &mut con)
+        .expect("Failed to peek token bucket");
+
+    assert_eq!(quota.remaining, 1);
+
+    let res = token_bucket
+        .acquire("res:peek", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 2, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn peek_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let token_bucket = TokenBucket::new(2, Interval::from_secs(1).unwrap(), 1).unwrap();
+
+        let quota = aio::RateLimiter::peek(&token_bucket, "res:peek_async", &mut con)
+            .await
+            .expect("Failed to peek token bucket");
+
+        assert_eq!(quota.remaining, 2);
+
+        // Peeking a resource which was never requested does not create its state
+        let exists: bool = redis::cmd("EXISTS")
+            .arg(Keyspace::new().key("token_bucket", "res:peek_async"))
+            .query_async(&mut con)
+            .await
+            .expect("Failed to check key");
+
+        assert!(!exists);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 1);
+
+        let quota = aio::RateLimiter::peek(&token_bucket, "res:peek_async", &mut con)
+            .await
+            .expect("Failed to peek token bucket");
+
+        assert_eq!(quota.remaining, 1);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:peek_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 2, 0);
+    })
+}
+
+#[test]
+fn refund() {
+    let mut con = prepare_redis_connection();
+
+    let clock = MockClock::new();
+
+    let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+        .unwrap()
+        .with_clock(clock.clone());
+
+    let res = token_bucket
+        .acquire("res:refund", 8, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 2);
+
+    let quota = token_bucket
+        .refund("res:refund", 3, clock.now(), &mut con)
+        .expect("Failed to refund token bucket");
+
+    assert_eq!(quota.remaining, 5);
+
+    // Refunds are clamped to the capacity
+    let quota = token_bucket
+        .refund("res:refund", 20, clock.now(), &mut con)
+        .expect("Failed to refund token bucket");
+
+    assert_eq!(quota.remaining, 10);
+
+    let res = token_bucket
+        .acquire("res:refund", 10, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_ok!(res, 10, 0);
+
+    let res = token_bucket
+        .acquire("res:refund", 1, &mut con)
+        .expect("Failed to acquire from token bucket");
+
+    assert_throttled!(res, 10, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn refund_async() {
+    block_on(async {
+        let mut con = prepare_redis_async_connection().await;
+
+        let clock = MockClock::new();
+
+        let token_bucket = TokenBucket::new(10, Interval::from_secs(1).unwrap(), 1)
+            .unwrap()
+            .with_clock(clock.clone());
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:refund_async", 8, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 2);
+
+        let quota =
+            aio::RateLimiter::refund(&token_bucket, "res:refund_async", 3, clock.now(), &mut con)
+                .await
+                .expect("Failed to refund token bucket");
+
+        assert_eq!(quota.remaining, 5);
+
+        // Refunds are clamped to the capacity
+        let quota =
+            aio::RateLimiter::refund(&token_bucket, "res:refund_async", 20, clock.now(), &mut con)
+                .await
+                .expect("Failed to refund token bucket");
+
+        assert_eq!(quota.remaining, 10);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:refund_async", 10, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_ok!(res, 10, 0);
+
+        let res = aio::RateLimiter::acquire(&token_bucket, "res:refund_async", 1, &mut con)
+            .await
+            .expect("Failed to acquire from token bucket");
+
+        assert_throttled!(res, 10, 0);
+    })
+}
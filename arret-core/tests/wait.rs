@@ -0,0 +1,221 @@
+use std::time::{Duration, Instant};
+
+use arret_core::{
+    error::Error,
+    interval::Interval,
+    rate_limiter::RateLimiter,
+    rule::{Clock, FixedWindow, LeakyBucket, SystemClock},
+    store::MemoryStore,
+};
+use test_utils::MockClock;
+
+#[cfg(feature = "aio")]
+use arret_core::aio;
+
+#[cfg(feature = "aio")]
+use test_utils::aio::block_on;
+
+#[test]
+fn fixed_window() {
+    let mut store = MemoryStore::new();
+
+    let fixed_window = FixedWindow::new(1, Interval::from_millis(100).unwrap()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+
+    let quota = fixed_window
+        .acquire_wait("res:wait:fixed_window", 1, deadline, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_eq!(quota.remaining, 0);
+
+    // The window is used up, so the second acquisition waits for the next one
+    let quota = fixed_window
+        .acquire_wait("res:wait:fixed_window", 1, deadline, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    assert_eq!(quota.remaining, 0);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn fixed_window_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let fixed_window = FixedWindow::new(1, Interval::from_millis(100).unwrap()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        let quota = aio::RateLimiter::acquire_wait(
+            &fixed_window,
+            "res:wait:fixed_window_async",
+            1,
+            deadline,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        assert_eq!(quota.remaining, 0);
+
+        let quota = aio::RateLimiter::acquire_wait(
+            &fixed_window,
+            "res:wait:fixed_window_async",
+            1,
+            deadline,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        assert_eq!(quota.remaining, 0);
+    })
+}
+
+#[test]
+fn deadline_exceeded() {
+    let mut store = MemoryStore::new();
+
+    let fixed_window = FixedWindow::new(1, Interval::from_secs(60).unwrap()).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+
+    fixed_window
+        .acquire_wait("res:wait:deadline_exceeded", 1, deadline, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    // The window only resets after the deadline, so there is no point in waiting for it
+    let err = fixed_window
+        .acquire_wait("res:wait:deadline_exceeded", 1, deadline, &mut store)
+        .expect_err("Expected the deadline to be exceeded");
+
+    assert!(matches!(err, Error::DeadlineExceeded(quota) if quota.remaining == 0));
+    assert!(Instant::now() < deadline);
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn deadline_exceeded_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let fixed_window = FixedWindow::new(1, Interval::from_secs(60).unwrap()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        aio::RateLimiter::acquire_wait(
+            &fixed_window,
+            "res:wait:deadline_exceeded_async",
+            1,
+            deadline,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        let err = aio::RateLimiter::acquire_wait(
+            &fixed_window,
+            "res:wait:deadline_exceeded_async",
+            1,
+            deadline,
+            &mut store,
+        )
+        .await
+        .expect_err("Expected the deadline to be exceeded");
+
+        assert!(matches!(err, Error::DeadlineExceeded(quota) if quota.remaining == 0));
+        assert!(Instant::now() < deadline);
+    })
+}
+
+#[test]
+fn clock() {
+    let mut store = MemoryStore::new();
+
+    // The clock of the rule is an hour behind the system, so its window resets
+    // before the current system time
+    let fixed_window = FixedWindow::new(1, Interval::from_secs(60).unwrap())
+        .unwrap()
+        .with_clock(MockClock::at(SystemClock.now() - 3_600_000));
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(1);
+
+    fixed_window
+        .acquire_wait("res:wait:clock", 1, deadline, &mut store)
+        .expect("Failed to acquire from fixed window");
+
+    // The wait is measured against the clock of the rule, which is still within the
+    // window, instead of retrying until the deadline
+    let err = fixed_window
+        .acquire_wait("res:wait:clock", 1, deadline, &mut store)
+        .expect_err("Expected the deadline to be exceeded");
+
+    assert!(matches!(err, Error::DeadlineExceeded(_)));
+    assert!(start.elapsed() < Duration::from_millis(500));
+}
+
+#[cfg(feature = "aio")]
+#[test]
+fn clock_async() {
+    block_on(async {
+        let mut store = MemoryStore::new();
+
+        let fixed_window = FixedWindow::new(1, Interval::from_secs(60).unwrap())
+            .unwrap()
+            .with_clock(MockClock::at(SystemClock.now() - 3_600_000));
+
+        let start = Instant::now();
+        let deadline = start + Duration::from_secs(1);
+
+        aio::RateLimiter::acquire_wait(
+            &fixed_window,
+            "res:wait:clock_async",
+            1,
+            deadline,
+            &mut store,
+        )
+        .await
+        .expect("Failed to acquire from fixed window");
+
+        let err = aio::RateLimiter::acquire_wait(
+            &fixed_window,
+            "res:wait:clock_async",
+            1,
+            deadline,
+            &mut store,
+        )
+        .await
+        .expect_err("Expected the deadline to be exceeded");
+
+        assert!(matches!(err, Error::DeadlineExceeded(_)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+    })
+}
+
+#[test]
+fn delayed() {
+    let mut store = MemoryStore::new();
+
+    let leaky_bucket = LeakyBucket::new(10, Interval::from_secs(60).unwrap(), 1).unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(1);
+
+    leaky_bucket
+        .acquire_wait("res:wait:delayed", 1, deadline, &mut store)
+        .expect("Failed to acquire from leaky bucket");
+
+    // The request would only proceed after the deadline, so its place is given back
+    let err = leaky_bucket
+        .acquire_wait("res:wait:delayed", 1, deadline, &mut store)
+        .expect_err("Expected the deadline to be exceeded");
+
+    assert!(matches!(err, Error::DeadlineExceeded(_)));
+
+    let quota = leaky_bucket
+        .peek("res:wait:delayed", &mut store)
+        .expect("Failed to peek leaky bucket");
+
+    assert_eq!(quota.remaining, 9);
+}